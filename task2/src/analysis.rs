@@ -0,0 +1,163 @@
+//! Trend analytics over hourly series.
+//!
+//! All functions expect the series in chronological order (oldest hour first),
+//! while `HashtagStatistics` returns the most recent hour first, see `analyze`.
+
+pub struct AnalysisConfig {
+    pub sma_window: usize,
+    /// Smoothing factor of the exponential moving average, in `(0, 1]`.
+    pub ema_alpha: f64,
+    /// Season length in hours used for day-over-day growth, anomalies and forecasting.
+    pub season: usize,
+    /// Hours whose `|z|` against the same hour of previous seasons reaches this value are anomalies.
+    pub anomaly_z_score: f64,
+    pub forecast_horizon: usize,
+    pub holt_winters: HoltWinters,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            sma_window: 3,
+            ema_alpha: 0.5,
+            season: 24,
+            anomaly_z_score: 3.0,
+            forecast_horizon: 6,
+            holt_winters: HoltWinters { alpha: 0.5, beta: 0.1, gamma: 0.3 },
+        }
+    }
+}
+
+/// Smoothing factors of the additive Holt-Winters model.
+#[derive(Clone, Copy)]
+pub struct HoltWinters {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+}
+
+/// Every vector is aligned with the analyzed series, except `forecast`,
+/// which continues it for `forecast_horizon` hours.
+pub struct Analysis {
+    pub series: Vec<u32>,
+    pub sma: Vec<Option<f64>>,
+    pub ema: Vec<f64>,
+    pub hour_over_hour: Vec<Option<f64>>,
+    pub day_over_day: Vec<Option<f64>>,
+    pub anomalies: Vec<bool>,
+    pub forecast: Vec<f64>,
+}
+
+/// Analyzes a series as returned by `HashtagStatistics::get_statistics`, i.e. most recent hour first.
+/// The resulting vectors are in chronological order.
+pub fn analyze(statistics: &[u32], config: &AnalysisConfig) -> Analysis {
+    let series = statistics.iter().rev().copied().collect::<Vec<_>>();
+    let values = series.iter().map(|&x| x as f64).collect::<Vec<_>>();
+    Analysis {
+        sma: sma(&values, config.sma_window),
+        ema: ema(&values, config.ema_alpha),
+        hour_over_hour: growth(&values, 1),
+        day_over_day: growth(&values, config.season),
+        anomalies: seasonal_anomalies(&values, config.season, config.anomaly_z_score),
+        forecast: forecast(&values, config.season, config.holt_winters, config.forecast_horizon),
+        series,
+    }
+}
+
+/// Simple moving average, `None` until `window` values are available.
+pub fn sma(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            if window == 0 || i + 1 < window {
+                return None;
+            }
+            Some(values[i + 1 - window..=i].iter().sum::<f64>() / window as f64)
+        })
+        .collect()
+}
+
+/// Exponential moving average seeded with the first value.
+pub fn ema(values: &[f64], alpha: f64) -> Vec<f64> {
+    let mut result = Vec::with_capacity(values.len());
+    for &value in values {
+        let next = match result.last() {
+            Some(&prev) => alpha * value + (1.0 - alpha) * prev,
+            None => value,
+        };
+        result.push(next);
+    }
+    result
+}
+
+/// Relative change against the value `lag` hours earlier, `None` if it is absent or zero.
+pub fn growth(values: &[f64], lag: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            let prev = values[i.checked_sub(lag)?];
+            (lag > 0 && prev != 0.0).then(|| (values[i] - prev) / prev)
+        })
+        .collect()
+}
+
+/// Flags hours deviating from the same hour of previous seasons.
+/// Hours with fewer than two previous seasons are never flagged.
+pub fn seasonal_anomalies(values: &[f64], season: usize, z_score: f64) -> Vec<bool> {
+    (0..values.len())
+        .map(|i| {
+            if season == 0 {
+                return false;
+            }
+            let peers = (1..=i / season)
+                .map(|k| values[i - k * season])
+                .collect::<Vec<_>>();
+            if peers.len() < 2 {
+                return false;
+            }
+            let mean = peers.iter().sum::<f64>() / peers.len() as f64;
+            let stddev = (peers.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / peers.len() as f64).sqrt();
+            if stddev == 0.0 {
+                return values[i] != mean;
+            }
+            ((values[i] - mean) / stddev).abs() >= z_score
+        })
+        .collect()
+}
+
+/// Forecasts the next `horizon` hours with additive Holt-Winters.
+/// Falls back to Holt's linear trend if there are less than two full seasons.
+pub fn forecast(values: &[f64], season: usize, params: HoltWinters, horizon: usize) -> Vec<f64> {
+    let n = values.len();
+    if n == 0 {
+        return vec![];
+    }
+    if n == 1 {
+        return vec![values[0]; horizon];
+    }
+    let result = if season == 0 || n < 2 * season {
+        let mut level = values[0];
+        let mut trend = values[1] - values[0];
+        for &value in &values[1..] {
+            let last_level = level;
+            level = params.alpha * value + (1.0 - params.alpha) * (level + trend);
+            trend = params.beta * (level - last_level) + (1.0 - params.beta) * trend;
+        }
+        (1..=horizon).map(|k| level + k as f64 * trend).collect::<Vec<_>>()
+    } else {
+        let first = values[..season].iter().sum::<f64>() / season as f64;
+        let second = values[season..2 * season].iter().sum::<f64>() / season as f64;
+        let mut level = first;
+        let mut trend = (second - first) / season as f64;
+        let mut seasonal = values[..season].iter().map(|x| x - first).collect::<Vec<_>>();
+        for t in season..n {
+            let last_level = level;
+            let last_seasonal = seasonal[t - season];
+            level = params.alpha * (values[t] - last_seasonal) + (1.0 - params.alpha) * (level + trend);
+            trend = params.beta * (level - last_level) + (1.0 - params.beta) * trend;
+            seasonal.push(params.gamma * (values[t] - level) + (1.0 - params.gamma) * last_seasonal);
+        }
+        (1..=horizon)
+            .map(|k| level + k as f64 * trend + seasonal[n - season + (k - 1) % season])
+            .collect()
+    };
+    result.into_iter().map(|x| x.max(0.0)).collect()
+}
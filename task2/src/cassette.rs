@@ -0,0 +1,182 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::token::TokenStore;
+use crate::vk_api::{VkApiError, VkApiRequester};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Response(Value),
+    /// The `error` object of the VK response.
+    VkError(Value),
+    /// Any other error, only the message is kept.
+    Error(String),
+}
+
+impl Outcome {
+    fn from_result(result: &anyhow::Result<Value>) -> Self {
+        match result {
+            Ok(value) => Outcome::Response(value.clone()),
+            Err(err) => match err.downcast_ref::<VkApiError>().and_then(VkApiError::to_value) {
+                Some(error) => Outcome::VkError(error),
+                None => Outcome::Error(format!("{err:#}")),
+            },
+        }
+    }
+
+    fn into_result(self) -> anyhow::Result<Value> {
+        match self {
+            Outcome::Response(value) => Ok(value),
+            Outcome::VkError(error) => Err(VkApiError::parse(&error).into()),
+            Outcome::Error(msg) => Err(Error::msg(msg)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub query: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub outcome: Outcome,
+}
+
+/// Recorded `VkApiRequester` interactions in the order they happened.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("Can't read cassette {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Can't parse cassette {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Can't write cassette {}", path.display()))
+    }
+
+    /// Replaces `tokens` and echoed `access_token` request params with `***`.
+    pub fn scrub(&mut self, tokens: Option<&TokenStore>) {
+        for interaction in &mut self.interactions {
+            match &mut interaction.outcome {
+                Outcome::Response(value) | Outcome::VkError(value) => scrub_value(value, tokens),
+                Outcome::Error(msg) => {
+                    if let Some(tokens) = tokens {
+                        *msg = tokens.redact(msg);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn scrub_value(value: &mut Value, tokens: Option<&TokenStore>) {
+    match value {
+        Value::String(text) => {
+            if let Some(tokens) = tokens {
+                *text = tokens.redact(text);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| scrub_value(item, tokens)),
+        Value::Object(map) => {
+            if map.get("key").and_then(Value::as_str) == Some("access_token") {
+                if let Some(param) = map.get_mut("value") {
+                    *param = Value::String("***".to_string());
+                }
+            }
+            if let Some(param) = map.get_mut("access_token") {
+                *param = Value::String("***".to_string());
+            }
+            map.values_mut().for_each(|item| scrub_value(item, tokens));
+        }
+        _ => {}
+    }
+}
+
+/// Passes requests to `inner` and writes every interaction to the cassette file.
+pub struct RecordingRequester<T: VkApiRequester> {
+    inner: T,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl<T: VkApiRequester> RecordingRequester<T> {
+    pub fn new(inner: T, path: PathBuf) -> Self {
+        Self { inner, path, cassette: Mutex::new(Cassette::default()) }
+    }
+
+    /// Saves the recorded interactions with `tokens` scrubbed.
+    pub fn save(&self, tokens: Option<&TokenStore>) -> anyhow::Result<()> {
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.scrub(tokens);
+        cassette.save(&self.path)
+    }
+}
+
+#[async_trait]
+impl<T: VkApiRequester + Sync + Send> VkApiRequester for RecordingRequester<T> {
+    async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        let result = self.inner.newsfeed_search(query, start_time, end_time).await;
+        self.cassette.lock().unwrap().interactions.push(Interaction {
+            query: query.to_string(),
+            start_time: start_time.timestamp(),
+            end_time: end_time.timestamp(),
+            outcome: Outcome::from_result(&result),
+        });
+        result
+    }
+}
+
+/// Serves recorded interactions matched by query and time range.
+/// Repeated requests get the recorded outcomes in order, the last one is repeated afterwards.
+pub struct ReplayRequester {
+    interactions: Mutex<HashMap<(String, i64, i64), VecDeque<Outcome>>>,
+}
+
+impl ReplayRequester {
+    pub fn new(cassette: Cassette) -> Self {
+        let mut interactions = HashMap::<_, VecDeque<_>>::new();
+        for interaction in cassette.interactions {
+            interactions
+                .entry((interaction.query, interaction.start_time, interaction.end_time))
+                .or_default()
+                .push_back(interaction.outcome);
+        }
+        Self { interactions: Mutex::new(interactions) }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+}
+
+#[async_trait]
+impl VkApiRequester for ReplayRequester {
+    async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        let key = (query.to_string(), start_time.timestamp(), end_time.timestamp());
+        let mut interactions = self.interactions.lock().unwrap();
+        let outcomes = interactions.get_mut(&key)
+            .ok_or_else(|| Error::msg(format!(
+                "No recorded interaction for {query} from {start_time} to {end_time}")))?;
+        let outcome = if outcomes.len() > 1 {
+            outcomes.pop_front().unwrap()
+        } else {
+            outcomes[0].clone()
+        };
+        outcome.into_result()
+    }
+}
@@ -7,30 +7,66 @@ use futures::StreamExt;
 
 use crate::vk_api::{VkApi, VkApiParser, VkApiRequester};
 
+/// Result of a single hour bucket, `Err` if the bucket could not be fetched.
+pub type BucketResult = anyhow::Result<u32>;
+
 #[async_trait]
 pub trait HashtagStatistics {
     async fn get_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>>;
+
+    /// Same as `get_statistics`, but a failed bucket doesn't discard the successful ones.
+    async fn get_partial_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> Vec<BucketResult>;
+
+    /// Refetches only the buckets of `buckets` that failed, keeping the successful ones as is.
+    async fn retry_failed(&self, hashtag: &str, from: DateTime<Utc>, buckets: Vec<BucketResult>) -> Vec<BucketResult>;
 }
 
-#[async_trait]
-impl<T, K> HashtagStatistics for VkApi<T, K>
+fn bucket_bounds(from: DateTime<Utc>, hour: u32) -> (DateTime<Utc>, DateTime<Utc>) {
+    let right = from.sub(Duration::hours(hour as i64));
+    let left = right.sub(Duration::hours(1i64));
+    (left, right)
+}
+
+impl<T, K> VkApi<T, K>
     where T: VkApiRequester + Sync + Send, K: VkApiParser + Sync + Send {
-    async fn get_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
-        let mut futures = Vec::with_capacity(hours as usize);
-        for hour in 0..hours {
-            let right = from.sub(Duration::hours(hour as i64));
-            let left = right.sub(Duration::hours(1i64));
+    async fn fetch_buckets(&self, hashtag: &str, from: DateTime<Utc>, hours: &[u32]) -> Vec<BucketResult> {
+        let mut futures = Vec::with_capacity(hours.len());
+        for &hour in hours {
+            let (left, right) = bucket_bounds(from, hour);
             futures.push(self.newsfeed_search(hashtag, left, right));
         }
-        // let handles = futures::future::join_all(futures).await;
-        let handles = futures::stream::iter(futures)
+        futures::stream::iter(futures)
             .buffered(5)
+            .map(|task| task.map(|x| x.count))
             .collect::<Vec<_>>()
-            .await;
-        let mut result = Vec::<u32>::with_capacity(handles.len());
-        for task in handles {
-            result.push(task?.count);
+            .await
+    }
+}
+
+#[async_trait]
+impl<T, K> HashtagStatistics for VkApi<T, K>
+    where T: VkApiRequester + Sync + Send, K: VkApiParser + Sync + Send {
+    async fn get_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
+        self.get_partial_statistics(hashtag, from, hours).await
+            .into_iter()
+            .collect()
+    }
+
+    async fn get_partial_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> Vec<BucketResult> {
+        let hours = (0..hours).collect::<Vec<_>>();
+        self.fetch_buckets(hashtag, from, &hours).await
+    }
+
+    async fn retry_failed(&self, hashtag: &str, from: DateTime<Utc>, mut buckets: Vec<BucketResult>) -> Vec<BucketResult> {
+        let failed = buckets.iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.is_err())
+            .map(|(hour, _)| hour as u32)
+            .collect::<Vec<_>>();
+        let retried = self.fetch_buckets(hashtag, from, &failed).await;
+        for (hour, bucket) in failed.into_iter().zip(retried) {
+            buckets[hour as usize] = bucket;
         }
-        Ok(result)
+        buckets
     }
-}
\ No newline at end of file
+}
@@ -1,23 +1,79 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 
-use crate::hashtag_statistics::HashtagStatistics;
-use crate::vk_api::{RealVkApiParser, RealVkApiRequester, VkApi};
+use crate::cassette::RecordingRequester;
+use crate::hashtag_statistics::{BucketResult, HashtagStatistics};
+use crate::store::{SeriesStore, StoredStatistics};
+use crate::token::TokenStore;
+use crate::vk_api::{HttpConfig, RealVkApiParser, RealVkApiRequester, VkApi};
+use crate::watch::{AlertSink, WatchConfig, Watcher};
 
+pub mod analysis;
+pub mod cassette;
+pub mod token;
 pub mod vk_api;
 pub mod hashtag_statistics;
+pub mod server;
+pub mod store;
+pub mod watch;
 
-pub async fn run(access_token: &str, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
-    let requester = Box::new(RealVkApiRequester {
-        access_token: access_token.to_string(),
-        base_url: "https://api.vk.com/method/newsfeed.search".to_string(),
-    });
+pub fn real_api(tokens: TokenStore, config: HttpConfig) -> anyhow::Result<VkApi<RealVkApiRequester, RealVkApiParser>> {
+    let requester = Box::new(RealVkApiRequester::new(tokens, config)?);
     let parser = Box::new(RealVkApiParser);
-    let api = VkApi {
+    Ok(VkApi {
         requester,
         parser,
         sleep_on_too_many_requests: Duration::from_secs(1),
+    })
+}
+
+/// Real api, serving already stored buckets from `store` if it is given.
+pub fn statistics(tokens: TokenStore, config: HttpConfig,
+                  store: Option<SeriesStore>) -> anyhow::Result<Box<dyn HashtagStatistics + Send + Sync>> {
+    let api = real_api(tokens, config)?;
+    Ok(match store {
+        Some(store) => Box::new(StoredStatistics::new(api, store)),
+        None => Box::new(api),
+    })
+}
+
+pub async fn run(tokens: TokenStore, config: HttpConfig, store: Option<SeriesStore>, hashtag: &str,
+                 from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
+    statistics(tokens, config, store)?.get_statistics(hashtag, from, hours).await
+}
+
+pub async fn run_partial(tokens: TokenStore, config: HttpConfig, store: Option<SeriesStore>, hashtag: &str,
+                         from: DateTime<Utc>, hours: u32, retries: u32) -> anyhow::Result<Vec<BucketResult>> {
+    let api = statistics(tokens, config, store)?;
+    let mut buckets = api.get_partial_statistics(hashtag, from, hours).await;
+    for _ in 0..retries {
+        if buckets.iter().all(|bucket| bucket.is_ok()) {
+            break;
+        }
+        buckets = api.retry_failed(hashtag, from, buckets).await;
+    }
+    Ok(buckets)
+}
+
+pub async fn watch(tokens: TokenStore, http_config: HttpConfig, config: WatchConfig,
+                   sinks: Vec<AlertSink>) -> anyhow::Result<()> {
+    Watcher::new(real_api(tokens, http_config)?, config, sinks)?
+        .run()
+        .await
+}
+
+/// Same as `run`, but also writes the VK responses to `cassette` with tokens scrubbed.
+pub async fn record(tokens: TokenStore, config: HttpConfig, cassette: PathBuf, hashtag: &str,
+                    from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
+    let inner = RealVkApiRequester::new(tokens, config)?;
+    let api = VkApi {
+        requester: Box::new(RecordingRequester::new(inner.clone(), cassette)),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_secs(1),
     };
-    api.get_statistics(hashtag, from, hours).await
+    let result = api.get_statistics(hashtag, from, hours).await;
+    api.requester.save(Some(inner.tokens()))?;
+    result
 }
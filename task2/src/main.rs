@@ -1,25 +1,304 @@
-use chrono::Utc;
-use task2::run;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
+use chrono::{DateTime, Utc};
+use task2::{record, run, run_partial, statistics, watch};
+use task2::server::{serve, StatsService};
+use task2::cassette::Cassette;
+use task2::store::{align, SeriesStore};
+use task2::token::{TokenSource, TokenStore, ACCESS_TOKEN_ENV};
+use task2::vk_api::HttpConfig;
+use task2::analysis::{analyze, Analysis, AnalysisConfig};
+use task2::watch::{AlertSink, Thresholds, WatchConfig};
+
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    stats: Option<StatsArgs>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatsArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+
+    #[arg(long)]
+    hashtag: String,
+
+    #[arg(long)]
+    hours: u32,
+
+    /// Print successful buckets even if some of them failed
+    #[arg(long)]
+    partial: bool,
+
+    /// How many times to refetch failed buckets in partial mode
+    #[arg(long, default_value_t = 0, requires = "partial")]
+    retries: u32,
+
+    /// Print moving averages, growth, anomalies and a forecast for the series
+    #[arg(long, conflicts_with = "partial")]
+    analyze: bool,
+
+    /// Store fetched buckets in this file and fetch only the missing ones.
+    /// Buckets are aligned to whole hours, so the current hour is not included
+    #[arg(long)]
+    store: Option<PathBuf>,
+}
+
+/// Token sources, checked in order; `$VK_ACCESS_TOKEN` is used if none is given.
+/// Several tokens separated by commas or whitespace are used in turn.
+#[derive(clap::Args, Debug)]
+struct TokenArgs {
+    /// Access token, visible to other users in `ps`, prefer the other options
+    #[arg(long = "access-token")]
+    access_tokens: Vec<String>,
+
+    /// Read access tokens from stdin
+    #[arg(long)]
+    access_token_stdin: bool,
+
+    /// Read access tokens from a file readable by its owner only
+    #[arg(long)]
+    access_token_file: Option<PathBuf>,
+}
+
+impl TokenArgs {
+    fn load(self) -> anyhow::Result<TokenStore> {
+        let source = if !self.access_tokens.is_empty() {
+            TokenSource::Args(self.access_tokens)
+        } else if self.access_token_stdin {
+            TokenSource::Stdin
+        } else if let Some(path) = self.access_token_file {
+            TokenSource::File(path)
+        } else {
+            TokenSource::Env(ACCESS_TOKEN_ENV.to_string())
+        };
+        source.load()
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct HttpArgs {
+    /// VK API version
+    #[arg(long)]
+    api_version: Option<String>,
+
+    /// Connect timeout in seconds
+    #[arg(long)]
+    connect_timeout: Option<u64>,
+
+    /// Request timeout in seconds
     #[arg(long)]
-    access_token: String,
+    timeout: Option<u64>,
+
+    /// HTTP or SOCKS5 proxy url, e.g. `socks5://127.0.0.1:1080`
+    #[arg(long)]
+    proxy: Option<String>,
+
+    #[arg(long)]
+    user_agent: Option<String>,
+}
+
+impl From<HttpArgs> for HttpConfig {
+    fn from(args: HttpArgs) -> Self {
+        let default = HttpConfig::default();
+        HttpConfig {
+            api_version: args.api_version.unwrap_or(default.api_version),
+            connect_timeout: args.connect_timeout.map(Duration::from_secs).unwrap_or(default.connect_timeout),
+            timeout: args.timeout.map(Duration::from_secs).unwrap_or(default.timeout),
+            proxy: args.proxy,
+            user_agent: args.user_agent.unwrap_or(default.user_agent),
+            ..default
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Poll hashtags periodically and alert when the last hour deviates from the baseline
+    Watch(WatchArgs),
+    /// Fetch statistics and record VK responses to a cassette for replaying in tests
+    Record(RecordArgs),
+    /// Scrub access tokens from a recorded cassette
+    Scrub(ScrubArgs),
+    /// Print a series from the store without calling VK, `?` marks buckets that were never fetched
+    History(HistoryArgs),
+    /// Serve statistics as JSON on `GET /stats?hashtag=...&from=...&hours=...`
+    Serve(ServeArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+
+    /// How long responses are cached, in seconds
+    #[arg(long, default_value_t = 300)]
+    cache_ttl: u64,
+
+    /// How many responses are cached at most, the least recently used are dropped first
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    cache_size: u64,
+
+    /// Store fetched buckets in this file and fetch only the missing ones
+    #[arg(long)]
+    store: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    #[arg(long)]
+    store: PathBuf,
 
     #[arg(long)]
     hashtag: String,
 
     #[arg(long)]
     hours: u32,
+
+    /// End of the series, RFC 3339, the current hour by default
+    #[arg(long)]
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RecordArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+
+    #[arg(long)]
+    hashtag: String,
+
+    #[arg(long)]
+    hours: u32,
+
+    #[arg(long)]
+    cassette: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ScrubArgs {
+    /// Tokens to look for, `access_token` request params are scrubbed anyway
+    #[command(flatten)]
+    token: TokenArgs,
+
+    #[arg(long)]
+    cassette: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+
+    #[arg(long = "hashtag", required = true)]
+    hashtags: Vec<String>,
+
+    /// Polling interval in minutes
+    #[arg(long, default_value_t = 10)]
+    interval: u64,
+
+    /// How many completed hours make up the baseline
+    #[arg(long, default_value_t = 24)]
+    window: u32,
+
+    #[arg(long, required_unless_present = "ratio")]
+    z_score: Option<f64>,
+
+    #[arg(long)]
+    ratio: Option<f64>,
+
+    #[arg(long, default_value = "watch_state.json")]
+    state_file: PathBuf,
+
+    /// URL to POST alerts to as JSON
+    #[arg(long)]
+    webhook: Option<String>,
+
+    /// File to append alerts to as JSON lines
+    #[arg(long)]
+    alert_file: Option<PathBuf>,
+
+    /// Don't print alerts to stdout
+    #[arg(long)]
+    quiet: bool,
 }
 
 fn main() {
     let args = Args::parse();
-    let result = tokio::runtime::Runtime::new().unwrap().block_on(
-        run(&args.access_token, &args.hashtag, Utc::now(), args.hours)
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    match args.command {
+        Some(Command::Watch(args)) => run_watch(&runtime, args),
+        Some(Command::Record(args)) => run_record(&runtime, args),
+        Some(Command::Scrub(args)) => run_scrub(args),
+        Some(Command::History(args)) => run_history(args),
+        Some(Command::Serve(args)) => run_serve(&runtime, args),
+        None => run_stats(&runtime, args.stats.unwrap()),
+    }
+}
+
+fn run_watch(runtime: &tokio::runtime::Runtime, args: WatchArgs) {
+    let tokens = match args.token.load() {
+        Ok(tokens) => tokens,
+        Err(err) => return eprintln!("{err:#}"),
+    };
+    let mut sinks = vec![];
+    if !args.quiet {
+        sinks.push(AlertSink::Stdout);
+    }
+    let http = args.http.into();
+    if let Some(url) = args.webhook {
+        match AlertSink::webhook(url, &http) {
+            Ok(sink) => sinks.push(sink),
+            Err(err) => return eprintln!("{err:#}"),
+        }
+    }
+    if let Some(path) = args.alert_file {
+        sinks.push(AlertSink::File(path));
+    }
+    let config = WatchConfig {
+        hashtags: args.hashtags,
+        interval: Duration::from_secs(args.interval * 60),
+        window_hours: args.window,
+        thresholds: Thresholds { z_score: args.z_score, ratio: args.ratio },
+        state_file: args.state_file,
+    };
+    if let Err(err) = runtime.block_on(watch(tokens, http, config, sinks)) {
+        eprintln!("{}: {:#?}", err, err.root_cause())
+    }
+}
+
+fn run_record(runtime: &tokio::runtime::Runtime, args: RecordArgs) {
+    let tokens = match args.token.load() {
+        Ok(tokens) => tokens,
+        Err(err) => return eprintln!("{err:#}"),
+    };
+    let result = runtime.block_on(
+        record(tokens, args.http.into(), args.cassette, &args.hashtag, Utc::now(), args.hours)
     );
     match result {
         Ok(data) =>
@@ -28,6 +307,116 @@ fn main() {
                 .collect::<Vec<String>>()
                 .join(", ")),
         Err(err) =>
-            eprintln!("{}: {:#?}", err.to_string(), err.root_cause())
+            eprintln!("{}: {:#?}", err, err.root_cause())
     }
-}
\ No newline at end of file
+}
+
+fn run_scrub(args: ScrubArgs) {
+    let tokens = args.token.load()
+        .map_err(|err| eprintln!("Scrubbing access_token params only: {err:#}"))
+        .ok();
+    let result = Cassette::load(&args.cassette).and_then(|mut cassette| {
+        cassette.scrub(tokens.as_ref());
+        cassette.save(&args.cassette)
+    });
+    if let Err(err) = result {
+        eprintln!("{err:#}");
+    }
+}
+
+fn run_serve(runtime: &tokio::runtime::Runtime, args: ServeArgs) {
+    let result = args.token.load()
+        .and_then(|tokens| statistics(tokens, args.http.into(), args.store.map(SeriesStore::open)))
+        .and_then(|statistics| {
+            let service = StatsService::new(Arc::from(statistics), Duration::from_secs(args.cache_ttl),
+                                           args.cache_size as usize);
+            let listener = TcpListener::bind(args.bind)?;
+            runtime.block_on(serve(listener, Arc::new(service)))
+        });
+    if let Err(err) = result {
+        eprintln!("{err:#}");
+    }
+}
+
+fn run_history(args: HistoryArgs) {
+    let to = args.to.unwrap_or_else(|| align(Utc::now()));
+    match SeriesStore::open(args.store).series(&args.hashtag, to, args.hours) {
+        Ok(data) =>
+            println!("{}", data.iter()
+                .map(|value| value.map(|x| x.to_string()).unwrap_or_else(|| "?".to_string()))
+                .collect::<Vec<String>>()
+                .join(", ")),
+        Err(err) =>
+            eprintln!("{err:#}")
+    }
+}
+
+fn run_stats(runtime: &tokio::runtime::Runtime, args: StatsArgs) {
+    let tokens = match args.token.load() {
+        Ok(tokens) => tokens,
+        Err(err) => return eprintln!("{err:#}"),
+    };
+    let now = if args.store.is_some() { align(Utc::now()) } else { Utc::now() };
+    let store = args.store.map(SeriesStore::open);
+    if args.partial {
+        let buckets = match runtime.block_on(
+            run_partial(tokens, args.http.into(), store, &args.hashtag, now, args.hours, args.retries)
+        ) {
+            Ok(buckets) => buckets,
+            Err(err) => return eprintln!("{}: {:#?}", err, err.root_cause()),
+        };
+        println!("{}", buckets.iter()
+            .map(|bucket| match bucket {
+                Ok(value) => value.to_string(),
+                Err(_) => "?".to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", "));
+        for (hour, bucket) in buckets.iter().enumerate() {
+            if let Err(err) = bucket {
+                eprintln!("bucket {hour} failed: {}: {:#?}", err, err.root_cause());
+            }
+        }
+        return;
+    }
+    let result = runtime.block_on(
+        run(tokens, args.http.into(), store, &args.hashtag, now, args.hours)
+    );
+    match result {
+        Ok(data) => {
+            println!("{}", data.iter()
+                .map(|value| { value.to_string() })
+                .collect::<Vec<String>>()
+                .join(", "));
+            if args.analyze {
+                print_analysis(&analyze(&data, &AnalysisConfig::default()), now);
+            }
+        }
+        Err(err) =>
+            eprintln!("{}: {:#?}", err, err.root_cause())
+    }
+}
+
+fn print_analysis(analysis: &Analysis, to: DateTime<Utc>) {
+    fn optional(value: Option<f64>) -> String {
+        value.map(|x| format!("{x:.2}")).unwrap_or_else(|| "-".to_string())
+    }
+
+    let hours = analysis.series.len();
+    println!("{:<25} {:>8} {:>8} {:>8} {:>8} {:>8} anomaly", "hour", "count", "sma", "ema", "hoh", "dod");
+    for i in 0..hours {
+        let start = to - chrono::Duration::hours((hours - i) as i64);
+        println!("{:<25} {:>8} {:>8} {:>8.2} {:>8} {:>8} {}",
+                 start.format("%Y-%m-%d %H:%M"),
+                 analysis.series[i],
+                 optional(analysis.sma[i]),
+                 analysis.ema[i],
+                 optional(analysis.hour_over_hour[i]),
+                 optional(analysis.day_over_day[i]),
+                 if analysis.anomalies[i] { "!" } else { "" });
+    }
+    for (k, value) in analysis.forecast.iter().enumerate() {
+        let start = to + chrono::Duration::hours(k as i64);
+        println!("{:<25} {:>8.1} (forecast)", start.format("%Y-%m-%d %H:%M"), value);
+    }
+}
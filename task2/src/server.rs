@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::hashtag_statistics::HashtagStatistics;
+use crate::store::align;
+
+pub const MAX_HOURS: u32 = 24 * 30;
+
+type StatsResult = Result<Arc<Vec<u32>>, Arc<String>>;
+
+type Key = (String, DateTime<Utc>, u32);
+
+enum CacheEntry {
+    Ready { at: Instant, counts: Arc<Vec<u32>> },
+    InFlight(Shared<BoxFuture<'static, StatsResult>>),
+}
+
+struct CacheSlot {
+    /// Value of `Cache::clock` when the entry was last read or written.
+    used: u64,
+    entry: CacheEntry,
+}
+
+/// Keys come from clients, so at most `max_entries` are kept and the least recently used goes first.
+/// Requests in flight are never evicted, as their callers may still be joined, so they can go over the limit.
+struct Cache {
+    slots: HashMap<Key, CacheSlot>,
+    clock: u64,
+    max_entries: usize,
+}
+
+impl Cache {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &Key) -> Option<&CacheEntry> {
+        let used = self.tick();
+        self.slots.get_mut(key).map(|slot| {
+            slot.used = used;
+            &slot.entry
+        })
+    }
+
+    fn insert(&mut self, key: Key, entry: CacheEntry, ttl: Duration) {
+        if !self.slots.contains_key(&key) && self.slots.len() >= self.max_entries {
+            self.slots.retain(|_, slot| !matches!(slot.entry, CacheEntry::Ready { at, .. } if at.elapsed() >= ttl));
+            while self.slots.len() >= self.max_entries {
+                let oldest = self.slots.iter()
+                    .filter(|(_, slot)| matches!(slot.entry, CacheEntry::Ready { .. }))
+                    .min_by_key(|(_, slot)| slot.used)
+                    .map(|(key, _)| key.clone());
+                match oldest {
+                    Some(oldest) => self.slots.remove(&oldest),
+                    None => break,
+                };
+            }
+        }
+        let used = self.tick();
+        self.slots.insert(key, CacheSlot { used, entry });
+    }
+
+    fn remove(&mut self, key: &Key) {
+        self.slots.remove(key);
+    }
+}
+
+/// Caches statistics for `ttl` and makes identical concurrent requests share one upstream call.
+pub struct StatsService {
+    statistics: Arc<dyn HashtagStatistics + Send + Sync>,
+    ttl: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl StatsService {
+    /// Keeps at most `max_entries` results, `max_entries` must not be zero.
+    pub fn new(statistics: Arc<dyn HashtagStatistics + Send + Sync>, ttl: Duration, max_entries: usize) -> Self {
+        assert!(max_entries > 0, "max_entries must not be zero");
+        let cache = Cache { slots: HashMap::new(), clock: 0, max_entries };
+        Self { statistics, ttl, cache: Mutex::new(cache) }
+    }
+
+    pub async fn get(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> StatsResult {
+        let key = (hashtag.to_string(), from, hours);
+        let future = {
+            let mut cache = self.cache.lock().unwrap();
+            match cache.get(&key) {
+                Some(CacheEntry::Ready { at, counts }) if at.elapsed() < self.ttl => {
+                    return Ok(counts.clone());
+                }
+                Some(CacheEntry::InFlight(future)) => future.clone(),
+                _ => {
+                    let statistics = self.statistics.clone();
+                    let hashtag = hashtag.to_string();
+                    let future = async move {
+                        statistics.get_statistics(&hashtag, from, hours).await
+                            .map(Arc::new)
+                            .map_err(|err| Arc::new(format!("{err:#}")))
+                    }.boxed().shared();
+                    cache.insert(key.clone(), CacheEntry::InFlight(future.clone()), self.ttl);
+                    future
+                }
+            }
+        };
+
+        let result = future.clone().await;
+        let mut cache = self.cache.lock().unwrap();
+        if matches!(cache.get(&key), Some(CacheEntry::InFlight(current)) if current.ptr_eq(&future)) {
+            match &result {
+                Ok(counts) => {
+                    cache.insert(key, CacheEntry::Ready { at: Instant::now(), counts: counts.clone() }, self.ttl);
+                }
+                Err(_) => {
+                    cache.remove(&key);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    hashtag: String,
+    /// End of the series, the current hour by default.
+    from: Option<DateTime<Utc>>,
+    hours: u32,
+}
+
+#[derive(Serialize)]
+struct StatsResponse<'a> {
+    hashtag: &'a str,
+    from: DateTime<Utc>,
+    hours: u32,
+    counts: &'a [u32],
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response<Body> {
+    json_response(status, &json!({ "error": error }))
+}
+
+async fn handle(service: Arc<StatsService>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != "/stats" {
+        return Ok(error_response(StatusCode::NOT_FOUND, "Not found"));
+    }
+    if req.method() != Method::GET {
+        return Ok(error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"));
+    }
+    let query = match serde_urlencoded::from_str::<StatsQuery>(req.uri().query().unwrap_or("")) {
+        Ok(query) => query,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    };
+    if query.hours == 0 || query.hours > MAX_HOURS {
+        return Ok(error_response(StatusCode::BAD_REQUEST, &format!("hours must be from 1 to {MAX_HOURS}")));
+    }
+    let from = query.from.unwrap_or_else(|| align(Utc::now()));
+    Ok(match service.get(&query.hashtag, from, query.hours).await {
+        Ok(counts) => json_response(StatusCode::OK, &StatsResponse {
+            hashtag: &query.hashtag,
+            from,
+            hours: query.hours,
+            counts: &counts,
+        }),
+        Err(err) => error_response(StatusCode::BAD_GATEWAY, &err),
+    })
+}
+
+/// Serves `GET /stats?hashtag=...&from=...&hours=...` on `listener` until the process exits.
+pub async fn serve(listener: TcpListener, service: Arc<StatsService>) -> anyhow::Result<()> {
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_| {
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(service.clone(), req)))
+        }
+    });
+    Server::from_tcp(listener)?
+        .serve(make_service)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Sub;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::hashtag_statistics::{BucketResult, HashtagStatistics};
+
+pub const BUCKET_LEN: i64 = 3600;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Record {
+    pub hashtag: String,
+    pub bucket_start: DateTime<Utc>,
+    /// Bucket length in seconds.
+    pub bucket_len: i64,
+    pub count: u32,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Append-only store of fetched buckets, one JSON record per line.
+pub struct SeriesStore {
+    path: PathBuf,
+}
+
+impl SeriesStore {
+    pub fn open(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn records(&self) -> anyhow::Result<Vec<Record>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let data = fs::read_to_string(&self.path)
+            .with_context(|| format!("Can't read store {}", self.path.display()))?;
+        let lines = data.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>();
+        let mut records = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                // Lines may be torn by an interrupted write, `append` starts a new line after them.
+                Err(err) if err.is_eof() || i + 1 == lines.len() => {}
+                Err(err) => return Err(Error::new(err)
+                    .context(format!("Can't parse line {} of store {}", i + 1, self.path.display()))),
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn append(&self, records: &[Record]) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut data = String::new();
+        for record in records {
+            data.push_str(&serde_json::to_string(record)?);
+            data.push('\n');
+        }
+        let mut write = || {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(&self.path)?;
+            // Finish a line torn by an interrupted write, so it stays the only bad one.
+            if file.seek(SeekFrom::End(-1)).is_ok() {
+                let mut last = [0];
+                file.read_exact(&mut last)?;
+                if last != *b"\n" {
+                    data.insert(0, '\n');
+                }
+            }
+            file.write_all(data.as_bytes())
+        };
+        write().with_context(|| format!("Can't write store {}", self.path.display()))
+    }
+
+    /// Stored hourly buckets going backwards from `from`, like `HashtagStatistics::get_statistics`.
+    /// The most recently fetched record wins if a bucket was stored several times.
+    pub fn series(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<Option<u32>>> {
+        let mut buckets = HashMap::new();
+        for record in self.records()? {
+            if record.hashtag != hashtag || record.bucket_len != BUCKET_LEN {
+                continue;
+            }
+            let newer = buckets.get(&record.bucket_start)
+                .is_none_or(|(fetched_at, _)| *fetched_at <= record.fetched_at);
+            if newer {
+                buckets.insert(record.bucket_start, (record.fetched_at, record.count));
+            }
+        }
+        Ok((0..hours)
+            .map(|hour| buckets.get(&bucket_start(from, hour)).map(|(_, count)| *count))
+            .collect())
+    }
+}
+
+fn bucket_start(from: DateTime<Utc>, hour: u32) -> DateTime<Utc> {
+    from.sub(Duration::hours(hour as i64 + 1))
+}
+
+/// Aligns `time` to the hour, so that buckets of different runs coincide and can be reused.
+pub fn align(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.duration_trunc(Duration::hours(1)).unwrap_or(time)
+}
+
+/// Serves buckets from the store and fetches only the missing ones from `inner`.
+/// `from` should be aligned with `align`, otherwise nothing is reused.
+pub struct StoredStatistics<S: HashtagStatistics> {
+    inner: S,
+    store: SeriesStore,
+}
+
+impl<S: HashtagStatistics> StoredStatistics<S> {
+    pub fn new(inner: S, store: SeriesStore) -> Self {
+        Self { inner, store }
+    }
+
+    fn save_fetched(&self, hashtag: &str, from: DateTime<Utc>, missing: &[bool],
+                    buckets: &[BucketResult]) -> anyhow::Result<()> {
+        let fetched_at = Utc::now();
+        let records = buckets.iter()
+            .zip(missing)
+            .enumerate()
+            .filter_map(|(hour, (bucket, &missing))| match bucket {
+                Ok(count) if missing => Some(Record {
+                    hashtag: hashtag.to_string(),
+                    bucket_start: bucket_start(from, hour as u32),
+                    bucket_len: BUCKET_LEN,
+                    count: *count,
+                    fetched_at,
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.store.append(&records)
+    }
+}
+
+#[async_trait]
+impl<S: HashtagStatistics + Sync + Send> HashtagStatistics for StoredStatistics<S> {
+    async fn get_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
+        self.get_partial_statistics(hashtag, from, hours).await
+            .into_iter()
+            .collect()
+    }
+
+    async fn get_partial_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> Vec<BucketResult> {
+        let stored = match self.store.series(hashtag, from, hours) {
+            Ok(stored) => stored,
+            Err(err) => {
+                let err = format!("{err:#}");
+                return (0..hours).map(|_| Err(Error::msg(err.clone()))).collect();
+            }
+        };
+        let buckets = stored.into_iter()
+            .map(|count| count.ok_or_else(|| Error::msg("Bucket is not stored")))
+            .collect();
+        self.retry_failed(hashtag, from, buckets).await
+    }
+
+    async fn retry_failed(&self, hashtag: &str, from: DateTime<Utc>, buckets: Vec<BucketResult>) -> Vec<BucketResult> {
+        let missing = buckets.iter().map(|bucket| bucket.is_err()).collect::<Vec<_>>();
+        let buckets = self.inner.retry_failed(hashtag, from, buckets).await;
+        // Fetched buckets are still returned, they will be fetched again next time.
+        if let Err(err) = self.save_fetched(hashtag, from, &missing, &buckets) {
+            eprintln!("{err:#}");
+        }
+        buckets
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Error};
+
+pub const ACCESS_TOKEN_ENV: &str = "VK_ACCESS_TOKEN";
+
+/// VK access token, never shown by `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessToken(String);
+
+impl AccessToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for AccessToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AccessToken(***)")
+    }
+}
+
+impl From<&str> for AccessToken {
+    fn from(token: &str) -> Self {
+        Self::new(token)
+    }
+}
+
+/// Set of tokens used in turn, so the rate limit is spread between them.
+#[derive(Debug)]
+pub struct TokenStore {
+    tokens: Vec<AccessToken>,
+    next: AtomicUsize,
+}
+
+impl TokenStore {
+    pub fn new(tokens: Vec<AccessToken>) -> anyhow::Result<Self> {
+        if tokens.is_empty() {
+            return Err(Error::msg("No access token provided"));
+        }
+        Ok(Self { tokens, next: AtomicUsize::new(0) })
+    }
+
+    pub fn next(&self) -> &AccessToken {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.tokens[index % self.tokens.len()]
+    }
+
+    /// Replaces every known token in `text` with `***`.
+    pub fn redact(&self, text: &str) -> String {
+        self.tokens.iter()
+            .filter(|token| !token.expose().is_empty())
+            .fold(text.to_string(), |text, token| text.replace(token.expose(), "***"))
+    }
+}
+
+impl From<AccessToken> for TokenStore {
+    fn from(token: AccessToken) -> Self {
+        Self { tokens: vec![token], next: AtomicUsize::new(0) }
+    }
+}
+
+pub enum TokenSource {
+    /// Tokens passed directly, visible in `ps` and shell history.
+    Args(Vec<String>),
+    Env(String),
+    /// File readable by its owner only.
+    File(PathBuf),
+    Stdin,
+}
+
+impl TokenSource {
+    /// Loads tokens separated by whitespace or commas.
+    pub fn load(&self) -> anyhow::Result<TokenStore> {
+        let data = match self {
+            TokenSource::Args(tokens) => tokens.join(","),
+            TokenSource::Env(name) => std::env::var(name)
+                .with_context(|| format!("Can't read access token from ${name}"))?,
+            TokenSource::File(path) => read_private_file(path)?,
+            TokenSource::Stdin => {
+                let mut data = String::new();
+                std::io::stdin().read_to_string(&mut data)
+                    .context("Can't read access token from stdin")?;
+                data
+            }
+        };
+        TokenStore::new(data
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .map(AccessToken::from)
+            .collect())
+    }
+}
+
+fn read_private_file(path: &Path) -> anyhow::Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)
+            .with_context(|| format!("Can't read token file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(Error::msg(format!(
+                "Token file {} is accessible by other users (mode {:o}), run `chmod 600` on it",
+                path.display(), mode & 0o777)));
+        }
+    }
+    fs::read_to_string(path)
+        .with_context(|| format!("Can't read token file {}", path.display()))
+}
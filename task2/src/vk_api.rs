@@ -1,11 +1,15 @@
+use std::sync::Arc;
 use std::time::Duration;
 use anyhow::{Context, Error};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use thiserror::Error;
 
+use crate::token::TokenStore;
+
 #[async_trait]
 pub trait VkApiRequester {
     async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value>;
@@ -18,11 +22,16 @@ pub trait VkApiParser {
 pub struct NewsFeedSearch {
     pub count: u32,
 }
+
+/// Retries of a request failing with a retryable VK error before the error is returned.
+pub const MAX_RETRIES: u32 = 5;
+
 pub struct VkApi<T, E>
     where T: VkApiRequester,
           E: VkApiParser {
     pub requester: Box<T>,
     pub parser: Box<E>,
+    /// Sleep before the first retry, it doubles with every next one.
     pub sleep_on_too_many_requests: Duration,
 }
 
@@ -31,15 +40,18 @@ impl<T, E> VkApi<T, E>
           E: VkApiParser {
     pub async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<NewsFeedSearch> {
         let data: Value;
+        let mut sleep = self.sleep_on_too_many_requests;
+        let mut retries = 0;
         loop {
             let response = self.requester.newsfeed_search(query, start_time, end_time).await;
             match response {
                 Err(x) => {
-                    if let Some(x) = x.downcast_ref::<VkApiError>() {
-                        if x.code == 6 {
-                            tokio::time::sleep(self.sleep_on_too_many_requests).await;
-                            continue;
-                        }
+                    let retryable = x.downcast_ref::<VkApiError>().is_some_and(VkApiError::is_retryable);
+                    if retryable && retries < MAX_RETRIES {
+                        tokio::time::sleep(sleep).await;
+                        sleep *= 2;
+                        retries += 1;
+                        continue;
                     }
                     return Err(x.context("Error during http request"));
                 }
@@ -54,16 +66,237 @@ impl<T, E> VkApi<T, E>
             .context("Error during parsing response")
     }
 }
-pub struct RealVkApiRequester {
-    pub access_token: String,
+pub struct HttpConfig {
     pub base_url: String,
+    pub api_version: String,
+    pub connect_timeout: Duration,
+    /// Timeout of the whole request, including reading the response.
+    pub timeout: Duration,
+    /// `http://`, `https://` or `socks5://` proxy url.
+    pub proxy: Option<String>,
+    pub user_agent: String,
+    pub pool_idle_timeout: Duration,
 }
 
-#[derive(Error, Debug)]
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.vk.com/method/newsfeed.search".to_string(),
+            api_version: "5.131".to_string(),
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            proxy: None,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            pool_idle_timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Client with the timeouts, proxy and user agent of the config.
+    pub fn client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .user_agent(&self.user_agent)
+            .gzip(true)
+            .pool_idle_timeout(self.pool_idle_timeout);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).context("Invalid proxy url")?);
+        }
+        builder.build().context("Can't create http client")
+    }
+}
+
+/// Cheap to clone, clones share the connection pool and the token rotation.
+#[derive(Clone, Debug)]
+pub struct RealVkApiRequester {
+    client: reqwest::Client,
+    tokens: Arc<TokenStore>,
+    base_url: String,
+    api_version: String,
+}
+
+impl RealVkApiRequester {
+    pub fn new(tokens: TokenStore, config: HttpConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: config.client()?,
+            tokens: Arc::new(tokens),
+            base_url: config.base_url,
+            api_version: config.api_version,
+        })
+    }
+
+    pub fn tokens(&self) -> &TokenStore {
+        &self.tokens
+    }
+}
+
+/// Parameter of the failed request as echoed back by VK.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RequestParam {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[error("{code}: {msg}")]
-pub struct VkApiError {
+pub struct VkErrorDetails {
     pub code: i64,
     pub msg: String,
+    pub request_params: Vec<RequestParam>,
+}
+
+/// Error returned by VK, see <https://dev.vk.com/reference/errors>.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum VkApiError {
+    #[error("unknown error, try later: {0}")]
+    Unknown(VkErrorDetails),
+    #[error("application is disabled: {0}")]
+    AppDisabled(VkErrorDetails),
+    #[error("unknown method: {0}")]
+    UnknownMethod(VkErrorDetails),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(VkErrorDetails),
+    #[error("user authorization failed: {0}")]
+    AuthFailed(VkErrorDetails),
+    #[error("too many requests per second: {0}")]
+    TooManyRequests(VkErrorDetails),
+    #[error("permission denied: {0}")]
+    PermissionDenied(VkErrorDetails),
+    #[error("invalid request: {0}")]
+    InvalidRequest(VkErrorDetails),
+    #[error("flood control: {0}")]
+    FloodControl(VkErrorDetails),
+    #[error("internal server error: {0}")]
+    InternalError(VkErrorDetails),
+    #[error("captcha needed: {details}")]
+    CaptchaNeeded {
+        details: VkErrorDetails,
+        captcha_sid: String,
+        captcha_img: String,
+    },
+    #[error("access denied: {0}")]
+    AccessDenied(VkErrorDetails),
+    #[error("validation required: {0}")]
+    ValidationRequired(VkErrorDetails),
+    #[error("rate limit reached: {0}")]
+    RateLimitReached(VkErrorDetails),
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(VkErrorDetails),
+    #[error("{0}")]
+    Other(VkErrorDetails),
+    /// Response has neither `response` nor a well-formed `error`.
+    #[error("malformed response: {0}")]
+    Malformed(String),
+}
+
+/// Details of a `VkApiError`, borrowed like the error is, so `details` and `details_mut` can't disagree.
+macro_rules! details_of {
+    ($error:expr) => {
+        match $error {
+            VkApiError::Unknown(details)
+            | VkApiError::AppDisabled(details)
+            | VkApiError::UnknownMethod(details)
+            | VkApiError::InvalidSignature(details)
+            | VkApiError::AuthFailed(details)
+            | VkApiError::TooManyRequests(details)
+            | VkApiError::PermissionDenied(details)
+            | VkApiError::InvalidRequest(details)
+            | VkApiError::FloodControl(details)
+            | VkApiError::InternalError(details)
+            | VkApiError::AccessDenied(details)
+            | VkApiError::ValidationRequired(details)
+            | VkApiError::RateLimitReached(details)
+            | VkApiError::InvalidParameter(details)
+            | VkApiError::Other(details)
+            | VkApiError::CaptchaNeeded { details, .. } => Some(details),
+            VkApiError::Malformed(_) => None,
+        }
+    };
+}
+
+impl VkApiError {
+    /// Parses the `error` object of a VK response.
+    pub fn parse(error: &Value) -> Self {
+        let code = error.get("error_code").and_then(Value::as_i64);
+        let msg = error.get("error_msg").and_then(Value::as_str);
+        let (Some(code), Some(msg)) = (code, msg) else {
+            return VkApiError::Malformed("error without error_code or error_msg".to_string());
+        };
+        let request_params = error.get("request_params")
+            .and_then(|params| serde_json::from_value(params.clone()).ok())
+            .unwrap_or_default();
+        let details = VkErrorDetails { code, msg: msg.to_string(), request_params };
+        match code {
+            1 => VkApiError::Unknown(details),
+            2 => VkApiError::AppDisabled(details),
+            3 => VkApiError::UnknownMethod(details),
+            4 => VkApiError::InvalidSignature(details),
+            5 => VkApiError::AuthFailed(details),
+            6 => VkApiError::TooManyRequests(details),
+            7 => VkApiError::PermissionDenied(details),
+            8 => VkApiError::InvalidRequest(details),
+            9 => VkApiError::FloodControl(details),
+            10 => VkApiError::InternalError(details),
+            14 => VkApiError::CaptchaNeeded {
+                details,
+                captcha_sid: error.get("captcha_sid").and_then(Value::as_str).unwrap_or_default().to_string(),
+                captcha_img: error.get("captcha_img").and_then(Value::as_str).unwrap_or_default().to_string(),
+            },
+            15 => VkApiError::AccessDenied(details),
+            17 => VkApiError::ValidationRequired(details),
+            29 => VkApiError::RateLimitReached(details),
+            100 => VkApiError::InvalidParameter(details),
+            _ => VkApiError::Other(details),
+        }
+    }
+
+    /// Inverse of `parse`, `None` for `Malformed`.
+    pub fn to_value(&self) -> Option<Value> {
+        let details = self.details()?;
+        let mut error = json!({
+            "error_code": details.code,
+            "error_msg": details.msg,
+            "request_params": details.request_params,
+        });
+        if let VkApiError::CaptchaNeeded { captcha_sid, captcha_img, .. } = self {
+            error["captcha_sid"] = json!(captcha_sid);
+            error["captcha_img"] = json!(captcha_img);
+        }
+        Some(error)
+    }
+
+    pub fn details(&self) -> Option<&VkErrorDetails> {
+        details_of!(self)
+    }
+
+    pub fn code(&self) -> Option<i64> {
+        self.details().map(|details| details.code)
+    }
+
+    /// Whether the same request may succeed later without any changes.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self,
+            VkApiError::Unknown(_)
+            | VkApiError::TooManyRequests(_)
+            | VkApiError::FloodControl(_)
+            | VkApiError::InternalError(_))
+    }
+
+    fn details_mut(&mut self) -> Option<&mut VkErrorDetails> {
+        details_of!(self)
+    }
+
+    fn redact(mut self, tokens: &TokenStore) -> Self {
+        if let Some(details) = self.details_mut() {
+            details.msg = tokens.redact(&details.msg);
+            for param in &mut details.request_params {
+                param.value = tokens.redact(&param.value);
+            }
+        }
+        self
+    }
 }
 
 pub struct RealVkApiParser;
@@ -71,37 +304,31 @@ pub struct RealVkApiParser;
 #[async_trait]
 impl VkApiRequester for RealVkApiRequester {
     async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
-        let client = reqwest::Client::new();
-
-        let mut body = client.get(&self.base_url)
+        let mut body = self.client.get(&self.base_url)
             .query(&[
-                ("v", "5.131"),
+                ("v", self.api_version.as_str()),
                 ("count", "0"),
-                ("access_token", &self.access_token),
+                ("access_token", self.tokens.next().expose()),
                 ("q", query),
                 ("start_time", &start_time.timestamp().to_string()),
                 ("end_time", &end_time.timestamp().to_string()),
             ])
             .send()
-            .await?
+            .await
+            .map_err(|err| err.without_url())?
             .json::<Value>()
-            .await?;
+            .await
+            .map_err(|err| err.without_url())?;
 
         if let Some(response) = body.get_mut("response") {
             return Ok(response.take());
         }
 
-        let error = body.get_mut("error").map(|error| {
-            let err = VkApiError {
-                msg: error.get_mut("error_msg")?.as_str()?.into(),
-                code: error.get_mut("error_code")?.as_i64()?,
-            };
-            return Some(err.into());
-        });
-        if let Some(Some(parsed_error)) = error {
-            return Err(parsed_error);
-        }
-        Err(Error::msg("Can't parse error"))
+        let error = match body.get("error") {
+            Some(error) => VkApiError::parse(error).redact(&self.tokens),
+            None => VkApiError::Malformed("response without response or error".to_string()),
+        };
+        Err(error.into())
     }
 }
 
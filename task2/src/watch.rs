@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::hashtag_statistics::HashtagStatistics;
+use crate::vk_api::HttpConfig;
+
+pub struct WatchConfig {
+    pub hashtags: Vec<String>,
+    pub interval: Duration,
+    /// How many completed hours are kept as a baseline.
+    pub window_hours: u32,
+    pub thresholds: Thresholds,
+    pub state_file: PathBuf,
+}
+
+pub struct Thresholds {
+    /// Alert when `|current - mean| / stddev` of the baseline reaches this value.
+    pub z_score: Option<f64>,
+    /// Alert when `current / mean` is at least this value or at most its inverse.
+    pub ratio: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HourlyCount {
+    pub start: DateTime<Utc>,
+    pub count: u32,
+}
+
+/// Rolling windows of completed hours for every watched hashtag, persisted between restarts.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct WatchState {
+    pub hashtags: HashMap<String, VecDeque<HourlyCount>>,
+}
+
+impl WatchState {
+    pub fn load(path: &PathBuf) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read(path)
+            .with_context(|| format!("Can't read state file {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Can't parse state file {}", path.display()))
+    }
+
+    pub fn save(&self, path: &PathBuf) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Can't write state file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Can't write state file {}", path.display()))
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Alert {
+    pub hashtag: String,
+    pub at: DateTime<Utc>,
+    pub count: u32,
+    pub baseline_mean: f64,
+    pub z_score: Option<f64>,
+    pub ratio: Option<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Deviation {
+    pub mean: f64,
+    pub z_score: Option<f64>,
+    pub ratio: Option<f64>,
+}
+
+/// Compares `current` with `baseline`, returns `None` if no threshold is reached.
+pub fn detect(baseline: &[u32], current: u32, thresholds: &Thresholds) -> Option<Deviation> {
+    if baseline.len() < 2 {
+        return None;
+    }
+    let len = baseline.len() as f64;
+    let mean = baseline.iter().map(|&x| x as f64).sum::<f64>() / len;
+    let variance = baseline.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / len;
+    let stddev = variance.sqrt();
+    let current = current as f64;
+
+    let z_score = (stddev > 0.0).then(|| (current - mean) / stddev);
+    let ratio = (mean > 0.0).then(|| current / mean);
+
+    let z_exceeded = matches!((z_score, thresholds.z_score),
+        (Some(z), Some(limit)) if z.abs() >= limit);
+    let ratio_exceeded = matches!((ratio, thresholds.ratio),
+        (Some(r), Some(limit)) if r >= limit || r <= 1.0 / limit);
+    if z_exceeded || ratio_exceeded {
+        Some(Deviation { mean, z_score, ratio })
+    } else {
+        None
+    }
+}
+
+pub enum AlertSink {
+    Stdout,
+    /// POSTs alerts as JSON with a client shared by all of them.
+    Webhook { url: String, client: reqwest::Client },
+    /// Appends alerts as JSON lines.
+    File(PathBuf),
+}
+
+impl AlertSink {
+    pub fn webhook(url: String, config: &HttpConfig) -> anyhow::Result<Self> {
+        Ok(AlertSink::Webhook { url, client: config.client()? })
+    }
+
+    pub async fn send(&self, alert: &Alert) -> anyhow::Result<()> {
+        match self {
+            AlertSink::Stdout => {
+                println!("{} {}: {} posts in the last hour, baseline {:.1}",
+                         alert.at, alert.hashtag, alert.count, alert.baseline_mean);
+            }
+            AlertSink::Webhook { url, client } => {
+                client
+                    .post(url)
+                    .json(alert)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            AlertSink::File(path) => {
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                writeln!(file, "{}", serde_json::to_string(alert)?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Watcher<S: HashtagStatistics> {
+    statistics: S,
+    config: WatchConfig,
+    sinks: Vec<AlertSink>,
+    state: WatchState,
+}
+
+impl<S: HashtagStatistics + Sync> Watcher<S> {
+    pub fn new(statistics: S, config: WatchConfig, sinks: Vec<AlertSink>) -> anyhow::Result<Self> {
+        let state = WatchState::load(&config.state_file)?;
+        Ok(Self { statistics, config, sinks, state })
+    }
+
+    pub fn state(&self) -> &WatchState {
+        &self.state
+    }
+
+    /// Polls every hashtag once, sends alerts to all sinks and saves the state.
+    pub async fn poll(&mut self, now: DateTime<Utc>) -> anyhow::Result<Vec<Alert>> {
+        let mut alerts = vec![];
+        for hashtag in self.config.hashtags.clone() {
+            match self.poll_hashtag(&hashtag, now).await {
+                Ok(Some(alert)) => alerts.push(alert),
+                Ok(None) => {}
+                Err(err) => eprintln!("{hashtag}: {err:#}"),
+            }
+        }
+        self.state.save(&self.config.state_file)?;
+        for alert in &alerts {
+            for sink in &self.sinks {
+                if let Err(err) = sink.send(alert).await {
+                    eprintln!("Can't send alert: {err:#}");
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.poll(Utc::now()).await?;
+        }
+    }
+
+    async fn poll_hashtag(&mut self, hashtag: &str, now: DateTime<Utc>) -> anyhow::Result<Option<Alert>> {
+        let hour = chrono::Duration::hours(1);
+        let hour_start = now.duration_trunc(hour)?;
+        let window_start = hour_start - hour * self.config.window_hours as i32;
+
+        let window = self.state.hashtags.entry(hashtag.to_string()).or_default();
+        window.retain(|x| x.start >= window_start);
+        let missing = window.back()
+            .map(|last| (hour_start - last.start).num_hours() - 1)
+            .unwrap_or(self.config.window_hours as i64)
+            .clamp(0, self.config.window_hours as i64) as u32;
+
+        // Buckets go backwards from `hour_start`; failed ones are left as gaps in the window.
+        let buckets = self.statistics.get_partial_statistics(hashtag, hour_start, missing).await;
+        for (i, bucket) in buckets.into_iter().enumerate().rev() {
+            if let Ok(count) = bucket {
+                window.push_back(HourlyCount { start: hour_start - hour * (i as i32 + 1), count });
+            }
+        }
+
+        let current = self.statistics.get_statistics(hashtag, now, 1).await?[0];
+        let baseline = window.iter().map(|x| x.count).collect::<Vec<_>>();
+        Ok(detect(&baseline, current, &self.config.thresholds).map(|deviation| Alert {
+            hashtag: hashtag.to_string(),
+            at: now,
+            count: current,
+            baseline_mean: deviation.mean,
+            z_score: deviation.z_score,
+            ratio: deviation.ratio,
+        }))
+    }
+}
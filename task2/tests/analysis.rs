@@ -0,0 +1,52 @@
+use task2::analysis::{analyze, ema, forecast, growth, seasonal_anomalies, sma, AnalysisConfig, HoltWinters};
+
+#[test]
+fn moving_averages() {
+    let values = [1.0, 2.0, 3.0, 4.0];
+    assert_eq!(sma(&values, 2), vec![None, Some(1.5), Some(2.5), Some(3.5)]);
+    assert_eq!(ema(&values, 0.5), vec![1.0, 1.5, 2.25, 3.125]);
+}
+
+#[test]
+fn growth_rates() {
+    let values = [10.0, 20.0, 0.0, 5.0];
+    assert_eq!(growth(&values, 1), vec![None, Some(1.0), Some(-1.0), None]);
+    assert_eq!(growth(&values, 2), vec![None, None, Some(-1.0), Some(-0.75)]);
+}
+
+#[test]
+fn anomalies_use_same_hour_of_previous_seasons() {
+    let values = [
+        10.0, 50.0, 5.0,
+        12.0, 48.0, 6.0,
+        10.0, 50.0, 5.0,
+        12.0, 48.0, 40.0,
+    ];
+    // 40 is below the busiest hour of the day, but far above the same hour of previous days.
+    let anomalies = seasonal_anomalies(&values, 3, 3.0);
+    assert_eq!(anomalies.iter().filter(|&&x| x).count(), 1);
+    assert!(anomalies[11]);
+}
+
+#[test]
+fn forecast_repeats_season() {
+    let pattern = [10.0, 30.0, 20.0];
+    let values = pattern.iter().cycle().take(12).copied().collect::<Vec<_>>();
+    let params = HoltWinters { alpha: 0.5, beta: 0.1, gamma: 0.3 };
+    let result = forecast(&values, 3, params, 3);
+    for (actual, expected) in result.iter().zip(pattern) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    let result = forecast(&[1.0, 2.0, 3.0], 24, params, 2);
+    assert!((result[0] - 4.0).abs() < 1e-6);
+    assert!((result[1] - 5.0).abs() < 1e-6);
+}
+
+#[test]
+fn analyze_reverses_statistics() {
+    let analysis = analyze(&[30, 20, 10], &AnalysisConfig { forecast_horizon: 1, ..AnalysisConfig::default() });
+    assert_eq!(analysis.series, vec![10, 20, 30]);
+    assert_eq!(analysis.hour_over_hour, vec![None, Some(1.0), Some(0.5)]);
+    assert_eq!(analysis.forecast.len(), 1);
+}
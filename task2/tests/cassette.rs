@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path};
+
+use task2::cassette::{Cassette, Interaction, Outcome, RecordingRequester, ReplayRequester};
+use task2::hashtag_statistics::HashtagStatistics;
+use task2::token::AccessToken;
+use task2::vk_api::{HttpConfig, RealVkApiParser, RealVkApiRequester, VkApi};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+#[tokio::test]
+async fn replay_fixture() {
+    let api = VkApi {
+        requester: Box::new(ReplayRequester::load(&fixture("newsfeed_mem.json")).unwrap()),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_millis(1),
+    };
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let data = api.get_statistics("#mem", start_time, 3).await.unwrap();
+    assert_eq!(data, vec![20u32, 15u32, 0u32]);
+
+    let data = api.get_partial_statistics("#mem", start_time, 4).await;
+    assert!(data[3].is_err());
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/method/newsfeed.search"))
+        .respond_with(ResponseTemplate::new(200)
+            .set_body_string("{\"response\":{\"count\":20,\"items\":[],\"total_count\":20}}")
+        )
+        .mount(&mock_server)
+        .await;
+
+    let cassette = std::env::temp_dir().join(format!("task2_cassette_{}.json", std::process::id()));
+    let inner = RealVkApiRequester::new(AccessToken::from("secret").into(), HttpConfig {
+        base_url: format!("{}/method/newsfeed.search", &mock_server.uri()),
+        ..HttpConfig::default()
+    }).unwrap();
+    let api = VkApi {
+        requester: Box::new(RecordingRequester::new(inner.clone(), cassette.clone())),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_secs(1),
+    };
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let recorded = api.get_statistics("#mem", start_time, 2).await.unwrap();
+    api.requester.save(Some(inner.tokens())).unwrap();
+    drop(mock_server);
+
+    let api = VkApi {
+        requester: Box::new(ReplayRequester::load(&cassette).unwrap()),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_secs(1),
+    };
+    let replayed = api.get_statistics("#mem", start_time, 2).await.unwrap();
+    assert_eq!(recorded, replayed);
+    std::fs::remove_file(&cassette).unwrap();
+}
+
+#[test]
+fn scrub_tokens() {
+    let mut cassette = Cassette {
+        interactions: vec![Interaction {
+            query: "#mem".to_string(),
+            start_time: 0,
+            end_time: 3600,
+            outcome: Outcome::Response(json!({
+                "request_params": [{"key": "access_token", "value": "other"}, {"key": "q", "value": "#mem"}],
+                "text": "token secret leaked",
+            })),
+        }],
+    };
+    cassette.scrub(Some(&AccessToken::from("secret").into()));
+    assert_eq!(cassette.interactions[0].outcome, Outcome::Response(json!({
+        "request_params": [{"key": "access_token", "value": "***"}, {"key": "q", "value": "#mem"}],
+        "text": "token *** leaked",
+    })));
+}
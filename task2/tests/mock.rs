@@ -1,20 +1,20 @@
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
 
 use async_trait::async_trait;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use serde_json::Value;
+use chrono::{DateTime, Utc};
+use anyhow::Error;
+use serde_json::{json, Value};
 
 use task2::hashtag_statistics::HashtagStatistics;
-use task2::vk_api::{RealVkApiParser, VkApi, VkApiRequester};
+use task2::vk_api::{RealVkApiParser, VkApi, VkApiError, VkApiRequester, MAX_RETRIES};
 
 struct MockVkApi {}
 
 #[async_trait]
 impl VkApiRequester for MockVkApi {
     async fn newsfeed_search(&self, _query: &str, _start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
-        if end_time == DateTime::<Utc>::from_utc(
-            NaiveDateTime::from_timestamp_opt(1668388063i64, 0u32).unwrap(),
-            Utc) {
+        if end_time == DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap() {
             Ok(serde_json::from_str("{\"count\":20,\"items\":[],\"total_count\":20}").unwrap())
         } else {
             Ok(serde_json::from_str("{\"count\":15,\"items\":[],\"total_count\":15}").unwrap())
@@ -31,9 +31,87 @@ async fn mock_api() {
         parser,
         sleep_on_too_many_requests: Duration::from_secs(1),
     };
-    let start_time = DateTime::<Utc>::from_utc(
-        NaiveDateTime::from_timestamp_opt(1668388063i64, 0u32).unwrap(),
-        Utc);
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
     let data = api.get_statistics("#mem", start_time, 2).await.unwrap();
     assert_eq!(data, vec![20u32, 15u32]);
 }
+
+
+/// Fails the first request for the second bucket, then behaves like `MockVkApi`.
+struct FlakyMockVkApi {
+    failures_left: AtomicU32,
+}
+
+#[async_trait]
+impl VkApiRequester for FlakyMockVkApi {
+    async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        if end_time != DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap()
+            && self.failures_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1)).is_ok() {
+            return Err(Error::msg("connection reset"));
+        }
+        MockVkApi {}.newsfeed_search(query, start_time, end_time).await
+    }
+}
+
+#[tokio::test]
+async fn mock_api_partial() {
+    let requester = Box::new(FlakyMockVkApi { failures_left: AtomicU32::new(1) });
+    let parser = Box::new(RealVkApiParser);
+    let api = VkApi {
+        requester,
+        parser,
+        sleep_on_too_many_requests: Duration::from_secs(1),
+    };
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let data = api.get_partial_statistics("#mem", start_time, 2).await;
+    assert_eq!(data[0].as_ref().unwrap(), &20u32);
+    assert!(data[1].is_err());
+
+    let data = api.retry_failed("#mem", start_time, data).await;
+    let data = data.into_iter().collect::<anyhow::Result<Vec<_>>>().unwrap();
+    assert_eq!(data, vec![20u32, 15u32]);
+}
+
+/// Fails with the VK error `code` the first `failures_left` times, then behaves like `MockVkApi`.
+struct VkErrorMockVkApi {
+    code: u32,
+    failures_left: AtomicU32,
+    requests: AtomicU32,
+}
+
+#[async_trait]
+impl VkApiRequester for VkErrorMockVkApi {
+    async fn newsfeed_search(&self, query: &str, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        if self.failures_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1)).is_ok() {
+            return Err(VkApiError::parse(&json!({"error_code": self.code, "error_msg": "Failed"})).into());
+        }
+        MockVkApi {}.newsfeed_search(query, start_time, end_time).await
+    }
+}
+
+#[tokio::test]
+async fn mock_api_retries_retryable_errors() {
+    let api = |code, failures| VkApi {
+        requester: Box::new(VkErrorMockVkApi { code, failures_left: AtomicU32::new(failures), requests: AtomicU32::new(0) }),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_millis(1),
+    };
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let end_time = start_time + chrono::Duration::hours(1);
+
+    // Internal server error.
+    let flaky = api(10, 2);
+    assert_eq!(flaky.newsfeed_search("#mem", start_time, end_time).await.unwrap().count, 15);
+    assert_eq!(flaky.requester.requests.load(Ordering::SeqCst), 3);
+
+    let down = api(10, u32::MAX);
+    let err = down.newsfeed_search("#mem", start_time, end_time).await.err().unwrap();
+    assert!(matches!(err.downcast_ref::<VkApiError>(), Some(VkApiError::InternalError(_))));
+    assert_eq!(down.requester.requests.load(Ordering::SeqCst), MAX_RETRIES + 1);
+
+    // User authorization failed.
+    let fatal = api(5, 1);
+    assert!(fatal.newsfeed_search("#mem", start_time, end_time).await.is_err());
+    assert_eq!(fatal.requester.requests.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,129 @@
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path, query_param};
+
+use task2::hashtag_statistics::{BucketResult, HashtagStatistics};
+use task2::real_api;
+use task2::server::{serve, StatsService};
+use task2::token::AccessToken;
+use task2::vk_api::HttpConfig;
+
+/// Slow statistics counting how many times they were requested.
+struct SlowStatistics {
+    calls: AtomicU32,
+}
+
+#[async_trait]
+impl HashtagStatistics for SlowStatistics {
+    async fn get_statistics(&self, _hashtag: &str, _from: DateTime<Utc>, hours: u32) -> anyhow::Result<Vec<u32>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(vec![1; hours as usize])
+    }
+
+    async fn get_partial_statistics(&self, hashtag: &str, from: DateTime<Utc>, hours: u32) -> Vec<BucketResult> {
+        vec![self.get_statistics(hashtag, from, hours).await.map(|x| x[0])]
+    }
+
+    async fn retry_failed(&self, _hashtag: &str, _from: DateTime<Utc>, buckets: Vec<BucketResult>) -> Vec<BucketResult> {
+        buckets
+    }
+}
+
+#[tokio::test]
+async fn service_coalesces_and_caches() {
+    let statistics = Arc::new(SlowStatistics { calls: AtomicU32::new(0) });
+    let service = StatsService::new(statistics.clone(), Duration::from_secs(60), 16);
+    let from = DateTime::<Utc>::from_timestamp(1668387600i64, 0u32).unwrap();
+
+    let (first, second) = tokio::join!(service.get("#mem", from, 2), service.get("#mem", from, 2));
+    assert_eq!(*first.unwrap(), vec![1, 1]);
+    assert_eq!(*second.unwrap(), vec![1, 1]);
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 1);
+
+    service.get("#mem", from, 2).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 1);
+    service.get("#mem", from, 3).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn service_evicts_least_recently_used() {
+    let statistics = Arc::new(SlowStatistics { calls: AtomicU32::new(0) });
+    let service = StatsService::new(statistics.clone(), Duration::from_secs(60), 2);
+    let from = DateTime::<Utc>::from_timestamp(1668387600i64, 0u32).unwrap();
+
+    service.get("#mem", from, 1).await.unwrap();
+    service.get("#mem", from, 2).await.unwrap();
+    service.get("#mem", from, 1).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 2);
+
+    service.get("#mem", from, 3).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 3);
+    service.get("#mem", from, 1).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 3);
+    service.get("#mem", from, 2).await.unwrap();
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn service_keeps_requests_in_flight_over_the_limit() {
+    let statistics = Arc::new(SlowStatistics { calls: AtomicU32::new(0) });
+    let service = StatsService::new(statistics.clone(), Duration::from_secs(60), 1);
+    let from = DateTime::<Utc>::from_timestamp(1668387600i64, 0u32).unwrap();
+
+    let (first, _, third) = tokio::join!(service.get("#mem", from, 1), service.get("#mem", from, 2), service.get("#mem", from, 1));
+    assert_eq!(first.unwrap(), third.unwrap());
+    assert_eq!(statistics.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn serve_stats() {
+    let vk_server = MockServer::start().await;
+    for (end_time, count) in [("1668387600", 20), ("1668384000", 15)] {
+        Mock::given(method("GET"))
+            .and(path("/method/newsfeed.search"))
+            .and(query_param("end_time", end_time))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"response": {"count": count, "items": [], "total_count": count}}))
+            )
+            .expect(1)
+            .mount(&vk_server)
+            .await;
+    }
+
+    let api = real_api(AccessToken::from("token").into(), HttpConfig {
+        base_url: format!("{}/method/newsfeed.search", &vk_server.uri()),
+        ..HttpConfig::default()
+    }).unwrap();
+    let service = Arc::new(StatsService::new(Arc::new(api), Duration::from_secs(60), 16));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener, service));
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{address}/stats?hashtag=%23mem&from=2022-11-14T01:00:00Z&hours=2");
+    for _ in 0..2 {
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.json::<Value>().await.unwrap();
+        assert_eq!(body, json!({
+            "hashtag": "#mem",
+            "from": "2022-11-14T01:00:00Z",
+            "hours": 2,
+            "counts": [20, 15],
+        }));
+    }
+
+    let response = client.get(format!("http://{address}/stats?hashtag=%23mem&hours=0")).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    let response = client.get(format!("http://{address}/other")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use task2::hashtag_statistics::HashtagStatistics;
+use task2::store::{align, Record, SeriesStore, StoredStatistics, BUCKET_LEN};
+use task2::vk_api::{RealVkApiParser, VkApi, VkApiRequester};
+
+/// Returns the hour of the bucket as the count and counts requests.
+struct CountingVkApi {
+    requests: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl VkApiRequester for CountingVkApi {
+    async fn newsfeed_search(&self, _query: &str, start_time: DateTime<Utc>, _end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let count = start_time.timestamp() / 3600 % 24;
+        Ok(json!({"count": count, "items": [], "total_count": count}))
+    }
+}
+
+fn stored(requests: Arc<AtomicU32>, path: std::path::PathBuf) -> StoredStatistics<VkApi<CountingVkApi, RealVkApiParser>> {
+    let api = VkApi {
+        requester: Box::new(CountingVkApi { requests }),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_secs(1),
+    };
+    StoredStatistics::new(api, SeriesStore::open(path))
+}
+
+#[tokio::test]
+async fn store_fills_only_gaps() {
+    let path = std::env::temp_dir().join(format!("task2_store_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let requests = Arc::new(AtomicU32::new(0));
+    let from = align(DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap());
+    assert_eq!(from.timestamp(), 1668387600);
+
+    let data = stored(requests.clone(), path.clone()).get_statistics("#mem", from, 3).await.unwrap();
+    assert_eq!(data, vec![0, 23, 22]);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    let data = stored(requests.clone(), path.clone()).get_statistics("#mem", from, 5).await.unwrap();
+    assert_eq!(data, vec![0, 23, 22, 21, 20]);
+    assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+    let store = SeriesStore::open(path.clone());
+    assert_eq!(store.records().unwrap().len(), 5);
+    assert_eq!(store.series("#mem", from, 6).unwrap(), vec![Some(0), Some(23), Some(22), Some(21), Some(20), None]);
+    assert_eq!(store.series("#other", from, 1).unwrap(), vec![None]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn store_appends_after_torn_line() {
+    let path = std::env::temp_dir().join(format!("task2_store_torn_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let from = DateTime::<Utc>::from_timestamp(1668387600i64, 0u32).unwrap();
+    let record = |hour: i64| Record {
+        hashtag: "#mem".to_string(),
+        bucket_start: from - chrono::Duration::hours(hour),
+        bucket_len: BUCKET_LEN,
+        count: hour as u32,
+        fetched_at: from,
+    };
+    let store = SeriesStore::open(path.clone());
+    store.append(&[record(0)]).unwrap();
+    let data = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, format!("{data}{}", &data[..data.len() / 2])).unwrap();
+
+    store.append(&[record(1)]).unwrap();
+    store.append(&[record(2)]).unwrap();
+    assert_eq!(store.records().unwrap(), vec![record(0), record(1), record(2)]);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,11 +1,13 @@
 use std::ops::Add;
 
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Utc};
+use serde_json::json;
 use wiremock::{Mock, MockServer, ResponseTemplate};
-use wiremock::matchers::{method, path, query_param};
+use wiremock::matchers::{header, method, path, query_param};
 
 use task2::hashtag_statistics::HashtagStatistics;
-use task2::vk_api::{RealVkApiParser, RealVkApiRequester, VkApi, VkApiError, VkApiRequester};
+use task2::token::AccessToken;
+use task2::vk_api::{HttpConfig, RealVkApiParser, RealVkApiRequester, RequestParam, VkApi, VkApiError, VkApiRequester};
 
 #[tokio::test]
 async fn stub_api() {
@@ -27,19 +29,17 @@ async fn stub_api() {
         .mount(&mock_server)
         .await;
 
-    let requester = Box::new(RealVkApiRequester {
-        access_token: "token".to_string(),
+    let requester = Box::new(RealVkApiRequester::new(AccessToken::from("token").into(), HttpConfig {
         base_url: format!("{}/method/newsfeed.search", &mock_server.uri()).to_string(),
-    });
+        ..HttpConfig::default()
+    }).unwrap());
     let parser = Box::new(RealVkApiParser);
     let api = VkApi {
         requester,
         parser,
         sleep_on_too_many_requests: std::time::Duration::from_secs(1),
     };
-    let start_time = DateTime::<Utc>::from_utc(
-        NaiveDateTime::from_timestamp_opt(1668388063i64, 0u32).unwrap(),
-        Utc);
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
     let data = api.get_statistics("#mem", start_time, 2).await.unwrap();
     assert_eq!(data, vec![20u32, 15u32]);
 }
@@ -55,15 +55,66 @@ async fn stub_check_vk_error() {
         .mount(&mock_server)
         .await;
 
-    let requester = Box::new(RealVkApiRequester {
-        access_token: "token".to_string(),
+    let requester = Box::new(RealVkApiRequester::new(AccessToken::from("token").into(), HttpConfig {
         base_url: format!("{}/method/newsfeed.search", &mock_server.uri()).to_string(),
-    });
-    let start_time = DateTime::<Utc>::from_utc(
-        NaiveDateTime::from_timestamp_opt(1668388063i64, 0u32).unwrap(),
-        Utc);
+        ..HttpConfig::default()
+    }).unwrap());
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
     let end_time = start_time.add(chrono::Duration::hours(1i64));
     let data = requester.newsfeed_search("#mem", start_time, end_time).await;
     assert!(data.is_err());
-    assert_eq!(data.err().unwrap().downcast_ref::<VkApiError>().unwrap().code, 6);
-}
\ No newline at end of file
+    let err = data.err().unwrap();
+    let err = err.downcast_ref::<VkApiError>().unwrap();
+    assert!(matches!(err, VkApiError::TooManyRequests(_)));
+    assert!(err.is_retryable());
+    assert_eq!(err.code(), Some(6));
+    let params = &err.details().unwrap().request_params;
+    assert!(params.contains(&RequestParam { key: "q".to_string(), value: "#мем".to_string() }));
+}
+
+#[tokio::test]
+async fn stub_http_config() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/method/newsfeed.search"))
+        .and(query_param("v", "5.199"))
+        .and(header("user-agent", "hashtag-stats"))
+        .respond_with(ResponseTemplate::new(200)
+            .set_body_string("{\"response\":{\"count\":20,\"items\":[],\"total_count\":20}}")
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let requester = RealVkApiRequester::new(AccessToken::from("token").into(), HttpConfig {
+        base_url: format!("{}/method/newsfeed.search", &mock_server.uri()),
+        api_version: "5.199".to_string(),
+        user_agent: "hashtag-stats".to_string(),
+        ..HttpConfig::default()
+    }).unwrap();
+    let start_time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let end_time = start_time.add(chrono::Duration::hours(1i64));
+    requester.newsfeed_search("#mem", start_time, end_time).await.unwrap();
+    requester.clone().newsfeed_search("#mem", start_time, end_time).await.unwrap();
+}
+
+#[test]
+fn vk_error_taxonomy() {
+    let err = VkApiError::parse(&json!({
+        "error_code": 14,
+        "error_msg": "Captcha needed",
+        "captcha_sid": "123",
+        "captcha_img": "https://api.vk.com/captcha.php?sid=123",
+    }));
+    assert!(matches!(&err, VkApiError::CaptchaNeeded { captcha_sid, .. } if captcha_sid == "123"));
+    assert!(!err.is_retryable());
+    assert_eq!(VkApiError::parse(&err.to_value().unwrap()), err);
+
+    assert!(matches!(VkApiError::parse(&json!({"error_code": 5, "error_msg": "User authorization failed"})),
+        VkApiError::AuthFailed(_)));
+    assert!(matches!(VkApiError::parse(&json!({"error_code": 10, "error_msg": "Internal server error"})),
+        VkApiError::InternalError(_)));
+    assert!(matches!(VkApiError::parse(&json!({"error_code": 9000, "error_msg": "New error"})),
+        VkApiError::Other(_)));
+    assert!(matches!(VkApiError::parse(&json!({"error_msg": "No code"})), VkApiError::Malformed(_)));
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path, query_param};
+
+use task2::token::{AccessToken, TokenSource, TokenStore};
+use task2::vk_api::{HttpConfig, RealVkApiRequester, VkApiRequester};
+
+#[test]
+fn token_is_redacted_from_debug() {
+    let requester = RealVkApiRequester::new(AccessToken::from("secret").into(), HttpConfig {
+        base_url: "http://localhost".to_string(),
+        ..HttpConfig::default()
+    }).unwrap();
+    assert!(!format!("{requester:?}").contains("secret"));
+    let tokens = TokenStore::new(vec!["secret".into()]).unwrap();
+    assert_eq!(tokens.redact("invalid token secret"), "invalid token ***");
+}
+
+#[tokio::test]
+async fn tokens_rotate() {
+    let mock_server = MockServer::start().await;
+    for token in ["first", "second"] {
+        Mock::given(method("GET"))
+            .and(path("/method/newsfeed.search"))
+            .and(query_param("access_token", token))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string("{\"response\":{\"count\":20,\"items\":[],\"total_count\":20}}")
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+    }
+
+    let requester = RealVkApiRequester::new(TokenStore::new(vec!["first".into(), "second".into()]).unwrap(), HttpConfig {
+        base_url: format!("{}/method/newsfeed.search", &mock_server.uri()),
+        ..HttpConfig::default()
+    }).unwrap();
+    let time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    requester.newsfeed_search("#mem", time, time).await.unwrap();
+    requester.newsfeed_search("#mem", time, time).await.unwrap();
+}
+
+#[tokio::test]
+async fn token_is_redacted_from_http_errors() {
+    let requester = RealVkApiRequester::new(AccessToken::from("secret").into(), HttpConfig {
+        base_url: "http://127.0.0.1:1/method/newsfeed.search".to_string(),
+        ..HttpConfig::default()
+    }).unwrap();
+    let time = DateTime::<Utc>::from_timestamp(1668388063i64, 0u32).unwrap();
+    let err = requester.newsfeed_search("#mem", time, time).await.unwrap_err();
+    assert!(!format!("{err:?}").contains("secret"));
+    assert!(!format!("{err:#}").contains("secret"));
+}
+
+#[cfg(unix)]
+#[test]
+fn token_file_must_be_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("task2_token_{}", std::process::id()));
+    std::fs::write(&path, "first,second\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(TokenSource::File(path.clone()).load().is_err());
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let tokens = TokenSource::File(path.clone()).load().unwrap();
+    assert_eq!(tokens.next().expose(), "first");
+    assert_eq!(tokens.next().expose(), "second");
+    assert_eq!(tokens.next().expose(), "first");
+    std::fs::remove_file(&path).unwrap();
+}
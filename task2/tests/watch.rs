@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::method;
+
+use task2::vk_api::{HttpConfig, RealVkApiParser, VkApi, VkApiRequester};
+use task2::watch::{detect, AlertSink, Thresholds, WatchConfig, Watcher};
+
+const NOW: i64 = 1668388063;
+
+/// Returns 100 posts for the hour ending now and 10 for every other hour.
+struct SpikeVkApi {}
+
+#[async_trait]
+impl VkApiRequester for SpikeVkApi {
+    async fn newsfeed_search(&self, _query: &str, _start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> anyhow::Result<Value> {
+        let count = if end_time.timestamp() == NOW { 100 } else { 10 };
+        Ok(json!({"count": count, "items": [], "total_count": count}))
+    }
+}
+
+fn api() -> VkApi<SpikeVkApi, RealVkApiParser> {
+    VkApi {
+        requester: Box::new(SpikeVkApi {}),
+        parser: Box::new(RealVkApiParser),
+        sleep_on_too_many_requests: Duration::from_secs(1),
+    }
+}
+
+#[test]
+fn detect_thresholds() {
+    let z_only = Thresholds { z_score: Some(3.0), ratio: None };
+    assert_eq!(detect(&[10, 12, 8, 10], 11, &z_only), None);
+    assert!(detect(&[10, 12, 8, 10], 30, &z_only).is_some());
+    assert!(detect(&[10, 12, 8, 10], 0, &z_only).is_some());
+    assert_eq!(detect(&[10], 30, &z_only), None);
+
+    let ratio_only = Thresholds { z_score: None, ratio: Some(2.0) };
+    assert!(detect(&[10, 10], 20, &ratio_only).is_some());
+    assert!(detect(&[10, 10], 5, &ratio_only).is_some());
+    assert_eq!(detect(&[10, 10], 15, &ratio_only), None);
+}
+
+#[tokio::test]
+async fn watch_alerts_and_persists_state() {
+    let state_file = std::env::temp_dir().join(format!("task2_watch_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_file);
+    let config = || WatchConfig {
+        hashtags: vec!["#mem".to_string()],
+        interval: Duration::from_secs(60),
+        window_hours: 3,
+        thresholds: Thresholds { z_score: None, ratio: Some(2.0) },
+        state_file: state_file.clone(),
+    };
+    let now = DateTime::<Utc>::from_timestamp(NOW, 0u32).unwrap();
+
+    let mut watcher = Watcher::new(api(), config(), vec![]).unwrap();
+    let alerts = watcher.poll(now).await.unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].count, 100);
+    assert_eq!(alerts[0].ratio, Some(10.0));
+
+    let watcher = Watcher::new(api(), config(), vec![]).unwrap();
+    let window = &watcher.state().hashtags["#mem"];
+    assert_eq!(window.iter().map(|x| x.count).collect::<Vec<_>>(), vec![10, 10, 10]);
+    assert!(window.iter().zip(window.iter().skip(1)).all(|(a, b)| a.start < b.start));
+
+    std::fs::remove_file(&state_file).unwrap();
+}
+
+#[tokio::test]
+async fn hanging_webhook_times_out() {
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .expect(1)
+        .mount(&webhook)
+        .await;
+    let state_file = std::env::temp_dir().join(format!("task2_webhook_{}.json", std::process::id()));
+    let config = WatchConfig {
+        hashtags: vec!["#mem".to_string()],
+        interval: Duration::from_secs(60),
+        window_hours: 3,
+        thresholds: Thresholds { z_score: None, ratio: Some(2.0) },
+        state_file: state_file.clone(),
+    };
+    let http = HttpConfig { timeout: Duration::from_millis(200), ..HttpConfig::default() };
+    let sink = AlertSink::webhook(webhook.uri(), &http).unwrap();
+    let mut watcher = Watcher::new(api(), config, vec![sink]).unwrap();
+
+    let now = DateTime::<Utc>::from_timestamp(NOW, 0u32).unwrap();
+    let alerts = tokio::time::timeout(Duration::from_secs(5), watcher.poll(now)).await
+        .expect("the webhook stalled the poll")
+        .unwrap();
+    assert_eq!(alerts.len(), 1);
+
+    std::fs::remove_file(&state_file).unwrap();
+}
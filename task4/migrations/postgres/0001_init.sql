@@ -0,0 +1,16 @@
+CREATE TABLE task_lists
+(
+    id   UUID PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE tasks
+(
+    id           UUID PRIMARY KEY,
+    task_list_id UUID    NOT NULL REFERENCES task_lists (id) ON DELETE CASCADE,
+    name         TEXT    NOT NULL,
+    done         BOOLEAN NOT NULL DEFAULT FALSE,
+    position     BIGINT  NOT NULL
+);
+
+CREATE INDEX tasks_task_list_id_position_idx ON tasks (task_list_id, position);
@@ -0,0 +1,10 @@
+ALTER TABLE tasks
+    ADD COLUMN description  TEXT,
+    ADD COLUMN due          DATE,
+    ADD COLUMN priority     SMALLINT    NOT NULL DEFAULT 1,
+    ADD COLUMN tags         TEXT[]      NOT NULL DEFAULT '{}',
+    ADD COLUMN created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN completed_at TIMESTAMPTZ;
+
+UPDATE tasks SET completed_at = updated_at WHERE done;
@@ -0,0 +1,11 @@
+CREATE TABLE task_list_members
+(
+    task_list_id UUID    NOT NULL REFERENCES task_lists (id) ON DELETE CASCADE,
+    user_id      UUID    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role         TEXT    NOT NULL CHECK (role IN ('viewer', 'editor')),
+    -- Invitations give the role once accepted.
+    accepted     BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (task_list_id, user_id)
+);
+
+CREATE INDEX task_list_members_user_id_idx ON task_list_members (user_id);
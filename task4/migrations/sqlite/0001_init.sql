@@ -0,0 +1,16 @@
+CREATE TABLE task_lists
+(
+    id   BLOB PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE tasks
+(
+    id           BLOB PRIMARY KEY,
+    task_list_id BLOB    NOT NULL REFERENCES task_lists (id) ON DELETE CASCADE,
+    name         TEXT    NOT NULL,
+    done         BOOLEAN NOT NULL DEFAULT FALSE,
+    position     INTEGER NOT NULL
+);
+
+CREATE INDEX tasks_task_list_id_position_idx ON tasks (task_list_id, position);
@@ -0,0 +1,11 @@
+CREATE TABLE users
+(
+    id            BLOB PRIMARY KEY,
+    username      TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
+
+-- Lists created before there were users have no owner and are not shown to anyone.
+ALTER TABLE task_lists ADD COLUMN owner_id BLOB REFERENCES users (id) ON DELETE CASCADE;
+
+CREATE INDEX task_lists_owner_id_idx ON task_lists (owner_id);
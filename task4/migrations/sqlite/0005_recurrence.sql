@@ -0,0 +1,3 @@
+ALTER TABLE tasks ADD COLUMN recurrence TEXT;
+-- IANA name like Europe/Berlin.
+ALTER TABLE users ADD COLUMN time_zone TEXT NOT NULL DEFAULT 'UTC';
@@ -0,0 +1,959 @@
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+
+use actix_session::Session;
+use actix_web::{delete, get, HttpRequest, HttpResponse, patch, post, put, ResponseError, Scope, web};
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, LINK, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use chrono::NaiveDate;
+use futures::StreamExt;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::auth;
+use crate::controller::{ListId, MemberId, TaskId};
+use crate::dao::{self, MemberDao, TaskDao, TaskListDao, UnitOfWork, UserDao};
+use crate::dep_middleware::{Deferred, Dependency};
+use crate::error::{TaskListError, TaskListResult};
+use crate::events::ChangeBus;
+use crate::exchange::{self, Format, ImportErrors};
+use crate::model::member::{Invitation, Member, MemberIn};
+use crate::model::query::{Page, TaskListQuery, TaskQuery};
+use crate::model::task::{Priority, TaskIn, TaskOut};
+use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+use crate::model::user::{self, Credentials, Settings, User};
+use crate::model::validation::{FieldErrors, Validate};
+
+pub const PREFIX: &str = "/api/v1";
+
+/// Error rendered as an RFC 7807 `application/problem+json` body.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    detail: String,
+    /// Invalid fields of the request body.
+    errors: FieldErrors,
+    /// Invalid lines of an imported file.
+    lines: ImportErrors,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self { status, detail: detail.into(), errors: FieldErrors::default(), lines: ImportErrors::default() }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    #[serde(skip_serializing_if = "FieldErrors::is_empty")]
+    errors: &'a FieldErrors,
+    #[serde(skip_serializing_if = "ImportErrors::is_empty")]
+    lines: &'a ImportErrors,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .insert_header((CONTENT_TYPE, "application/problem+json"))
+            .body(serde_json::to_string(&Problem {
+                kind: "about:blank",
+                title: self.status.canonical_reason().unwrap_or_default(),
+                status: self.status.as_u16(),
+                detail: &self.detail,
+                errors: &self.errors,
+                lines: &self.lines,
+            }).unwrap())
+    }
+}
+
+impl From<TaskListError> for ApiError {
+    fn from(err: TaskListError) -> Self {
+        // `Unknown` displays a generic message, its source is only logged.
+        err.log_internal();
+        let mut api_error = Self::new(err.status_code(), err.to_string());
+        match err {
+            TaskListError::Invalid(errors) => api_error.errors = errors,
+            TaskListError::InvalidImport(lines) => api_error.lines = lines,
+            _ => {}
+        }
+        api_error
+    }
+}
+
+/// Failed dependencies keep their status, like the other extractors.
+impl From<actix_web::Error> for ApiError {
+    fn from(err: actix_web::Error) -> Self {
+        if let Some(err) = err.as_error::<TaskListError>() {
+            err.log_internal();
+        }
+        Self::new(err.as_response_error().status_code(), err.to_string())
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Fields to change, the others are left as is. `null` clears optional fields.
+#[derive(Deserialize)]
+pub struct TaskPatch {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub due: Option<Option<NaiveDate>>,
+    pub priority: Option<Priority>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub recurrence: Option<Option<String>>,
+    pub done: Option<bool>,
+    pub position: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct FormatQuery {
+    #[serde(default)]
+    pub format: Format,
+}
+
+impl TaskPatch {
+    fn changes_core(&self) -> bool {
+        self.name.is_some() || self.description.is_some() || self.due.is_some()
+            || self.priority.is_some() || self.tags.is_some() || self.recurrence.is_some()
+    }
+}
+
+/// Tells a present `null` from a missing field.
+fn nullable<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+fn created(location: String, body: &impl Serialize) -> HttpResponse {
+    HttpResponse::Created()
+        .insert_header((LOCATION, location))
+        .json(body)
+}
+
+/// File to download, named `name` with the extension of the format.
+fn exported(format: Format, name: &str, body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, format.content_type()))
+        .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{name}.{}\"", format.extension())))
+        .body(body)
+}
+
+/// Items of the page, with a `Link` to the next one which is requested with `next_query`.
+fn paged(req: &HttpRequest, page: Page<impl Serialize>, next_query: Option<impl Serialize>) -> HttpResponse {
+    let mut res = HttpResponse::Ok();
+    if let Some(next_query) = next_query {
+        let next_query = serde_urlencoded::to_string(next_query).unwrap();
+        res.insert_header((LINK, format!("<{}?{next_query}>; rel=\"next\"", req.path())));
+    }
+    res.json(page.items)
+}
+
+/// Registers and logs in.
+#[post("/users")]
+pub async fn register(credentials: web::Json<Credentials>,
+                      session: Session,
+                      user_dao: Dependency<dyn UserDao>) -> ApiResult<HttpResponse> {
+    let user = auth::register(&**user_dao, credentials.into_inner()).await?;
+    auth::log_in(&session, &user)?;
+    Ok(HttpResponse::Created().json(user))
+}
+
+#[get("/session")]
+pub async fn get_session(req: HttpRequest) -> ApiResult<web::Json<User>> {
+    auth::current_user(&req)
+        .map(web::Json)
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "log in first"))
+}
+
+#[post("/session")]
+pub async fn log_in(credentials: web::Json<Credentials>,
+                    session: Session,
+                    user_dao: Dependency<dyn UserDao>) -> ApiResult<web::Json<User>> {
+    let user = auth::authenticate(&**user_dao, credentials.into_inner()).await?;
+    auth::log_in(&session, &user)?;
+    Ok(web::Json(user))
+}
+
+#[delete("/session")]
+pub async fn log_out(session: Session, bus: web::Data<ChangeBus>) -> HttpResponse {
+    auth::log_out(&session, &bus);
+    HttpResponse::NoContent().finish()
+}
+
+/// Id of the logged in user, for resources of the user rather than of a list.
+fn current_user_id(req: &HttpRequest) -> ApiResult<user::Id> {
+    auth::current_user_id(req).map_err(|err| ApiError::new(StatusCode::UNAUTHORIZED, err.to_string()))
+}
+
+#[get("/settings")]
+pub async fn get_settings(req: HttpRequest,
+                          user_dao: Dependency<dyn UserDao>) -> ApiResult<web::Json<Settings>> {
+    Ok(web::Json(user_dao.get_settings(current_user_id(&req)?).await?))
+}
+
+#[put("/settings")]
+pub async fn update_settings(req: HttpRequest,
+                             settings: web::Json<Settings>,
+                             user_dao: Dependency<dyn UserDao>) -> ApiResult<web::Json<Settings>> {
+    Ok(web::Json(user_dao.update_settings(current_user_id(&req)?, settings.into_inner()).await?))
+}
+
+#[get("/lists")]
+pub async fn get_task_lists(req: HttpRequest,
+                            query: web::Query<TaskListQuery>,
+                            task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<HttpResponse> {
+    let query = query.into_inner();
+    let page = task_list_dao.find(query.clone()).await?;
+    let next_query = page.next.clone().map(|after| TaskListQuery { after: Some(after), ..query });
+    Ok(paged(&req, page, next_query))
+}
+
+#[post("/lists")]
+pub async fn add_task_list(task_list: web::Json<TaskListIn>,
+                           task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<HttpResponse> {
+    let task_list = task_list_dao.add(task_list.into_inner().validate()?).await?;
+    Ok(created(format!("{PREFIX}/lists/{}", task_list.id), &task_list))
+}
+
+#[get("/lists/{id}")]
+pub async fn get_task_list(list_id: web::Path<ListId>,
+                           task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<web::Json<TaskListWithTasks>> {
+    Ok(web::Json(task_list_dao.get_by_id(list_id.id).await?))
+}
+
+#[get("/lists/{id}/export")]
+pub async fn export_task_list(list_id: web::Path<ListId>,
+                              query: web::Query<FormatQuery>,
+                              task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<HttpResponse> {
+    let task_list = task_list_dao.get_by_id(list_id.id).await?;
+    Ok(exported(query.format, "list", exchange::export_list(query.format, &task_list)))
+}
+
+/// Every list of the user with its tasks.
+#[get("/export")]
+pub async fn export_task_lists(query: web::Query<FormatQuery>,
+                               task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<HttpResponse> {
+    let mut task_lists = vec![];
+    for task_list in task_list_dao.get_all().await? {
+        task_lists.push(task_list_dao.get_by_id(task_list.id).await?);
+    }
+    Ok(exported(query.format, "lists", exchange::export_lists(query.format, &task_lists)))
+}
+
+/// Creates the lists of the file, or none if any line of it is invalid.
+#[post("/import")]
+pub async fn import_task_lists(query: web::Query<FormatQuery>,
+                               data: String,
+                               unit: Deferred<dyn UnitOfWork>) -> ApiResult<HttpResponse> {
+    let task_lists = exchange::import(query.format, &data).map_err(TaskListError::InvalidImport)?;
+    let unit = unit.resolve().await?;
+    let task_list_dao = unit.task_lists();
+    let task_dao = unit.tasks();
+    let created = async move {
+        let mut created = vec![];
+        for task_list in task_lists {
+            let id = task_list_dao.add(task_list.core).await?.id;
+            for task in task_list.tasks {
+                let task_id = task_dao.add(id, task.core).await?.id;
+                if task.done {
+                    task_dao.mark_as_done(id, task_id).await?;
+                }
+            }
+            created.push(task_list_dao.get_by_id(id).await?);
+        }
+        Ok(created)
+    }.await;
+    Ok(HttpResponse::Created().json(dao::finish::<Vec<TaskListWithTasks>>(&**unit, created).await?))
+}
+
+#[patch("/lists/{id}")]
+pub async fn rename_task_list(list_id: web::Path<ListId>,
+                              task_list: web::Json<TaskListIn>,
+                              task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<web::Json<TaskListOut>> {
+    Ok(web::Json(task_list_dao.rename(list_id.id, task_list.into_inner().validate()?).await?))
+}
+
+#[delete("/lists/{id}")]
+pub async fn delete_task_list(list_id: web::Path<ListId>,
+                              task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<HttpResponse> {
+    task_list_dao.delete(list_id.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/lists/{id}/tasks")]
+pub async fn get_tasks(req: HttpRequest,
+                       list_id: web::Path<ListId>,
+                       query: web::Query<TaskQuery>,
+                       task_dao: Dependency<dyn TaskDao>) -> ApiResult<HttpResponse> {
+    let query = query.into_inner();
+    let page = task_dao.find(list_id.id, query.clone()).await?;
+    let next_query = page.next.clone().map(|after| TaskQuery { after: Some(after), ..query });
+    Ok(paged(&req, page, next_query))
+}
+
+#[post("/lists/{id}/tasks")]
+pub async fn add_task(list_id: web::Path<ListId>,
+                      task: web::Json<TaskIn>,
+                      task_dao: Dependency<dyn TaskDao>) -> ApiResult<HttpResponse> {
+    let task = task_dao.add(list_id.id, task.into_inner().validate()?).await?;
+    Ok(created(format!("{PREFIX}/lists/{}/tasks/{}", list_id.id, task.id), &task))
+}
+
+async fn find_task(task_id: &TaskId, task_list_dao: &dyn TaskListDao) -> TaskListResult<TaskOut> {
+    task_list_dao.get_by_id(task_id.list_id).await?
+        .tasks
+        .into_iter()
+        .find(|task| task.id == task_id.id)
+        .ok_or(TaskListError::TaskNotFound(task_id.id))
+}
+
+#[get("/lists/{list_id}/tasks/{id}")]
+pub async fn get_task(task_id: web::Path<TaskId>,
+                      task_list_dao: Dependency<dyn TaskListDao>) -> ApiResult<web::Json<TaskOut>> {
+    Ok(web::Json(find_task(&task_id, &**task_list_dao).await?))
+}
+
+/// Either every change of the patch is made or none.
+#[patch("/lists/{list_id}/tasks/{id}")]
+pub async fn update_task(task_id: web::Path<TaskId>,
+                         patch: web::Json<TaskPatch>,
+                         unit: Deferred<dyn UnitOfWork>) -> ApiResult<web::Json<TaskOut>> {
+    let patch = patch.into_inner();
+    let unit = unit.resolve().await?;
+    let task_list_dao = unit.task_lists();
+    let task_dao = unit.tasks();
+    let task = async move {
+        if patch.changes_core() {
+            let mut core = find_task(&task_id, &*task_list_dao).await?.core;
+            if let Some(name) = patch.name {
+                core.name = name;
+            }
+            if let Some(description) = patch.description {
+                core.description = description;
+            }
+            if let Some(due) = patch.due {
+                core.due = due;
+            }
+            if let Some(priority) = patch.priority {
+                core.priority = priority;
+            }
+            if let Some(tags) = patch.tags {
+                core.tags = tags;
+            }
+            if let Some(recurrence) = patch.recurrence {
+                core.recurrence = recurrence;
+            }
+            task_dao.update(task_id.list_id, task_id.id, core.validate()?).await?;
+        }
+        match patch.done {
+            Some(true) => {
+                task_dao.mark_as_done(task_id.list_id, task_id.id).await?;
+            }
+            Some(false) => task_dao.mark_as_undone(task_id.list_id, task_id.id).await?,
+            None => {}
+        }
+        if let Some(position) = patch.position {
+            task_dao.move_to(task_id.list_id, task_id.id, position).await?;
+        }
+        find_task(&task_id, &*task_list_dao).await
+    }.await;
+    Ok(web::Json(dao::finish(&**unit, task).await?))
+}
+
+#[delete("/lists/{list_id}/tasks/{id}")]
+pub async fn delete_task(task_id: web::Path<TaskId>,
+                         task_dao: Dependency<dyn TaskDao>) -> ApiResult<HttpResponse> {
+    task_dao.delete(task_id.list_id, task_id.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Changes of the list as Server-Sent Events, each one a JSON `Change`, until the user loses access or logs out.
+#[get("/lists/{id}/events")]
+pub async fn get_events(req: HttpRequest,
+                        list_id: web::Path<ListId>,
+                        bus: web::Data<ChangeBus>,
+                        member_dao: Dependency<dyn MemberDao>) -> ApiResult<HttpResponse> {
+    member_dao.get_role(list_id.id).await?;
+    let events = bus.subscribe(list_id.id, current_user_id(&req)?).map(|change| {
+        Ok::<_, Infallible>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&change).unwrap())))
+    });
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, "text/event-stream"))
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+#[get("/lists/{id}/members")]
+pub async fn get_members(list_id: web::Path<ListId>,
+                         member_dao: Dependency<dyn MemberDao>) -> ApiResult<web::Json<Vec<Member>>> {
+    Ok(web::Json(member_dao.get_members(list_id.id).await?))
+}
+
+#[post("/lists/{id}/members")]
+pub async fn invite_member(list_id: web::Path<ListId>,
+                           member: web::Json<MemberIn>,
+                           member_dao: Dependency<dyn MemberDao>) -> ApiResult<HttpResponse> {
+    let member = member_dao.invite(list_id.id, member.into_inner()).await?;
+    Ok(created(format!("{PREFIX}/lists/{}/members/{}", list_id.id, member.user.id), &member))
+}
+
+#[delete("/lists/{list_id}/members/{user_id}")]
+pub async fn revoke_member(member_id: web::Path<MemberId>,
+                           member_dao: Dependency<dyn MemberDao>) -> ApiResult<HttpResponse> {
+    member_dao.revoke(member_id.list_id, member_id.user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/invitations")]
+pub async fn get_invitations(member_dao: Dependency<dyn MemberDao>) -> ApiResult<web::Json<Vec<Invitation>>> {
+    Ok(web::Json(member_dao.get_invitations().await?))
+}
+
+#[post("/invitations/{id}/accept")]
+pub async fn accept_invitation(list_id: web::Path<ListId>,
+                               member_dao: Dependency<dyn MemberDao>) -> ApiResult<HttpResponse> {
+    member_dao.accept(list_id.id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Declines an invitation, or leaves the list once it is accepted.
+#[delete("/invitations/{id}")]
+pub async fn decline_invitation(req: HttpRequest,
+                                list_id: web::Path<ListId>,
+                                member_dao: Dependency<dyn MemberDao>) -> ApiResult<HttpResponse> {
+    member_dao.revoke(list_id.id, current_user_id(&req)?).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Routes of the JSON API, extractor failures are reported as problems too.
+pub fn scope() -> Scope {
+    web::scope(PREFIX)
+        .app_data(web::JsonConfig::default()
+            .error_handler(|err, _| ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into()))
+        .app_data(web::PathConfig::default()
+            .error_handler(|err, _| ApiError::new(StatusCode::NOT_FOUND, err.to_string()).into()))
+        .app_data(web::QueryConfig::default()
+            .error_handler(|err, _| ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into()))
+        .service(register)
+        .service(get_session)
+        .service(log_in)
+        .service(log_out)
+        .service(get_settings)
+        .service(update_settings)
+        .service(get_task_lists)
+        .service(add_task_list)
+        .service(get_task_list)
+        .service(rename_task_list)
+        .service(delete_task_list)
+        .service(export_task_list)
+        .service(export_task_lists)
+        .service(import_task_lists)
+        .service(get_tasks)
+        .service(add_task)
+        .service(get_task)
+        .service(update_task)
+        .service(delete_task)
+        .service(get_events)
+        .service(get_members)
+        .service(invite_member)
+        .service(revoke_member)
+        .service(get_invitations)
+        .service(accept_invitation)
+        .service(decline_invitation)
+        .default_service(web::to(|| async {
+            Err::<HttpResponse, _>(ApiError::new(StatusCode::NOT_FOUND, "no such resource"))
+        }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_session::SessionMiddleware;
+    use actix_session::storage::CookieSessionStore;
+    use actix_web::{App, test};
+    use actix_web::body::MessageBody;
+    use actix_web::cookie::{Cookie, Key};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::header::SET_COOKIE;
+    use actix_web::web::Data;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::dao::TaskListMemoryState;
+    use crate::dep_middleware::{register_memory, register_notifying, Container};
+
+    macro_rules! app {
+        () => {
+            app!(ChangeBus::new())
+        };
+        ($bus:expr) => {{
+            let bus = $bus;
+            let mut container = Container::new();
+            register_memory(&mut container, TaskListMemoryState::new());
+            register_notifying(&mut container, bus.clone());
+            test::init_service(App::new()
+                .app_data(Data::new(container))
+                .app_data(Data::new(bus))
+                .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::from(&[0; 64])))
+                .service(scope())).await
+        }};
+    }
+
+    fn session_cookie(res: &ServiceResponse) -> Cookie<'static> {
+        Cookie::parse_encoded(res.headers().get(SET_COOKIE).unwrap().to_str().unwrap().to_owned()).unwrap()
+    }
+
+    /// Registers the user and evaluates to their session cookie.
+    macro_rules! register_user {
+        ($app:expr, $username:expr) => {{
+            let req = test::TestRequest::post().uri("/api/v1/users")
+                .set_json(json!({ "username": $username, "password": "secret" })).to_request();
+            let res = test::call_service($app, req).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+            session_cookie(&res)
+        }};
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle() {
+        let app = app!();
+        let cookie = register_user!(&app, "alice");
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists").set_json(json!({ "name": "hello" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_owned();
+        let list: Value = test::read_body_json(res).await;
+        assert_eq!(list["name"], "hello");
+        assert_eq!(location, format!("/api/v1/lists/{}", list["id"].as_str().unwrap()));
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri(&format!("{location}/tasks"))
+            .set_json(json!({ "name": "test" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let task_location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_owned();
+        let task: Value = test::read_body_json(res).await;
+        assert_eq!(task["done"], false);
+
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&task_location).set_json(json!({ "done": true })).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(task["done"], true);
+
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&task_location).set_json(json!({ "done": true })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["status"], 409);
+
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&task_location)
+            .set_json(json!({ "name": "renamed", "done": false, "due": "2022-12-31", "tags": ["home"] })).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(task["name"], "renamed");
+        assert_eq!(task["done"], false);
+        assert_eq!(task["due"], "2022-12-31");
+        assert_eq!(task["priority"], "normal");
+        assert_eq!(task["tags"], json!(["home"]));
+        assert_eq!(task["completed_at"], Value::Null);
+
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&task_location).set_json(json!({ "due": null })).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(task["due"], Value::Null);
+        assert_eq!(task["tags"], json!(["home"]));
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri(&format!("{location}/tasks"))
+            .set_json(json!({ "name": "second" })).to_request();
+        let res = test::call_service(&app, req).await;
+        let second_location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_owned();
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&second_location).set_json(json!({ "position": 0 })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&location).set_json(json!({ "name": "world" })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["name"], "world");
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&location).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["name"], "world");
+        assert_eq!(list["tasks"][0]["name"], "second");
+        assert_eq!(list["tasks"][1]["name"], "renamed");
+
+        let req = test::TestRequest::delete().cookie(cookie.clone()).uri(&second_location).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&second_location).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete().cookie(cookie.clone()).uri(&location).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&location).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_problems() {
+        let app = app!();
+        let cookie = register_user!(&app, "alice");
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri(&format!("/api/v1/lists/{}/tasks", Uuid::new_v4()))
+            .set_json(json!({ "name": "test" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["title"], "Not Found");
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists").set_json(json!({})).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/lists/nope").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists")
+            .set_json(json!({ "name": " \t" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["errors"], json!([{ "field": "name", "message": "must not be empty" }]));
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists")
+            .set_json(json!({ "name": " list " })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["name"], "list");
+        let req = test::TestRequest::post().cookie(cookie.clone())
+            .uri(&format!("/api/v1/lists/{}/tasks", list["id"].as_str().unwrap()))
+            .set_json(json!({ "name": "task", "tags": ["x".repeat(51)] })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["errors"][0]["field"], "tags");
+
+        let tasks_uri = format!("/api/v1/lists/{}/tasks", list["id"].as_str().unwrap());
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri(&tasks_uri)
+            .set_json(json!({ "name": "task" })).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        let task_uri = format!("{tasks_uri}/{}", task["id"].as_str().unwrap());
+        let req = test::TestRequest::patch().cookie(cookie.clone()).uri(&task_uri)
+            .set_json(json!({ "name": "renamed", "done": false })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&task_uri).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(task["name"], "task");
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/lists?limit=many").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/lists?after=nope").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_query() {
+        let app = app!();
+        let cookie = register_user!(&app, "alice");
+
+        for name in ["b", "a", "c"] {
+            let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists").set_json(json!({ "name": name })).to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/lists?limit=2").to_request();
+        let res = test::call_service(&app, req).await;
+        let link = res.headers().get(LINK).unwrap().to_str().unwrap().to_owned();
+        let lists: Value = test::read_body_json(res).await;
+        let owner = &lists[0]["owner"];
+        assert_eq!(lists, json!([{ "id": lists[0]["id"], "owner": owner, "name": "a" },
+                                 { "id": lists[1]["id"], "owner": owner, "name": "b" }]));
+        let next = link.strip_prefix('<').unwrap().strip_suffix(">; rel=\"next\"").unwrap();
+        assert!(next.starts_with("/api/v1/lists?"));
+        let res = test::call_service(&app, test::TestRequest::get().cookie(cookie.clone()).uri(next).to_request()).await;
+        assert!(res.headers().get(LINK).is_none());
+        let lists: Value = test::read_body_json(res).await;
+        assert_eq!(lists[0]["name"], "c");
+        assert_eq!(lists.as_array().unwrap().len(), 1);
+
+        let location = format!("/api/v1/lists/{}/tasks", lists[0]["id"].as_str().unwrap());
+        for (name, tags) in [("first", json!(["home"])), ("second", json!([])), ("third", json!(["home"]))] {
+            let req = test::TestRequest::post().cookie(cookie.clone()).uri(&location).set_json(json!({ "name": name, "tags": tags })).to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&format!("{location}?tag=home&sort=name&order=desc")).to_request();
+        let tasks: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(tasks.as_array().unwrap().iter().map(|x| x["name"].as_str().unwrap()).collect::<Vec<_>>(),
+                   ["third", "first"]);
+        let req = test::TestRequest::get().cookie(cookie.clone())
+            .uri(&format!("{location}?limit={}", i64::MAX)).to_request();
+        let tasks: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(tasks.as_array().unwrap().len(), 3);
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&format!("{location}?sort=sideways")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        let after = next.split_once("after=").unwrap().1;
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri(&format!("{location}?after={after}")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+    }
+
+    #[actix_web::test]
+    async fn test_auth() {
+        let app = app!();
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/api/v1/lists").to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+
+        let alice = register_user!(&app, "alice");
+        let req = test::TestRequest::post().uri("/api/v1/users")
+            .set_json(json!({ "username": "alice", "password": "other" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::post().uri("/api/v1/users")
+            .set_json(json!({ "username": " ", "password": "" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let req = test::TestRequest::post().uri("/api/v1/lists").cookie(alice.clone())
+            .set_json(json!({ "name": "alice" })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        let location = format!("/api/v1/lists/{}", list["id"].as_str().unwrap());
+
+        let req = test::TestRequest::post().uri("/api/v1/session")
+            .set_json(json!({ "username": "alice", "password": "wrong" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::post().uri("/api/v1/session")
+            .set_json(json!({ "username": "nobody", "password": "secret" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::post().uri("/api/v1/session")
+            .set_json(json!({ "username": "alice", "password": "secret" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let alice = session_cookie(&res);
+        let req = test::TestRequest::get().uri("/api/v1/session").cookie(alice.clone()).to_request();
+        let user: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(user["username"], "alice");
+        assert_eq!(user["id"], list["owner"]);
+
+        let bob = register_user!(&app, "bob");
+        let req = test::TestRequest::get().uri(&location).cookie(bob.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::get().uri("/api/v1/lists").cookie(bob.clone()).to_request();
+        let lists: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(lists, json!([]));
+
+        let req = test::TestRequest::delete().uri("/api/v1/session").cookie(alice.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let alice = session_cookie(&res);
+        let req = test::TestRequest::get().uri(&location).cookie(alice).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_members() {
+        let app = app!();
+        let alice = register_user!(&app, "alice");
+        let bob = register_user!(&app, "bob");
+
+        let req = test::TestRequest::post().uri("/api/v1/lists").cookie(alice.clone())
+            .set_json(json!({ "name": "shared" })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        let location = format!("/api/v1/lists/{}", list["id"].as_str().unwrap());
+
+        let req = test::TestRequest::post().uri(&format!("{location}/members")).cookie(alice.clone())
+            .set_json(json!({ "username": "bob", "role": "viewer" })).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let member_location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_owned();
+        let member: Value = test::read_body_json(res).await;
+        assert_eq!(member["user"]["username"], "bob");
+        assert_eq!(member["accepted"], false);
+        let req = test::TestRequest::post().uri(&format!("{location}/members")).cookie(alice.clone())
+            .set_json(json!({ "username": "nobody", "role": "viewer" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/api/v1/invitations").cookie(bob.clone()).to_request();
+        let invitations: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(invitations[0]["task_list"]["name"], "shared");
+        assert_eq!(invitations[0]["role"], "viewer");
+        let req = test::TestRequest::post().uri(&format!("/api/v1/invitations/{}/accept", list["id"].as_str().unwrap()))
+            .cookie(bob.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri(&location).cookie(bob.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::post().uri(&format!("{location}/tasks")).cookie(bob.clone())
+            .set_json(json!({ "name": "spam" })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::delete().uri(&member_location).cookie(alice.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().uri(&location).cookie(bob).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_events() {
+        let bus = ChangeBus::new();
+        let app = app!(bus.clone());
+        let alice = register_user!(&app, "alice");
+        let bob = register_user!(&app, "bob");
+
+        let req = test::TestRequest::post().uri("/api/v1/lists").cookie(alice.clone())
+            .set_json(json!({ "name": "live" })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        let location = format!("/api/v1/lists/{}", list["id"].as_str().unwrap());
+        let changes = bus.subscribe(list["id"].as_str().unwrap().parse().unwrap(), Uuid::new_v4());
+
+        let req = test::TestRequest::get().uri(&format!("{location}/events")).cookie(bob).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::get().uri(&format!("{location}/events")).cookie(alice.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/event-stream");
+        let mut events = Box::pin(res.into_body());
+
+        let req = test::TestRequest::post().uri(&format!("{location}/tasks")).cookie(alice.clone())
+            .set_json(json!({ "name": "milk" })).to_request();
+        let task: Value = test::call_and_read_body_json(&app, req).await;
+        let task_location = format!("{location}/tasks/{}", task["id"].as_str().unwrap());
+        let req = test::TestRequest::patch().uri(&task_location).cookie(alice.clone())
+            .set_json(json!({ "done": true })).to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete().uri(&task_location).cookie(alice.clone()).to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete().uri(&location).cookie(alice).to_request();
+        test::call_service(&app, req).await;
+
+        let event = futures::future::poll_fn(|cx| events.as_mut().poll_next(cx)).await.unwrap().unwrap();
+        let event = std::str::from_utf8(&event).unwrap();
+        assert!(event.starts_with("data: {\"type\":\"task_added\""));
+        assert!(event.ends_with("\n\n"));
+
+        let changes = changes.map(|change| serde_json::to_value(change).unwrap()["type"].clone()).collect::<Vec<_>>().await;
+        assert_eq!(changes, ["task_added", "task_done", "task_deleted", "list_deleted"]);
+    }
+
+    #[actix_web::test]
+    async fn test_events_end() {
+        let app = app!();
+        let alice = register_user!(&app, "alice");
+        let bob = register_user!(&app, "bob");
+
+        let req = test::TestRequest::post().uri("/api/v1/lists").cookie(alice.clone())
+            .set_json(json!({ "name": "shared" })).to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        let location = format!("/api/v1/lists/{}", list["id"].as_str().unwrap());
+        let req = test::TestRequest::post().uri(&format!("{location}/members")).cookie(alice.clone())
+            .set_json(json!({ "username": "bob", "role": "viewer" })).to_request();
+        let res = test::call_service(&app, req).await;
+        let member_location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_owned();
+        let req = test::TestRequest::post().uri(&format!("/api/v1/invitations/{}/accept", list["id"].as_str().unwrap()))
+            .cookie(bob.clone()).to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri(&format!("{location}/events")).cookie(bob).to_request();
+        let bob_events = test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri(&format!("{location}/events")).cookie(alice.clone()).to_request();
+        let alice_events = test::call_service(&app, req).await;
+
+        let req = test::TestRequest::delete().uri(&member_location).cookie(alice.clone()).to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post().uri(&format!("{location}/tasks")).cookie(alice.clone())
+            .set_json(json!({ "name": "secret" })).to_request();
+        test::call_service(&app, req).await;
+        let body = actix_web::rt::time::timeout(Duration::from_secs(5), test::read_body(bob_events)).await.unwrap();
+        assert!(body.is_empty());
+
+        let req = test::TestRequest::delete().uri("/api/v1/session").cookie(alice).to_request();
+        test::call_service(&app, req).await;
+        let body = actix_web::rt::time::timeout(Duration::from_secs(5), test::read_body(alice_events)).await.unwrap();
+        assert!(std::str::from_utf8(&body).unwrap().starts_with("data: {\"type\":\"task_added\""));
+    }
+
+    #[actix_web::test]
+    async fn test_import_export() {
+        let app = app!();
+        let cookie = register_user!(&app, "alice");
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/import?format=markdown")
+            .insert_header((CONTENT_TYPE, "text/markdown; charset=utf-8"))
+            .set_payload("# Home\n- [x] Buy milk\n- [ ] Call Bob\n\n# Work\n- [ ] Write report\n").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let lists: Value = test::read_body_json(res).await;
+        assert_eq!(lists[0]["name"], "Home");
+        assert_eq!(lists[0]["tasks"][0]["done"], true);
+        assert_eq!(lists[1]["tasks"][0]["name"], "Write report");
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/export?format=todotxt").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(CONTENT_DISPOSITION).unwrap(), "attachment; filename=\"lists.txt\"");
+        let body = test::read_body(res).await;
+        let lines = std::str::from_utf8(&body).unwrap().lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("x ") && lines[0].ends_with(" Buy milk +Home"));
+
+        let req = test::TestRequest::get().cookie(cookie.clone())
+            .uri(&format!("/api/v1/lists/{}/export?format=csv", lists[1]["id"].as_str().unwrap())).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+        let body = test::read_body(res).await;
+        assert_eq!(body, "list,name,description,due,priority,tags,recurrence,done\r\nWork,Write report,,,normal,,,false\r\n");
+
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/export").to_request();
+        let exported = test::read_body(test::call_service(&app, req).await).await;
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/import").set_payload(exported).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/import?format=todotxt")
+            .insert_header((CONTENT_TYPE, Format::TodoTxt.content_type())).set_payload("Buy milk\n").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/import?format=csv")
+            .insert_header((CONTENT_TYPE, "text/csv")).set_payload("name,due\nok,\n,\nlate,soon\n").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["lines"], json!([
+            { "line": 3, "message": "name must not be empty" },
+            { "line": 4, "message": "due `soon` is not a date like 2024-12-31" },
+        ]));
+        let req = test::TestRequest::get().cookie(cookie.clone()).uri("/api/v1/lists").to_request();
+        let lists: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(lists.as_array().unwrap().len(), 5);
+    }
+
+    #[actix_web::test]
+    async fn test_slow_upload() {
+        let app = app!();
+        let cookie = register_user!(&app, "alice");
+
+        let mut req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/import")
+            .insert_header((CONTENT_TYPE, "application/json")).to_request();
+        // A body which never ends.
+        *req.payload() = actix_web::dev::Payload::Stream {
+            payload: Box::pin(futures::stream::pending::<Result<Bytes, actix_web::error::PayloadError>>()),
+        };
+        let upload = test::call_service(&app, req);
+        futures::pin_mut!(upload);
+        assert!(futures::poll!(&mut upload).is_pending());
+
+        let req = test::TestRequest::post().cookie(cookie.clone()).uri("/api/v1/lists").set_json(json!({ "name": "hello" })).to_request();
+        let res = actix_web::rt::time::timeout(Duration::from_secs(5), test::call_service(&app, req)).await
+            .expect("the upload blocked the request");
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+}
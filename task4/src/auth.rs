@@ -0,0 +1,116 @@
+use std::fmt::{Display, Formatter};
+
+use actix_session::{Session, SessionExt};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use anyhow::anyhow;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use tokio::sync::OnceCell;
+
+use crate::api;
+use crate::api::ApiError;
+use crate::dao::UserDao;
+use crate::error::{TaskListError, TaskListResult};
+use crate::events::ChangeBus;
+use crate::model::user;
+use crate::model::user::{Credentials, User};
+use crate::model::validation::Validate;
+
+/// Session entry with the logged in `User`.
+const USER_KEY: &str = "user";
+
+/// Checked against when the username is unknown, so that takes as long as a wrong password.
+static DUMMY_HASH: OnceCell<String> = OnceCell::const_new();
+
+/// Hashing is slow on purpose, so it runs off the async workers.
+async fn hash_password(password: String) -> TaskListResult<String> {
+    web::block(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt).map(|hash| hash.to_string())
+    }).await
+        .map_err(|err| TaskListError::Unknown(anyhow!("{err}")))?
+        .map_err(|err| TaskListError::Unknown(anyhow!("{err}")))
+}
+
+async fn verify_password(password: String, password_hash: String) -> TaskListResult<bool> {
+    web::block(move || {
+        let password_hash = PasswordHash::new(&password_hash)?;
+        Ok(Argon2::default().verify_password(password.as_bytes(), &password_hash).is_ok())
+    }).await
+        .map_err(|err| TaskListError::Unknown(anyhow!("{err}")))?
+        .map_err(|err: argon2::password_hash::Error| TaskListError::Unknown(anyhow!("{err}")))
+}
+
+pub async fn register(user_dao: &dyn UserDao, credentials: Credentials) -> TaskListResult<User> {
+    let credentials = credentials.validate()?;
+    let password_hash = hash_password(credentials.password).await?;
+    user_dao.add(credentials.username, password_hash).await
+}
+
+/// Fails with `InvalidCredentials` alike for unknown users and wrong passwords.
+pub async fn authenticate(user_dao: &dyn UserDao, credentials: Credentials) -> TaskListResult<User> {
+    let record = user_dao.get_by_username(credentials.username.trim().to_owned()).await?;
+    let password_hash = match &record {
+        Some(record) => record.password_hash.clone(),
+        None => DUMMY_HASH.get_or_try_init(|| hash_password(String::new())).await?.clone(),
+    };
+    match (verify_password(credentials.password, password_hash).await?, record) {
+        (true, Some(record)) => Ok(record.user),
+        _ => Err(TaskListError::InvalidCredentials),
+    }
+}
+
+/// Starts a new session, so an id known before logging in is of no use.
+pub fn log_in(session: &Session, user: &User) -> TaskListResult<()> {
+    session.renew();
+    session.insert(USER_KEY, user).map_err(|err| TaskListError::Unknown(anyhow!("{err}")))
+}
+
+/// Also ends the change subscriptions of the user, their other sessions subscribe again.
+pub fn log_out(session: &Session, bus: &ChangeBus) {
+    if let Ok(Some(user)) = session.get::<User>(USER_KEY) {
+        bus.end_user(user.id);
+    }
+    session.purge();
+}
+
+pub fn current_user(req: &HttpRequest) -> Option<User> {
+    req.get_session().get::<User>(USER_KEY).ok().flatten()
+}
+
+/// Id of the logged in user, DAOs act on their behalf.
+pub fn current_user_id(req: &HttpRequest) -> Result<user::Id, Unauthorized> {
+    current_user(req)
+        .map(|user| user.id)
+        .ok_or(Unauthorized { api: req.path().starts_with(api::PREFIX) })
+}
+
+/// Nobody is logged in: the API answers 401, pages send to the login form.
+#[derive(Clone, Copy, Debug)]
+pub struct Unauthorized {
+    api: bool,
+}
+
+impl Display for Unauthorized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("log in first")
+    }
+}
+
+impl ResponseError for Unauthorized {
+    fn status_code(&self) -> StatusCode {
+        if self.api { StatusCode::UNAUTHORIZED } else { StatusCode::SEE_OTHER }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.api {
+            return ApiError::new(StatusCode::UNAUTHORIZED, self.to_string()).error_response();
+        }
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login"))
+            .finish()
+    }
+}
@@ -1,14 +1,23 @@
-use actix_web::{get, post, Responder, web};
-use serde::Deserialize;
+use actix_session::Session;
+use actix_web::{get, HttpRequest, HttpResponse, post, Responder, web};
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
 
-use crate::dao::{TaskDao, TaskListDao};
+use crate::auth;
+use crate::dao::{MemberDao, TaskDao, TaskListDao, UserDao};
 use crate::dep_middleware::Dependency;
-use crate::error::TaskListResult;
-use crate::model::{task, task_list};
-use crate::model::task::TaskIn;
-use crate::model::task_list::{TaskListIn, TaskListWithTasks};
+use crate::error::{TaskListError, TaskListResult};
+use crate::events::ChangeBus;
+use crate::model::{task, task_list, user};
+use crate::model::member::{check_role, MemberIn, Role};
+use crate::model::query::{TaskListQuery, TaskQuery};
+use crate::model::task::{Priority, TaskIn};
+use crate::model::task_list::TaskListIn;
+use crate::model::user::{Credentials, Settings};
+use crate::model::validation::{FieldErrors, Validate};
 
 use super::view;
+use super::view::PageContext;
 
 #[derive(Deserialize)]
 pub struct ListId {
@@ -21,67 +30,336 @@ pub struct TaskId {
     pub id: task::Id,
 }
 
-async fn err_or_task_lists(res: TaskListResult<()>,
-                           task_list_dao: &Dependency<dyn TaskListDao>) ->
-                           TaskListResult<Vec<task_list::TaskListOut>> {
-    match res {
-        Ok(_) => task_list_dao.get_all().await,
-        Err(e) => Err(e)
+#[derive(Deserialize)]
+pub struct MemberId {
+    pub list_id: task_list::Id,
+    pub user_id: user::Id,
+}
+
+/// `TaskIn` as sent by the HTML form, where empty fields mean none and tags are comma separated.
+#[derive(Deserialize)]
+pub struct TaskForm {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub recurrence: String,
+}
+
+fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if value.is_empty() {
+        return Ok(None);
     }
+    value.parse().map(Some).map_err(serde::de::Error::custom)
 }
 
-async fn err_or_task_list(res: TaskListResult<()>,
-                          list_id: task_list::Id,
-                          task_list_dao: &Dependency<dyn TaskListDao>) ->
-                          TaskListResult<TaskListWithTasks> {
-    match res {
-        Ok(_) => task_list_dao.get_by_id(list_id).await,
-        Err(e) => Err(e)
+impl From<TaskForm> for TaskIn {
+    fn from(form: TaskForm) -> Self {
+        TaskIn {
+            name: form.name,
+            description: Some(form.description).filter(|x| !x.trim().is_empty()),
+            due: form.due,
+            priority: form.priority,
+            tags: form.tags
+                .split(',')
+                .map(|tag| tag.trim().to_owned())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            recurrence: Some(form.recurrence).filter(|x| !x.trim().is_empty()),
+        }
+    }
+}
+
+/// `Settings` as sent by the HTML form, an unknown time zone is reported like other invalid input.
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    pub time_zone: String,
+}
+
+impl TryFrom<SettingsForm> for Settings {
+    type Error = TaskListError;
+
+    fn try_from(form: SettingsForm) -> TaskListResult<Self> {
+        let time_zone = form.time_zone.trim().parse().map_err(|_| {
+            let mut errors = FieldErrors::default();
+            errors.add("time_zone", "must be a time zone like Europe/Berlin");
+            TaskListError::Invalid(errors)
+        })?;
+        Ok(Settings { time_zone })
     }
 }
 
+#[derive(Deserialize)]
+pub struct TaskPosition {
+    pub position: usize,
+}
+
+/// Refuses before touching the list when the role does not allow it, the DAOs check it again.
+async fn require_role(list_id: task_list::Id,
+                      required: Role,
+                      member_dao: &Dependency<dyn MemberDao>) -> TaskListResult<()> {
+    check_role(list_id, Some(member_dao.get_role(list_id).await?), required).map(|_| ())
+}
+
+#[get("/login")]
+pub async fn get_login(ctx: PageContext) -> HttpResponse {
+    view::view_login(&ctx)
+}
+
+#[post("/login")]
+pub async fn log_in(credentials: web::Form<Credentials>,
+                    session: Session,
+                    user_dao: Dependency<dyn UserDao>) -> HttpResponse {
+    let res = match auth::authenticate(&**user_dao, credentials.into_inner()).await {
+        Ok(user) => auth::log_in(&session, &user),
+        Err(e) => Err(e),
+    };
+    let location = if res.is_ok() { "/lists" } else { "/login" };
+    view::view_submitted(res, &session, location)
+}
+
+#[get("/register")]
+pub async fn get_register(ctx: PageContext) -> HttpResponse {
+    view::view_register(&ctx)
+}
+
+#[post("/register")]
+pub async fn register(credentials: web::Form<Credentials>,
+                      session: Session,
+                      user_dao: Dependency<dyn UserDao>) -> HttpResponse {
+    let res = match auth::register(&**user_dao, credentials.into_inner()).await {
+        Ok(user) => auth::log_in(&session, &user),
+        Err(e) => Err(e),
+    };
+    let location = if res.is_ok() { "/lists" } else { "/register" };
+    view::view_form_submitted(res, &session, location, "register")
+}
+
+#[post("/logout")]
+pub async fn log_out(session: Session, bus: web::Data<ChangeBus>) -> HttpResponse {
+    auth::log_out(&session, &bus);
+    view::redirect("/login")
+}
+
 #[get("/lists")]
-pub async fn get_todo_lists(task_list_dao: Dependency<dyn TaskListDao>) -> impl Responder {
-    let task_lists = task_list_dao.get_all().await;
-    view::view_get_todo_lists(task_lists)
+pub async fn get_todo_lists(query: web::Query<TaskListQuery>,
+                            ctx: PageContext,
+                            task_list_dao: Dependency<dyn TaskListDao>,
+                            user_dao: Dependency<dyn UserDao>) -> actix_web::Result<impl Responder> {
+    let user_id = ctx.user_id?;
+    let query = query.into_inner();
+    let task_lists = async {
+        Ok((task_list_dao.find(query.clone()).await?, user_dao.get_settings(user_id).await?))
+    }.await;
+    Ok(view::view_find_todo_lists(task_lists, &query, &ctx))
+}
+
+#[post("/settings")]
+pub async fn update_settings(req: HttpRequest,
+                             settings: web::Form<SettingsForm>,
+                             session: Session,
+                             user_dao: Dependency<dyn UserDao>) -> actix_web::Result<impl Responder> {
+    let user_id = auth::current_user_id(&req)?;
+    let res = async {
+        user_dao.update_settings(user_id, Settings::try_from(settings.into_inner())?).await.map(|_| ())
+    }.await;
+    Ok(view::view_form_submitted(res, &session, "/lists", "settings"))
 }
 
 #[post("/lists/{id}/drop")]
 pub async fn delete_task_list(list_id: web::Path<ListId>,
-                              task_list_dao: Dependency<dyn TaskListDao>) -> impl Responder {
-    let res = task_list_dao.delete(list_id.id).await;
-    view::view_get_todo_lists(err_or_task_lists(res, &task_list_dao).await)
+                              session: Session,
+                              task_list_dao: Dependency<dyn TaskListDao>,
+                              member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(list_id.id, Role::Owner, &member_dao).await?;
+        task_list_dao.delete(list_id.id).await
+    }.await;
+    view::view_submitted(res, &session, "/lists")
 }
 
 #[post("/lists")]
 pub async fn add_task_list(task_list: web::Form<TaskListIn>,
+                           session: Session,
                            task_list_dao: Dependency<dyn TaskListDao>) -> impl Responder {
-    let res = task_list_dao.add(task_list.into_inner()).await;
-    view::view_get_todo_lists(err_or_task_lists(res, &task_list_dao).await)
+    let res = async {
+        task_list_dao.add(task_list.into_inner().validate()?).await.map(|_| ())
+    }.await;
+    view::view_form_submitted(res, &session, "/lists", "add_list")
+}
+
+#[post("/lists/{id}/rename")]
+pub async fn rename_task_list(list_id: web::Path<ListId>,
+                              task_list: web::Form<TaskListIn>,
+                              session: Session,
+                              task_list_dao: Dependency<dyn TaskListDao>,
+                              member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(list_id.id, Role::Editor, &member_dao).await?;
+        task_list_dao.rename(list_id.id, task_list.into_inner().validate()?).await.map(|_| ())
+    }.await;
+    view::view_form_submitted(res, &session, &format!("/lists/{}", list_id.id), "rename")
 }
 
 #[get("/lists/{id}")]
 pub async fn get_task_list(list_id: web::Path<ListId>,
-                           task_list_dao: Dependency<dyn TaskListDao>) -> impl Responder {
-    let task_list = task_list_dao.get_by_id(list_id.id).await;
-    view::view_get_todo_list(task_list)
+                           query: web::Query<TaskQuery>,
+                           ctx: PageContext,
+                           task_list_dao: Dependency<dyn TaskListDao>,
+                           task_dao: Dependency<dyn TaskDao>,
+                           member_dao: Dependency<dyn MemberDao>,
+                           user_dao: Dependency<dyn UserDao>) -> actix_web::Result<impl Responder> {
+    let user_id = ctx.user_id?;
+    let query = query.into_inner();
+    let task_list = async {
+        let role = member_dao.get_role(list_id.id).await?;
+        let mut task_list = task_list_dao.get_by_id(list_id.id).await?;
+        let page = task_dao.find(list_id.id, query.clone()).await?;
+        task_list.tasks = page.items;
+        Ok((task_list, role, page.next, user_dao.get_settings(user_id).await?))
+    }.await;
+    Ok(view::view_find_todo_list(task_list, &query, &ctx))
 }
 
 #[post("/lists/{id}/tasks")]
 pub async fn add_task(list_id: web::Path<ListId>,
-                      task: web::Form<TaskIn>,
-                      task_list_dao: Dependency<dyn TaskListDao>,
-                      task_dao: Dependency<dyn TaskDao>) -> impl Responder {
-    let res = task_dao
-        .add(list_id.id, task.into_inner()).await;
-    view::view_get_todo_list(err_or_task_list(res, list_id.id, &task_list_dao).await)
+                      task: web::Form<TaskForm>,
+                      session: Session,
+                      task_dao: Dependency<dyn TaskDao>,
+                      member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(list_id.id, Role::Editor, &member_dao).await?;
+        task_dao.add(list_id.id, TaskIn::from(task.into_inner()).validate()?).await.map(|_| ())
+    }.await;
+    view::view_form_submitted(res, &session, &format!("/lists/{}", list_id.id), "add_task")
 }
 
 #[post("/lists/{list_id}/tasks/{id}/done")]
 pub async fn mark_task_as_done(task_id: web::Path<TaskId>,
-                               task_list_dao: Dependency<dyn TaskListDao>,
-                               task_dao: Dependency<dyn TaskDao>) -> impl Responder {
-    let res = task_dao
-        .mark_as_done(task_id.list_id, task_id.id).await;
-    view::view_get_todo_list(err_or_task_list(res, task_id.list_id, &task_list_dao).await)
+                               session: Session,
+                               task_dao: Dependency<dyn TaskDao>,
+                               member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(task_id.list_id, Role::Editor, &member_dao).await?;
+        task_dao.mark_as_done(task_id.list_id, task_id.id).await.map(|_| ())
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}", task_id.list_id))
+}
+
+#[post("/lists/{list_id}/tasks/{id}/toggle")]
+pub async fn toggle_task(task_id: web::Path<TaskId>,
+                         session: Session,
+                         task_dao: Dependency<dyn TaskDao>,
+                         member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(task_id.list_id, Role::Editor, &member_dao).await?;
+        task_dao.toggle(task_id.list_id, task_id.id).await.map(|_| ())
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}", task_id.list_id))
+}
+
+#[post("/lists/{list_id}/tasks/{id}/edit")]
+pub async fn update_task(task_id: web::Path<TaskId>,
+                         task: web::Form<TaskForm>,
+                         session: Session,
+                         task_dao: Dependency<dyn TaskDao>,
+                         member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(task_id.list_id, Role::Editor, &member_dao).await?;
+        task_dao.update(task_id.list_id, task_id.id, TaskIn::from(task.into_inner()).validate()?).await.map(|_| ())
+    }.await;
+    view::view_form_submitted(res, &session, &format!("/lists/{}", task_id.list_id), &task_id.id.to_string())
+}
+
+#[post("/lists/{list_id}/tasks/{id}/drop")]
+pub async fn delete_task(task_id: web::Path<TaskId>,
+                         session: Session,
+                         task_dao: Dependency<dyn TaskDao>,
+                         member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(task_id.list_id, Role::Editor, &member_dao).await?;
+        task_dao.delete(task_id.list_id, task_id.id).await
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}", task_id.list_id))
+}
+
+#[post("/lists/{list_id}/tasks/{id}/move")]
+pub async fn move_task(task_id: web::Path<TaskId>,
+                       position: web::Form<TaskPosition>,
+                       session: Session,
+                       task_dao: Dependency<dyn TaskDao>,
+                       member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(task_id.list_id, Role::Editor, &member_dao).await?;
+        task_dao.move_to(task_id.list_id, task_id.id, position.position).await
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}", task_id.list_id))
+}
+
+#[get("/lists/{id}/members")]
+pub async fn get_members(list_id: web::Path<ListId>,
+                         ctx: PageContext,
+                         task_list_dao: Dependency<dyn TaskListDao>,
+                         member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let members = async {
+        let role = member_dao.get_role(list_id.id).await?;
+        let task_list = task_list_dao.get_by_id(list_id.id).await?;
+        Ok((task_list, role, member_dao.get_members(list_id.id).await?))
+    }.await;
+    view::view_members(members, &ctx)
+}
+
+#[post("/lists/{id}/members")]
+pub async fn invite_member(list_id: web::Path<ListId>,
+                           member: web::Form<MemberIn>,
+                           session: Session,
+                           member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(list_id.id, Role::Owner, &member_dao).await?;
+        member_dao.invite(list_id.id, member.into_inner()).await.map(|_| ())
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}/members", list_id.id))
+}
+
+#[post("/lists/{list_id}/members/{user_id}/drop")]
+pub async fn revoke_member(member_id: web::Path<MemberId>,
+                           session: Session,
+                           member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = async {
+        require_role(member_id.list_id, Role::Owner, &member_dao).await?;
+        member_dao.revoke(member_id.list_id, member_id.user_id).await
+    }.await;
+    view::view_submitted(res, &session, &format!("/lists/{}/members", member_id.list_id))
+}
+
+#[get("/invitations")]
+pub async fn get_invitations(ctx: PageContext,
+                             member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    view::view_invitations(member_dao.get_invitations().await, &ctx)
+}
+
+#[post("/invitations/{id}/accept")]
+pub async fn accept_invitation(list_id: web::Path<ListId>,
+                               session: Session,
+                               member_dao: Dependency<dyn MemberDao>) -> impl Responder {
+    let res = member_dao.accept(list_id.id).await;
+    view::view_submitted(res, &session, "/invitations")
+}
+
+/// Declines an invitation, or leaves the list once it is accepted.
+#[post("/invitations/{id}/decline")]
+pub async fn decline_invitation(req: HttpRequest,
+                                list_id: web::Path<ListId>,
+                                session: Session,
+                                member_dao: Dependency<dyn MemberDao>) -> actix_web::Result<impl Responder> {
+    let user_id = auth::current_user_id(&req)?;
+    let res = member_dao.revoke(list_id.id, user_id).await;
+    Ok(view::view_submitted(res, &session, "/invitations"))
 }
@@ -0,0 +1,114 @@
+use actix_session::{Session, SessionExt};
+use actix_web::{Error, ResponseError};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::http::header::HeaderName;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api;
+use crate::view;
+
+/// Session entry with the token forms have to send back.
+const TOKEN_KEY: &str = "csrf_token";
+
+/// Header every change through the JSON API has to carry. Forms can't send it,
+/// and scripts of other sites only after a CORS preflight, which is never allowed.
+pub const API_HEADER: HeaderName = HeaderName::from_static("x-requested-with");
+
+/// Hidden field of every form.
+#[derive(Deserialize)]
+struct TokenField {
+    csrf_token: Option<String>,
+}
+
+/// Token of the session, created on first use and kept until the session ends.
+pub fn token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(TOKEN_KEY) {
+        return token;
+    }
+    let token = Uuid::new_v4().simple().to_string();
+    let _ = session.insert(TOKEN_KEY, &token);
+    token
+}
+
+/// Rejects form submits without the token of the session, and changes through the JSON API without `API_HEADER`.
+pub async fn verify(mut req: ServiceRequest,
+                    next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if req.path().starts_with(api::PREFIX) {
+        let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+        if !safe && !req.headers().contains_key(API_HEADER) {
+            let res = api::ApiError::new(StatusCode::FORBIDDEN, format!("changes need the {API_HEADER} header"));
+            return Ok(req.into_response(res.error_response()));
+        }
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+    if req.method() != Method::POST {
+        return next.call(req).await.map(ServiceResponse::map_into_boxed_body);
+    }
+    let body = req.extract::<Bytes>().await?;
+    let sent = serde_urlencoded::from_bytes::<TokenField>(&body).ok().and_then(|field| field.csrf_token);
+    let expected = req.get_session().get::<String>(TOKEN_KEY).ok().flatten();
+    if sent.is_none() || sent != expected {
+        let res = view::view_error(StatusCode::FORBIDDEN, "the form has expired, reload the page and try again");
+        return Ok(req.into_response(res));
+    }
+    req.set_payload(Payload::from(body));
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actix_session::SessionMiddleware;
+    use actix_session::storage::CookieSessionStore;
+    use actix_web::{App, test, web};
+    use actix_web::cookie::Key;
+    use actix_web::http::header::ContentType;
+    use actix_web::middleware::from_fn;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_verify() {
+        let app = test::init_service(App::new()
+            .wrap(from_fn(verify))
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::from(&[0; 64])))
+            .route("/token", web::get().to(|session: Session| async move { token(&session) }))
+            .route("/echo", web::post().to(|body: String| async move { body }))
+            .route("/api/v1/echo", web::route().to(|body: String| async move { body }))).await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/token").to_request()).await;
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+        let token = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+        let submit = |body: String, with_cookie: bool| {
+            let req = test::TestRequest::post().uri("/echo").insert_header(ContentType::form_url_encoded());
+            let req = if with_cookie { req.cookie(cookie.clone()) } else { req };
+            req.set_payload(body).to_request()
+        };
+        for (body, with_cookie) in [("name=x".to_owned(), true),
+                                    ("csrf_token=wrong&name=x".to_owned(), true),
+                                    (format!("csrf_token={token}&name=x"), false)] {
+            let res = test::call_service(&app, submit(body, with_cookie)).await;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+
+        let body = format!("csrf_token={token}&name=x");
+        let res = test::call_service(&app, submit(body.clone(), true)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, body.as_bytes());
+
+        let api = |req: test::TestRequest| req.uri("/api/v1/echo").cookie(cookie.clone()).set_payload("x").to_request();
+        for req in [test::TestRequest::post(), test::TestRequest::delete(),
+                    test::TestRequest::post().insert_header(ContentType::plaintext())] {
+            assert_eq!(test::call_service(&app, api(req)).await.status(), StatusCode::FORBIDDEN);
+        }
+        for req in [test::TestRequest::get(), test::TestRequest::post().insert_header((API_HEADER, "XMLHttpRequest"))] {
+            assert_eq!(test::call_service(&app, api(req)).await.status(), StatusCode::OK);
+        }
+    }
+}
@@ -3,35 +3,139 @@ use std::ops::Deref;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use futures::lock::Mutex;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::lock::{Mutex, OwnedMutexGuard};
 use uuid::Uuid;
 
 use crate::error::{TaskListError, TaskListResult};
+use crate::model::member::{check_role, Invitation, Member, MemberIn, Role};
+use crate::model::query::{sort_by_name, Page, TaskListQuery, TaskQuery};
+use crate::model::recurrence::{next_task, today};
 use crate::model::task::{TaskIn, TaskOut};
 use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+use crate::model::user::{Settings, User, UserRecord};
+use crate::model::validation::MAX_TASKS;
 
-use super::model::{task, task_list};
+use super::model::{task, task_list, user};
 
+pub mod file;
+pub mod notify;
+pub mod postgres;
+pub mod sqlite;
+
+/// Lists the user of the request owns or is a member of, others are `Forbidden`.
+/// Viewers can only read, renaming needs an editor and deleting the owner.
 #[async_trait]
-pub trait TaskListDao {
-    async fn add(&self, data: TaskListIn) -> TaskListResult<()>;
+pub trait TaskListDao: Send + Sync {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut>;
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut>;
     async fn delete(&self, id: task_list::Id) -> TaskListResult<()>;
+    /// Every list, sorted by name.
     async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>>;
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>>;
     async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks>;
 }
 
+/// Tasks in lists of the user, like `TaskListDao`. Only `find` is allowed to viewers.
+#[async_trait]
+pub trait TaskDao: Send + Sync {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut>;
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut>;
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()>;
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>>;
+    /// A recurring task is followed by its next occurrence, added at the end of the list and returned.
+    /// The done task keeps its history but not its rule, which moves on to the next one.
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>>;
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()>;
+    /// The task with the occurrence which follows it, if it is done now like by `mark_as_done`.
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)>;
+    /// Moves the task to `position` in its list, past the end means last.
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()>;
+}
+
+#[async_trait]
+pub trait UserDao {
+    /// Fails with `UsernameTaken` if there is a user with the same name.
+    async fn add(&self, username: String, password_hash: String) -> TaskListResult<User>;
+    async fn get_by_username(&self, username: String) -> TaskListResult<Option<UserRecord>>;
+    /// Default settings for unknown users.
+    async fn get_settings(&self, id: user::Id) -> TaskListResult<Settings>;
+    async fn update_settings(&self, id: user::Id, settings: Settings) -> TaskListResult<Settings>;
+}
+
+/// Sharing of lists, for the user of the request like `TaskListDao`.
+#[async_trait]
+pub trait MemberDao: Send + Sync {
+    /// Role of the user in the list, `Forbidden` if they have none.
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role>;
+    /// Members and pending invitations, seen by every member.
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>>;
+    /// Only the owner invites, the role is given once the user accepts.
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member>;
+    /// Removes a member or an invitation. The owner revokes anybody, others only themselves.
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()>;
+    /// Invitations of the user which are not accepted yet, sorted by list name.
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>>;
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()>;
+}
+
+/// Task list and task DAOs which change the storage together: nobody sees their changes before `commit`,
+/// and the other requests wait for it.
+/// Its DAOs must not be used once the unit is committed or rolled back.
 #[async_trait]
-pub trait TaskDao {
-    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<()>;
-    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()>;
+pub trait UnitOfWork: Send + Sync {
+    fn task_lists(&self) -> Box<dyn TaskListDao>;
+    fn tasks(&self) -> Box<dyn TaskDao>;
+    /// Does nothing if the unit is already finished.
+    async fn commit(&self) -> TaskListResult<()>;
+    /// Also done when an unfinished unit is dropped.
+    async fn rollback(&self) -> TaskListResult<()>;
+}
+
+/// Commits `unit` if `res` is `Ok`, otherwise rolls it back.
+pub async fn finish<T>(unit: &dyn UnitOfWork, res: TaskListResult<T>) -> TaskListResult<T> {
+    match res {
+        Ok(value) => unit.commit().await.map(|_| value),
+        Err(err) => {
+            unit.rollback().await?;
+            Err(err)
+        }
+    }
 }
 
+type Members = HashMap<task_list::Id, Vec<Member>>;
+
 #[derive(Clone)]
-pub struct TaskListMemoryState(Arc<Mutex<HashMap<task_list::Id, TaskListWithTasks>>>);
+pub struct TaskListMemoryState {
+    task_lists: Arc<Mutex<HashMap<task_list::Id, TaskListWithTasks>>>,
+    /// Locked after `task_lists`.
+    members: Arc<Mutex<Members>>,
+    /// Users by name, locked last.
+    users: Arc<Mutex<HashMap<String, UserRecord>>>,
+}
 
 impl TaskListMemoryState {
     pub fn new() -> Self {
-        TaskListMemoryState { 0: Arc::new(Mutex::new(HashMap::new())) }
+        Self::with_task_lists(vec![], vec![], HashMap::new())
+    }
+
+    pub fn with_task_lists(task_lists: Vec<TaskListWithTasks>, users: Vec<UserRecord>, members: Members) -> Self {
+        let task_lists = task_lists.into_iter().map(|x| (x.id, x)).collect();
+        let users = users.into_iter().map(|x| (x.user.username.clone(), x)).collect();
+        TaskListMemoryState {
+            task_lists: Arc::new(Mutex::new(task_lists)),
+            members: Arc::new(Mutex::new(members)),
+            users: Arc::new(Mutex::new(users)),
+        }
+    }
+
+    pub fn members(&self) -> &Mutex<Members> {
+        &self.members
+    }
+
+    pub fn users(&self) -> &Mutex<HashMap<String, UserRecord>> {
+        &self.users
     }
 }
 
@@ -39,151 +143,932 @@ impl Deref for TaskListMemoryState {
     type Target = Arc<Mutex<HashMap<task_list::Id, TaskListWithTasks>>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.task_lists
+    }
+}
+
+/// Role of the user, `None` for strangers and invitations which are not accepted.
+fn role_of(task_list: &TaskListWithTasks, members: &Members, user: user::Id) -> Option<Role> {
+    if task_list.owner == user {
+        return Some(Role::Owner);
+    }
+    members.get(&task_list.id)?
+        .iter()
+        .find(|x| x.user.id == user && x.accepted)
+        .map(|x| x.role)
+}
+
+fn find_task_list<'a>(state: &'a mut HashMap<task_list::Id, TaskListWithTasks>, members: &Members, user: user::Id,
+                      task_list_id: task_list::Id, role: Role) -> TaskListResult<&'a mut TaskListWithTasks> {
+    let task_list = state.get_mut(&task_list_id).ok_or_else(|| TaskListError::TaskListNotFound(task_list_id))?;
+    check_role(task_list_id, role_of(task_list, members, user), role)?;
+    Ok(task_list)
+}
+
+fn task_index(task_list: &TaskListWithTasks, id: task::Id) -> TaskListResult<usize> {
+    task_list.tasks
+        .iter()
+        .position(|x| x.id == id)
+        .ok_or_else(|| TaskListError::TaskNotFound(id))
+}
+
+fn find_task<'a>(state: &'a mut HashMap<task_list::Id, TaskListWithTasks>, members: &Members, user: user::Id,
+                 task_list_id: task_list::Id, id: task::Id) -> TaskListResult<&'a mut TaskOut> {
+    let task_list = find_task_list(state, members, user, task_list_id, Role::Editor)?;
+    let index = task_index(task_list, id)?;
+    Ok(&mut task_list.tasks[index])
+}
+
+/// Time zone of the user, UTC for unknown users.
+async fn time_zone_of(state: &TaskListMemoryState, user: user::Id) -> Tz {
+    state.users().lock().await
+        .values()
+        .find(|x| x.user.id == user)
+        .map(|x| x.settings.time_zone)
+        .unwrap_or_default()
+}
+
+/// Marks the task at `index` done and adds the occurrence which follows it, see `TaskDao::mark_as_done`.
+fn complete(task_list: &mut TaskListWithTasks, index: usize, today: NaiveDate) -> TaskListResult<Option<TaskOut>> {
+    let now = Utc::now();
+    let next = next_task(&task_list.tasks[index].core, today).map(|core| TaskOut::new(Uuid::new_v4(), core, now));
+    if next.is_some() && task_list.tasks.len() >= MAX_TASKS {
+        return Err(TaskListError::TooManyTasks(task_list.id));
     }
+    let task = &mut task_list.tasks[index];
+    task.set_done(true, now);
+    task.core.recurrence = None;
+    task_list.tasks.extend(next.clone());
+    Ok(next)
+}
+
+fn task_list_out(task_list: &TaskListWithTasks) -> TaskListOut {
+    TaskListOut { id: task_list.id, owner: task_list.owner, core: task_list.core.clone() }
 }
 
 pub struct MemoryTaskListDao {
     state: TaskListMemoryState,
+    user: user::Id,
 }
 
 impl MemoryTaskListDao {
-    pub fn new(state: TaskListMemoryState) -> Self {
-        Self { state }
+    pub fn new(state: TaskListMemoryState, user: user::Id) -> Self {
+        Self { state, user }
     }
 }
 
 #[async_trait]
 impl TaskListDao for MemoryTaskListDao {
-    async fn add(&self, data: TaskListIn) -> TaskListResult<()> {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut> {
         let mut state = self.state.lock().await;
         let next_id = Uuid::new_v4();
         state.insert(next_id,
-                     TaskListWithTasks { id: next_id, core: TaskListIn { name: data.name }, tasks: vec![] });
-        Ok(())
+                     TaskListWithTasks { id: next_id, owner: self.user, core: data.clone(), tasks: vec![] });
+        Ok(TaskListOut { id: next_id, owner: self.user, core: data })
+    }
+
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, id, Role::Editor)?;
+        task_list.core = data;
+        Ok(task_list_out(task_list))
     }
 
     async fn delete(&self, id: task_list::Id) -> TaskListResult<()> {
-        self.state
-            .lock()
-            .await
-            .remove(&id)
-            .map(|_| ())
-            .ok_or_else(|| TaskListError::TaskListNotFound(id))
+        let mut state = self.state.lock().await;
+        let mut members = self.state.members().lock().await;
+        find_task_list(&mut state, &members, self.user, id, Role::Owner)?;
+        state.remove(&id);
+        members.remove(&id);
+        Ok(())
     }
 
     async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>> {
-        Ok(self.state
-            .lock()
-            .await
-            .iter()
-            .map(|(_, x)|
-                TaskListOut { id: x.id, core: TaskListIn { name: x.core.name.clone() } }
-            )
-            .collect())
+        let state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let mut task_lists: Vec<_> = state
+            .values()
+            .filter(|x| role_of(x, &members, self.user).is_some())
+            .map(task_list_out)
+            .collect();
+        sort_by_name(&mut task_lists);
+        Ok(task_lists)
+    }
+
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>> {
+        query.apply(self.get_all().await?)
     }
 
     async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks> {
-        self.state
-            .lock()
-            .await
-            .get(&id)
-            .map(|x| x.clone())
-            .ok_or_else(|| TaskListError::TaskListNotFound(id))
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        find_task_list(&mut state, &members, self.user, id, Role::Viewer).map(|x| x.clone())
     }
 }
 
 pub struct MemoryTaskDao {
     state: TaskListMemoryState,
+    user: user::Id,
 }
 
 impl MemoryTaskDao {
+    pub fn new(state: TaskListMemoryState, user: user::Id) -> Self {
+        Self { state, user }
+    }
+}
+
+#[async_trait]
+impl TaskDao for MemoryTaskDao {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Editor)?;
+        if task_list.tasks.len() >= MAX_TASKS {
+            return Err(TaskListError::TooManyTasks(task_list_id));
+        }
+        let task = TaskOut::new(Uuid::new_v4(), data, Utc::now());
+        task_list.tasks.push(task.clone());
+        Ok(task)
+    }
+
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task = find_task(&mut state, &members, self.user, task_list_id, id)?;
+        task.core = data;
+        task.updated_at = Utc::now();
+        Ok(task.clone())
+    }
+
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Editor)?;
+        let index = task_index(task_list, id)?;
+        task_list.tasks.remove(index);
+        Ok(())
+    }
+
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let tasks = find_task_list(&mut state, &members, self.user, task_list_id, Role::Viewer)?.tasks.clone();
+        query.apply(tasks)
+    }
+
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Editor)?;
+        let index = task_index(task_list, id)?;
+        if task_list.tasks[index].done {
+            return Err(TaskListError::TaskAlreadyDone(id));
+        }
+        complete(task_list, index, today(time_zone_of(&self.state, self.user).await))
+    }
+
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task = find_task(&mut state, &members, self.user, task_list_id, id)?;
+        if !task.done {
+            return Err(TaskListError::TaskNotDone(id));
+        }
+        task.set_done(false, Utc::now());
+        Ok(())
+    }
+
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Editor)?;
+        let index = task_index(task_list, id)?;
+        let next = if task_list.tasks[index].done {
+            task_list.tasks[index].set_done(false, Utc::now());
+            None
+        } else {
+            complete(task_list, index, today(time_zone_of(&self.state, self.user).await))?
+        };
+        Ok((task_list.tasks[index].clone(), next))
+    }
+
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Editor)?;
+        let task = task_list.tasks.remove(task_index(task_list, id)?);
+        let position = position.min(task_list.tasks.len());
+        task_list.tasks.insert(position, task);
+        Ok(())
+    }
+}
+
+pub struct MemoryUserDao {
+    state: TaskListMemoryState,
+}
+
+impl MemoryUserDao {
     pub fn new(state: TaskListMemoryState) -> Self {
         Self { state }
     }
 }
 
 #[async_trait]
-impl TaskDao for MemoryTaskDao {
-    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<()> {
-        self.state
-            .lock()
-            .await
-            .get_mut(&task_list_id)
-            .map(|task|
-                task.tasks.push(TaskOut { core: data, id: Uuid::new_v4(), done: false }))
-            .ok_or_else(|| TaskListError::TaskListNotFound(task_list_id))
-    }
-
-    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
-        self.state
-            .lock()
-            .await
-            .get_mut(&task_list_id)
-            .map(|task_list| {
-                task_list.tasks
-                    .iter_mut()
-                    .find(|x| x.id == id)
-                    .map(|task| if !task.done {
-                        task.done = true;
-                        Ok(())
-                    } else {
-                        Err(TaskListError::TaskAlreadyDone(id))
-                    })
-                    .unwrap_or(Err(TaskListError::TaskNotFound(id)))
+impl UserDao for MemoryUserDao {
+    async fn add(&self, username: String, password_hash: String) -> TaskListResult<User> {
+        let mut users = self.state.users().lock().await;
+        if users.contains_key(&username) {
+            return Err(TaskListError::UsernameTaken(username));
+        }
+        let user = User { id: Uuid::new_v4(), username: username.clone() };
+        users.insert(username, UserRecord { user: user.clone(), password_hash, settings: Settings::default() });
+        Ok(user)
+    }
+
+    async fn get_by_username(&self, username: String) -> TaskListResult<Option<UserRecord>> {
+        Ok(self.state.users().lock().await.get(&username).cloned())
+    }
+
+    async fn get_settings(&self, id: user::Id) -> TaskListResult<Settings> {
+        Ok(self.state.users().lock().await
+            .values()
+            .find(|x| x.user.id == id)
+            .map(|x| x.settings.clone())
+            .unwrap_or_default())
+    }
+
+    async fn update_settings(&self, id: user::Id, settings: Settings) -> TaskListResult<Settings> {
+        let mut users = self.state.users().lock().await;
+        let record = users.values_mut().find(|x| x.user.id == id).ok_or_else(|| TaskListError::UserNotFound(id.to_string()))?;
+        record.settings = settings.clone();
+        Ok(settings)
+    }
+}
+
+pub struct MemoryMemberDao {
+    state: TaskListMemoryState,
+    user: user::Id,
+}
+
+impl MemoryMemberDao {
+    pub fn new(state: TaskListMemoryState, user: user::Id) -> Self {
+        Self { state, user }
+    }
+}
+
+#[async_trait]
+impl MemberDao for MemoryMemberDao {
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role> {
+        let state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let task_list = state.get(&task_list_id).ok_or_else(|| TaskListError::TaskListNotFound(task_list_id))?;
+        check_role(task_list_id, role_of(task_list, &members, self.user), Role::Viewer)
+    }
+
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>> {
+        let mut state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        find_task_list(&mut state, &members, self.user, task_list_id, Role::Viewer)?;
+        Ok(members.get(&task_list_id).cloned().unwrap_or_default())
+    }
+
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member> {
+        let mut state = self.state.lock().await;
+        let mut members = self.state.members().lock().await;
+        let task_list = find_task_list(&mut state, &members, self.user, task_list_id, Role::Owner)?;
+        if data.role == Role::Owner {
+            return Err(TaskListError::OwnerInvited);
+        }
+        let user = self.state.users().lock().await
+            .get(&data.username)
+            .map(|x| x.user.clone())
+            .ok_or_else(|| TaskListError::UserNotFound(data.username.clone()))?;
+        let task_list_members = members.entry(task_list_id).or_default();
+        if user.id == task_list.owner || task_list_members.iter().any(|x| x.user.id == user.id) {
+            return Err(TaskListError::AlreadyMember(data.username));
+        }
+        let member = Member { user, role: data.role, accepted: false };
+        task_list_members.push(member.clone());
+        Ok(member)
+    }
+
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()> {
+        let state = self.state.lock().await;
+        let mut members = self.state.members().lock().await;
+        let task_list = state.get(&task_list_id).ok_or_else(|| TaskListError::TaskListNotFound(task_list_id))?;
+        if user_id != self.user {
+            check_role(task_list_id, role_of(task_list, &members, self.user), Role::Owner)?;
+        }
+        let task_list_members = members.get_mut(&task_list_id).ok_or(TaskListError::MemberNotFound(user_id))?;
+        let index = task_list_members
+            .iter()
+            .position(|x| x.user.id == user_id)
+            .ok_or(TaskListError::MemberNotFound(user_id))?;
+        task_list_members.remove(index);
+        Ok(())
+    }
+
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>> {
+        let state = self.state.lock().await;
+        let members = self.state.members().lock().await;
+        let mut invitations: Vec<_> = members
+            .iter()
+            .filter_map(|(task_list_id, members)| {
+                let member = members.iter().find(|x| x.user.id == self.user && !x.accepted)?;
+                Some(Invitation { task_list: task_list_out(state.get(task_list_id)?), role: member.role })
             })
-            .unwrap_or(Err(TaskListError::TaskListNotFound(task_list_id)))
+            .collect();
+        invitations.sort_by(|a, b| (&a.task_list.core.name, a.task_list.id).cmp(&(&b.task_list.core.name, b.task_list.id)));
+        Ok(invitations)
+    }
+
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()> {
+        let mut members = self.state.members().lock().await;
+        let member = members.get_mut(&task_list_id)
+            .and_then(|members| members.iter_mut().find(|x| x.user.id == self.user && !x.accepted))
+            .ok_or(TaskListError::InvitationNotFound(task_list_id))?;
+        member.accepted = true;
+        Ok(())
+    }
+}
+
+/// Lists and members locked by a unit of work until it is finished.
+type Locked = (OwnedMutexGuard<HashMap<task_list::Id, TaskListWithTasks>>, OwnedMutexGuard<Members>);
+
+/// Its DAOs change a snapshot of the lists and members, which replaces them on commit.
+pub struct MemoryUnitOfWork {
+    locked: std::sync::Mutex<Option<Locked>>,
+    snapshot: TaskListMemoryState,
+    user: user::Id,
+}
+
+impl MemoryUnitOfWork {
+    pub async fn begin(state: TaskListMemoryState, user: user::Id) -> Self {
+        let task_lists = state.task_lists.clone().lock_owned().await;
+        let members = state.members.clone().lock_owned().await;
+        let snapshot = TaskListMemoryState {
+            task_lists: Arc::new(Mutex::new(task_lists.clone())),
+            members: Arc::new(Mutex::new(members.clone())),
+            users: state.users.clone(),
+        };
+        Self { locked: std::sync::Mutex::new(Some((task_lists, members))), snapshot, user }
+    }
+
+    fn unlock(&self) -> Option<Locked> {
+        self.locked.lock().unwrap().take()
     }
 }
 
+#[async_trait]
+impl UnitOfWork for MemoryUnitOfWork {
+    fn task_lists(&self) -> Box<dyn TaskListDao> {
+        Box::new(MemoryTaskListDao::new(self.snapshot.clone(), self.user))
+    }
+
+    fn tasks(&self) -> Box<dyn TaskDao> {
+        Box::new(MemoryTaskDao::new(self.snapshot.clone(), self.user))
+    }
+
+    async fn commit(&self) -> TaskListResult<()> {
+        let Some((mut task_lists, mut members)) = self.unlock() else {
+            return Ok(());
+        };
+        *task_lists = std::mem::take(&mut *self.snapshot.lock().await);
+        *members = std::mem::take(&mut *self.snapshot.members().lock().await);
+        Ok(())
+    }
+
+    async fn rollback(&self) -> TaskListResult<()> {
+        self.unlock();
+        Ok(())
+    }
+}
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
+    use std::future::Future;
+
+    use chrono::Days;
+
     use super::*;
+    use crate::model::query::{Order, TaskSort};
+    use crate::model::task::Priority;
 
-    #[actix_web::test]
-    async fn test_lists() {
-        let memory_state = TaskListMemoryState::new();
-        let task_lists_dao = MemoryTaskListDao::new(memory_state.clone());
+    pub fn task_in(name: &str) -> TaskIn {
+        TaskIn { name: name.to_owned(), ..Default::default() }
+    }
 
-        let res = task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await;
-        assert!(res.is_ok());
+    /// Checks shared by every backend, `task_lists_dao` should start empty.
+    pub async fn check_lists(task_lists_dao: &dyn TaskListDao) {
+        let added = task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
+        assert_eq!(added.core.name, "hello");
 
         let task_lists = task_lists_dao.get_all().await.unwrap();
         assert_eq!(task_lists.len(), 1);
-        let task = task_lists.get(0).unwrap();
+        let task = task_lists.first().unwrap();
+        assert_eq!(task.id, added.id);
         assert_eq!(task.core.name, "hello");
         let res = task_lists_dao.delete(task.id).await;
         assert!(res.is_ok());
         let task_lists = task_lists_dao.get_all().await.unwrap();
         assert_eq!(task_lists.len(), 0);
+        assert!(matches!(task_lists_dao.delete(task.id).await, Err(TaskListError::TaskListNotFound(_))));
     }
 
-    #[actix_web::test]
-    async fn test_list() {
-        let memory_state = TaskListMemoryState::new();
-        let task_lists_dao = MemoryTaskListDao::new(memory_state.clone());
-        let task_dao = MemoryTaskDao::new(memory_state.clone());
-
+    pub async fn check_list(task_lists_dao: &dyn TaskListDao, task_dao: &dyn TaskDao) {
         task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
         let task_lists = task_lists_dao.get_all().await.unwrap();
-        let task_list = task_lists.get(0).unwrap();
+        let task_list = task_lists.first().unwrap();
 
-        let res = task_dao.add(task_list.id, TaskIn { name: "test".to_owned() }).await;
-        assert!(res.is_ok());
+        let added = task_dao.add(task_list.id, task_in("test")).await.unwrap();
+        assert_eq!(added.core.name, "test");
+        assert!(!added.done);
+        task_dao.add(task_list.id, task_in("second")).await.unwrap();
 
         let res = task_lists_dao.get_by_id(task_list.id).await;
         assert!(res.is_ok());
         let task_list = res.unwrap();
-        assert_eq!(task_list.tasks.len(), 1);
-        let task = task_list.tasks.get(0).unwrap();
+        assert_eq!(task_list.tasks.len(), 2);
+        let task = task_list.tasks.first().unwrap();
+        assert_eq!(task.id, added.id);
         assert_eq!(task.core.name, "test");
-        assert_eq!(task.done, false);
+        assert!(!task.done);
 
         let res = task_dao.mark_as_done(task_list.id, task.id).await;
         assert!(res.is_ok());
         let task_list = task_lists_dao.get_by_id(task_list.id).await.unwrap();
-        let task = task_list.tasks.get(0).unwrap();
-        assert_eq!(task.done, true);
+        let task = task_list.tasks.first().unwrap();
+        assert!(task.done);
+
+        assert!(matches!(task_dao.mark_as_done(task_list.id, task.id).await,
+            Err(TaskListError::TaskAlreadyDone(_))));
+        assert!(matches!(task_dao.mark_as_done(task_list.id, Uuid::new_v4()).await,
+            Err(TaskListError::TaskNotFound(_))));
+        assert!(matches!(task_dao.add(Uuid::new_v4(), task_in("test")).await,
+            Err(TaskListError::TaskListNotFound(_))));
+        assert!(matches!(task_lists_dao.get_by_id(Uuid::new_v4()).await,
+            Err(TaskListError::TaskListNotFound(_))));
+    }
+
+    pub async fn check_lifecycle(task_lists_dao: &dyn TaskListDao, task_dao: &dyn TaskDao) {
+        let task_list = task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
+        let renamed = task_lists_dao.rename(task_list.id, TaskListIn { name: "renamed".to_owned() }).await.unwrap();
+        assert_eq!(renamed.core.name, "renamed");
+        assert_eq!(task_lists_dao.get_by_id(task_list.id).await.unwrap().core.name, "renamed");
+        assert!(matches!(task_lists_dao.rename(Uuid::new_v4(), TaskListIn { name: "test".to_owned() }).await,
+            Err(TaskListError::TaskListNotFound(_))));
+
+        let mut ids = vec![];
+        for name in ["first", "second", "third"] {
+            ids.push(task_dao.add(task_list.id, task_in(name)).await.unwrap().id);
+        }
+        let names = || async {
+            task_lists_dao.get_by_id(task_list.id).await.unwrap()
+                .tasks
+                .into_iter()
+                .map(|x| x.core.name)
+                .collect::<Vec<_>>()
+        };
+
+        let updated = task_dao.update(task_list.id, ids[0], task_in("updated")).await.unwrap();
+        assert_eq!(updated.id, ids[0]);
+        assert_eq!(updated.core.name, "updated");
+        assert_eq!(names().await, ["updated", "second", "third"]);
+        assert!(matches!(task_dao.update(task_list.id, Uuid::new_v4(), task_in("test")).await,
+            Err(TaskListError::TaskNotFound(_))));
+
+        assert!(matches!(task_dao.mark_as_undone(task_list.id, ids[1]).await, Err(TaskListError::TaskNotDone(_))));
+        assert!(task_dao.toggle(task_list.id, ids[1]).await.unwrap().0.done);
+        task_dao.mark_as_undone(task_list.id, ids[1]).await.unwrap();
+        assert!(task_dao.toggle(task_list.id, ids[1]).await.unwrap().0.done);
+        assert!(!task_dao.toggle(task_list.id, ids[1]).await.unwrap().0.done);
+        assert!(matches!(task_dao.toggle(Uuid::new_v4(), ids[1]).await, Err(TaskListError::TaskListNotFound(_))));
+
+        task_dao.move_to(task_list.id, ids[2], 0).await.unwrap();
+        assert_eq!(names().await, ["third", "updated", "second"]);
+        task_dao.move_to(task_list.id, ids[2], 1).await.unwrap();
+        assert_eq!(names().await, ["updated", "third", "second"]);
+        task_dao.move_to(task_list.id, ids[0], 10).await.unwrap();
+        assert_eq!(names().await, ["third", "second", "updated"]);
+        assert!(matches!(task_dao.move_to(task_list.id, Uuid::new_v4(), 0).await,
+            Err(TaskListError::TaskNotFound(_))));
+        task_dao.add(task_list.id, task_in("fourth")).await.unwrap();
+        assert_eq!(names().await, ["third", "second", "updated", "fourth"]);
+
+        task_dao.delete(task_list.id, ids[1]).await.unwrap();
+        assert_eq!(names().await, ["third", "updated", "fourth"]);
+        assert!(matches!(task_dao.delete(task_list.id, ids[1]).await, Err(TaskListError::TaskNotFound(_))));
+        assert!(matches!(task_dao.delete(Uuid::new_v4(), ids[0]).await, Err(TaskListError::TaskListNotFound(_))));
+    }
+
+    pub async fn check_details(task_lists_dao: &dyn TaskListDao, task_dao: &dyn TaskDao) {
+        let task_list = task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
+        let data = TaskIn {
+            name: "test".to_owned(),
+            description: Some("description".to_owned()),
+            due: NaiveDate::from_ymd_opt(2022, 12, 31),
+            priority: Priority::High,
+            tags: vec!["home".to_owned(), "urgent".to_owned()],
+            recurrence: Some("FREQ=MONTHLY;BYMONTHDAY=-1".to_owned()),
+        };
+        let added = task_dao.add(task_list.id, data).await.unwrap();
+        assert!(added.completed_at.is_none());
+
+        let task = task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks.remove(0);
+        assert_eq!(task.core.description.as_deref(), Some("description"));
+        assert_eq!(task.core.due, NaiveDate::from_ymd_opt(2022, 12, 31));
+        assert_eq!(task.core.priority, Priority::High);
+        assert_eq!(task.core.tags, ["home", "urgent"]);
+        assert_eq!(task.core.recurrence.as_deref(), Some("FREQ=MONTHLY;BYMONTHDAY=-1"));
+        assert_eq!(task.created_at, added.created_at);
+        assert!(task.is_overdue(&NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()));
+        assert!(!task.is_overdue(&NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()));
+
+        let updated = task_dao.update(task_list.id, task.id, task_in("updated")).await.unwrap();
+        assert_eq!(updated.core.description, None);
+        assert_eq!(updated.core.tags, Vec::<String>::new());
+        assert_eq!(updated.core.recurrence, None);
+        assert_eq!(updated.created_at, added.created_at);
+        assert!(updated.updated_at >= added.updated_at);
+
+        task_dao.mark_as_done(task_list.id, task.id).await.unwrap();
+        let task = task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks.remove(0);
+        assert!(task.completed_at.is_some());
+        assert!(!task.is_overdue(&NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()));
+        assert!(task_dao.toggle(task_list.id, task.id).await.unwrap().0.completed_at.is_none());
+        assert!(task_dao.toggle(task_list.id, task.id).await.unwrap().0.completed_at.is_some());
+    }
+
+    pub async fn check_query(task_lists_dao: &dyn TaskListDao, task_dao: &dyn TaskDao) {
+        for name in ["b", "a", "c"] {
+            task_lists_dao.add(TaskListIn { name: name.to_owned() }).await.unwrap();
+        }
+        let task_lists = task_lists_dao.get_all().await.unwrap();
+        assert_eq!(task_lists.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["a", "b", "c"]);
+
+        let page = task_lists_dao.find(TaskListQuery { limit: Some(2), ..Default::default() }).await.unwrap();
+        assert_eq!(page.items.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        let page = task_lists_dao.find(TaskListQuery { after: page.next, limit: Some(2), ..Default::default() })
+            .await.unwrap();
+        assert_eq!(page.items.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["c"]);
+        assert!(page.next.is_none());
+        let page = task_lists_dao.find(TaskListQuery { q: Some("B".to_owned()), ..Default::default() }).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].core.name, "b");
+
+        let id = task_lists[0].id;
+        let tasks = [
+            ("Write report", NaiveDate::from_ymd_opt(2022, 12, 10), Priority::High, "work"),
+            ("buy milk", None, Priority::Low, "home"),
+            ("Call mom", NaiveDate::from_ymd_opt(2022, 12, 1), Priority::Normal, "home"),
+        ];
+        for (name, due, priority, tag) in tasks {
+            let data = TaskIn { due, priority, tags: vec![tag.to_owned()], ..task_in(name) };
+            task_dao.add(id, data).await.unwrap();
+        }
+        let milk = task_dao.find(id, TaskQuery { q: Some("MILK".to_owned()), ..Default::default() }).await.unwrap();
+        task_dao.mark_as_done(id, milk.items[0].id).await.unwrap();
+
+        let names = |query: TaskQuery| async move {
+            let page = task_dao.find(id, query).await.unwrap();
+            page.items.into_iter().map(|x| x.core.name).collect::<Vec<_>>()
+        };
+        assert_eq!(names(TaskQuery::default()).await, ["Write report", "buy milk", "Call mom"]);
+        assert_eq!(names(TaskQuery { done: Some(true), ..Default::default() }).await, ["buy milk"]);
+        assert_eq!(names(TaskQuery { done: Some(false), ..Default::default() }).await, ["Write report", "Call mom"]);
+        assert_eq!(names(TaskQuery { tag: Some("home".to_owned()), ..Default::default() }).await,
+                   ["buy milk", "Call mom"]);
+        assert_eq!(names(TaskQuery { due_before: NaiveDate::from_ymd_opt(2022, 12, 5), ..Default::default() }).await,
+                   ["Call mom"]);
+        assert_eq!(names(TaskQuery { sort: TaskSort::Due, ..Default::default() }).await,
+                   ["Call mom", "Write report", "buy milk"]);
+        assert_eq!(names(TaskQuery { sort: TaskSort::Priority, order: Order::Desc, ..Default::default() }).await,
+                   ["Write report", "Call mom", "buy milk"]);
+        assert_eq!(names(TaskQuery { sort: TaskSort::Name, ..Default::default() }).await,
+                   ["Call mom", "Write report", "buy milk"]);
+
+        // Walking one task at a time gives the same order as a single page.
+        for sort in TaskSort::ALL {
+            for order in Order::ALL {
+                let mut walked = vec![];
+                let mut after = None;
+                loop {
+                    let page = task_dao.find(id, TaskQuery { sort, order, after, limit: Some(1), ..Default::default() })
+                        .await.unwrap();
+                    walked.extend(page.items.into_iter().map(|x| x.core.name));
+                    after = page.next;
+                    if after.is_none() {
+                        break;
+                    }
+                }
+                assert_eq!(walked, names(TaskQuery { sort, order, ..Default::default() }).await);
+            }
+        }
+
+        let page = task_dao.find(id, TaskQuery { sort: TaskSort::Due, limit: Some(1), ..Default::default() })
+            .await.unwrap();
+        assert!(matches!(task_dao.find(id, TaskQuery { after: page.next, ..Default::default() }).await,
+            Err(TaskListError::InvalidCursor)));
+        assert!(matches!(task_dao.find(Uuid::new_v4(), TaskQuery::default()).await,
+            Err(TaskListError::TaskListNotFound(_))));
+    }
+
+    pub async fn check_task_limit(task_lists_dao: &dyn TaskListDao, task_dao: &dyn TaskDao) {
+        let task_list = task_lists_dao.add(TaskListIn { name: "full".to_owned() }).await.unwrap();
+        for i in 0..MAX_TASKS {
+            task_dao.add(task_list.id, task_in(&i.to_string())).await.unwrap();
+        }
+        assert!(matches!(task_dao.add(task_list.id, task_in("one more")).await,
+            Err(TaskListError::TooManyTasks(_))));
+        let task = task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks.pop().unwrap();
+        task_dao.delete(task_list.id, task.id).await.unwrap();
+        task_dao.add(task_list.id, task_in("one more")).await.unwrap();
+
+        // A recurring task of a full list stays undone, as its next occurrence doesn't fit.
+        let first = task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks.remove(0);
+        let data = TaskIn { recurrence: Some("FREQ=DAILY".to_owned()), ..task_in("recurring") };
+        task_dao.update(task_list.id, first.id, data).await.unwrap();
+        assert!(matches!(task_dao.mark_as_done(task_list.id, first.id).await, Err(TaskListError::TooManyTasks(_))));
+        assert!(!task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks[0].done);
+    }
+
+    /// `user` is registered with `user_dao` and the user of the other DAOs.
+    pub async fn check_recurrence(user_dao: &dyn UserDao, user: user::Id, task_lists_dao: &dyn TaskListDao,
+                                  task_dao: &dyn TaskDao) {
+        // A day ahead of UTC for most of the day.
+        let time_zone: Tz = "Pacific/Kiritimati".parse().unwrap();
+        user_dao.update_settings(user, Settings { time_zone }).await.unwrap();
+        let today = today(time_zone);
+        let task_list = task_lists_dao.add(TaskListIn { name: "chores".to_owned() }).await.unwrap();
+
+        let data = TaskIn { recurrence: Some("FREQ=DAILY;COUNT=2".to_owned()), ..task_in("water plants") };
+        let task = task_dao.add(task_list.id, data).await.unwrap();
+        let next = task_dao.mark_as_done(task_list.id, task.id).await.unwrap().unwrap();
+        assert_eq!(next.core.name, "water plants");
+        assert_eq!(next.core.due, today.succ_opt());
+        assert_eq!(next.core.recurrence.as_deref(), Some("FREQ=DAILY;COUNT=1"));
+        assert!(!next.done);
+        let tasks = task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks;
+        assert_eq!(tasks.iter().map(|x| x.id).collect::<Vec<_>>(), [task.id, next.id]);
+        assert!(tasks[0].done && tasks[0].completed_at.is_some());
+        assert_eq!(tasks[0].core.recurrence, None);
+
+        // The last occurrence is followed by none, and doing the first one again adds none either.
+        let (last, none) = task_dao.toggle(task_list.id, next.id).await.unwrap();
+        assert!(last.done && none.is_none());
+        assert!(task_dao.toggle(task_list.id, task.id).await.unwrap().1.is_none());
+        assert!(task_dao.mark_as_done(task_list.id, task.id).await.unwrap().is_none());
+
+        let data = TaskIn { due: Some(today), recurrence: Some("FREQ=WEEKLY".to_owned()), ..task_in("bins") };
+        let task = task_dao.add(task_list.id, data).await.unwrap();
+        let (done, next) = task_dao.toggle(task_list.id, task.id).await.unwrap();
+        assert!(done.done);
+        assert_eq!(next.unwrap().core.due, today.checked_add_days(Days::new(7)));
+        assert_eq!(task_lists_dao.get_by_id(task_list.id).await.unwrap().tasks.len(), 4);
+    }
+
+    /// `begin` starts a unit of work for the user of `task_lists_dao`.
+    pub async fn check_unit_of_work<F, Fut>(task_lists_dao: &dyn TaskListDao, begin: F)
+        where F: Fn() -> Fut,
+              Fut: Future<Output=Box<dyn UnitOfWork>> {
+        let id = task_lists_dao.add(TaskListIn { name: "unit".to_owned() }).await.unwrap().id;
+
+        let unit = begin().await;
+        unit.tasks().add(id, task_in("committed")).await.unwrap();
+        unit.task_lists().rename(id, TaskListIn { name: "renamed".to_owned() }).await.unwrap();
+        assert_eq!(unit.task_lists().get_by_id(id).await.unwrap().tasks.len(), 1);
+        unit.commit().await.unwrap();
+        drop(unit);
+
+        let unit = begin().await;
+        unit.tasks().add(id, task_in("rolled back")).await.unwrap();
+        unit.rollback().await.unwrap();
+        drop(unit);
+
+        let unit = begin().await;
+        unit.tasks().add(id, task_in("dropped")).await.unwrap();
+        drop(unit);
+
+        let unit = begin().await;
+        let task_dao = unit.tasks();
+        let res = async {
+            task_dao.add(id, task_in("failed")).await?;
+            task_dao.delete(id, Uuid::new_v4()).await
+        }.await;
+        assert!(matches!(finish(&*unit, res).await, Err(TaskListError::TaskNotFound(_))));
+        drop(task_dao);
+        drop(unit);
+
+        let task_list = task_lists_dao.get_by_id(id).await.unwrap();
+        assert_eq!(task_list.core.name, "renamed");
+        assert_eq!(task_list.tasks.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["committed"]);
+    }
+
+    pub async fn check_users(user_dao: &dyn UserDao) {
+        let user = user_dao.add("alice".to_owned(), "hash".to_owned()).await.unwrap();
+        assert_eq!(user.username, "alice");
+        let record = user_dao.get_by_username("alice".to_owned()).await.unwrap().unwrap();
+        assert_eq!(record.user.id, user.id);
+        assert_eq!(record.password_hash, "hash");
+        assert!(user_dao.get_by_username("bob".to_owned()).await.unwrap().is_none());
+        assert!(matches!(user_dao.add("alice".to_owned(), "other".to_owned()).await,
+            Err(TaskListError::UsernameTaken(_))));
+
+        assert_eq!(user_dao.get_settings(user.id).await.unwrap().time_zone, Tz::UTC);
+        let time_zone = "Europe/Berlin".parse().unwrap();
+        user_dao.update_settings(user.id, Settings { time_zone }).await.unwrap();
+        assert_eq!(user_dao.get_settings(user.id).await.unwrap().time_zone, time_zone);
+        assert_eq!(user_dao.get_by_username("alice".to_owned()).await.unwrap().unwrap().settings.time_zone, time_zone);
+        assert_eq!(user_dao.get_settings(Uuid::new_v4()).await.unwrap().time_zone, Tz::UTC);
+        assert!(matches!(user_dao.update_settings(Uuid::new_v4(), Settings::default()).await,
+            Err(TaskListError::UserNotFound(_))));
+    }
+
+    /// `alice` and `bob` are DAOs of two different users.
+    pub async fn check_owners(alice: (&dyn TaskListDao, &dyn TaskDao), bob: (&dyn TaskListDao, &dyn TaskDao)) {
+        let task_list = alice.0.add(TaskListIn { name: "alice".to_owned() }).await.unwrap();
+        let task = alice.1.add(task_list.id, task_in("test")).await.unwrap();
+        bob.0.add(TaskListIn { name: "bob".to_owned() }).await.unwrap();
+
+        let names = bob.0.get_all().await.unwrap().into_iter().map(|x| x.core.name).collect::<Vec<_>>();
+        assert_eq!(names, ["bob"]);
+        assert_eq!(bob.0.find(TaskListQuery::default()).await.unwrap().items.len(), 1);
+        let forbidden = |res: TaskListResult<()>| matches!(res, Err(TaskListError::Forbidden(id)) if id == task_list.id);
+        assert!(forbidden(bob.0.get_by_id(task_list.id).await.map(|_| ())));
+        assert!(forbidden(bob.0.rename(task_list.id, TaskListIn { name: "mine".to_owned() }).await.map(|_| ())));
+        assert!(forbidden(bob.0.delete(task_list.id).await));
+        assert!(forbidden(bob.1.add(task_list.id, task_in("spam")).await.map(|_| ())));
+        assert!(forbidden(bob.1.update(task_list.id, task.id, task_in("spam")).await.map(|_| ())));
+        assert!(forbidden(bob.1.find(task_list.id, TaskQuery::default()).await.map(|_| ())));
+        assert!(forbidden(bob.1.mark_as_done(task_list.id, task.id).await.map(|_| ())));
+        assert!(forbidden(bob.1.toggle(task_list.id, task.id).await.map(|_| ())));
+        assert!(forbidden(bob.1.move_to(task_list.id, task.id, 0).await));
+        assert!(forbidden(bob.1.delete(task_list.id, task.id).await));
+
+        let task_list = alice.0.get_by_id(task_list.id).await.unwrap();
+        assert_eq!(task_list.core.name, "alice");
+        assert_eq!(task_list.tasks.len(), 1);
+        assert!(!task_list.tasks[0].done);
+    }
+
+    /// DAOs of one user.
+    pub type Access<'a> = (&'a dyn TaskListDao, &'a dyn TaskDao, &'a dyn MemberDao);
+
+    /// Sharing by `alice` with users named "bob" and "carol".
+    pub async fn check_members(alice: Access<'_>, bob: Access<'_>, carol: Access<'_>) {
+        let task_list = alice.0.add(TaskListIn { name: "shared".to_owned() }).await.unwrap();
+        let task = alice.1.add(task_list.id, task_in("test")).await.unwrap();
+        let invite = |username: &str, role| MemberIn { username: username.to_owned(), role };
+        let bob_member = alice.2.invite(task_list.id, invite("bob", Role::Editor)).await.unwrap();
+        assert!(!bob_member.accepted);
+        let bob_id = bob_member.user.id;
+        alice.2.invite(task_list.id, invite("carol", Role::Viewer)).await.unwrap();
+        assert!(matches!(alice.2.invite(task_list.id, invite("nobody", Role::Viewer)).await,
+            Err(TaskListError::UserNotFound(_))));
+        assert!(matches!(alice.2.invite(task_list.id, invite("bob", Role::Viewer)).await,
+            Err(TaskListError::AlreadyMember(_))));
+        assert!(matches!(alice.2.invite(task_list.id, invite("carol", Role::Owner)).await,
+            Err(TaskListError::OwnerInvited)));
+        assert_eq!(alice.2.get_role(task_list.id).await.unwrap(), Role::Owner);
+
+        let forbidden = |res: TaskListResult<()>| matches!(res, Err(TaskListError::Forbidden(id)) if id == task_list.id);
+        assert!(forbidden(bob.0.get_by_id(task_list.id).await.map(|_| ())));
+        let invitations = bob.2.get_invitations().await.unwrap();
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].task_list.core.name, "shared");
+        assert_eq!(invitations[0].role, Role::Editor);
+        bob.2.accept(task_list.id).await.unwrap();
+        assert!(matches!(bob.2.accept(task_list.id).await, Err(TaskListError::InvitationNotFound(_))));
+        assert!(bob.2.get_invitations().await.unwrap().is_empty());
+        carol.2.accept(task_list.id).await.unwrap();
+
+        let names = bob.0.get_all().await.unwrap().into_iter().map(|x| x.core.name).collect::<Vec<_>>();
+        assert_eq!(names, ["shared"]);
+        assert_eq!(bob.2.get_role(task_list.id).await.unwrap(), Role::Editor);
+        bob.1.add(task_list.id, task_in("by bob")).await.unwrap();
+        bob.1.mark_as_done(task_list.id, task.id).await.unwrap();
+        bob.0.rename(task_list.id, TaskListIn { name: "renamed".to_owned() }).await.unwrap();
+        assert!(forbidden(bob.0.delete(task_list.id).await));
+        assert!(forbidden(bob.2.invite(task_list.id, invite("carol", Role::Editor)).await.map(|_| ())));
+
+        assert_eq!(carol.2.get_role(task_list.id).await.unwrap(), Role::Viewer);
+        assert_eq!(carol.0.get_by_id(task_list.id).await.unwrap().tasks.len(), 2);
+        assert_eq!(carol.1.find(task_list.id, TaskQuery::default()).await.unwrap().items.len(), 2);
+        assert!(forbidden(carol.1.add(task_list.id, task_in("spam")).await.map(|_| ())));
+        assert!(forbidden(carol.1.mark_as_undone(task_list.id, task.id).await));
+        assert!(forbidden(carol.1.toggle(task_list.id, task.id).await.map(|_| ())));
+        assert!(forbidden(carol.1.delete(task_list.id, task.id).await));
+        assert!(forbidden(carol.0.rename(task_list.id, TaskListIn { name: "mine".to_owned() }).await.map(|_| ())));
+        let members = carol.2.get_members(task_list.id).await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(members.iter().all(|x| x.accepted));
+        assert!(forbidden(carol.2.revoke(task_list.id, bob_id).await));
+
+        alice.2.revoke(task_list.id, bob_id).await.unwrap();
+        assert!(matches!(alice.2.revoke(task_list.id, bob_id).await, Err(TaskListError::MemberNotFound(_))));
+        assert!(forbidden(bob.1.add(task_list.id, task_in("spam")).await.map(|_| ())));
+        assert!(bob.0.get_all().await.unwrap().is_empty());
+
+        let carol_id = members.iter().find(|x| x.user.username == "carol").unwrap().user.id;
+        carol.2.revoke(task_list.id, carol_id).await.unwrap();
+        assert!(forbidden(carol.0.get_by_id(task_list.id).await.map(|_| ())));
+
+        alice.2.invite(task_list.id, invite("bob", Role::Viewer)).await.unwrap();
+        alice.0.delete(task_list.id).await.unwrap();
+        assert!(bob.2.get_invitations().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_lists() {
+        let memory_state = TaskListMemoryState::new();
+        check_lists(&MemoryTaskListDao::new(memory_state, Uuid::new_v4())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_list() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_list(&MemoryTaskListDao::new(memory_state.clone(), owner), &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_lifecycle(&MemoryTaskListDao::new(memory_state.clone(), owner), &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_details() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_details(&MemoryTaskListDao::new(memory_state.clone(), owner), &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_query() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_query(&MemoryTaskListDao::new(memory_state.clone(), owner), &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_task_limit() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_task_limit(&MemoryTaskListDao::new(memory_state.clone(), owner),
+                         &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_unit_of_work() {
+        let memory_state = TaskListMemoryState::new();
+        let owner = Uuid::new_v4();
+        check_unit_of_work(&MemoryTaskListDao::new(memory_state.clone(), owner), || {
+            let memory_state = memory_state.clone();
+            async move {
+                let unit: Box<dyn UnitOfWork> = Box::new(MemoryUnitOfWork::begin(memory_state, owner).await);
+                unit
+            }
+        }).await;
+    }
+
+    #[actix_web::test]
+    async fn test_recurrence() {
+        let memory_state = TaskListMemoryState::new();
+        let user_dao = MemoryUserDao::new(memory_state.clone());
+        let owner = user_dao.add("alice".to_owned(), "hash".to_owned()).await.unwrap().id;
+        check_recurrence(&user_dao, owner, &MemoryTaskListDao::new(memory_state.clone(), owner),
+                         &MemoryTaskDao::new(memory_state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_users() {
+        check_users(&MemoryUserDao::new(TaskListMemoryState::new())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_owners() {
+        let memory_state = TaskListMemoryState::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        check_owners((&MemoryTaskListDao::new(memory_state.clone(), alice), &MemoryTaskDao::new(memory_state.clone(), alice)),
+                     (&MemoryTaskListDao::new(memory_state.clone(), bob), &MemoryTaskDao::new(memory_state, bob))).await;
+    }
+
+    #[actix_web::test]
+    async fn test_members() {
+        let memory_state = TaskListMemoryState::new();
+        let user_dao = MemoryUserDao::new(memory_state.clone());
+        let mut ids = vec![];
+        for username in ["alice", "bob", "carol"] {
+            ids.push(user_dao.add(username.to_owned(), "hash".to_owned()).await.unwrap().id);
+        }
+        let daos = ids.iter()
+            .map(|&id| (MemoryTaskListDao::new(memory_state.clone(), id), MemoryTaskDao::new(memory_state.clone(), id),
+                        MemoryMemberDao::new(memory_state.clone(), id)))
+            .collect::<Vec<_>>();
+        let [alice, bob, carol] = [0, 1, 2].map(|i| -> Access { (&daos[i].0, &daos[i].1, &daos[i].2) });
+        check_members(alice, bob, carol).await;
     }
 }
@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_web::web;
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TaskListResult;
+use crate::model::{task, task_list, user};
+use crate::model::member::{Invitation, Member, MemberIn, Role};
+use crate::model::query::{Page, TaskListQuery, TaskQuery};
+use crate::model::task::{TaskIn, TaskOut};
+use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+use crate::model::user::{Settings, User, UserRecord};
+
+use super::{MemberDao, MemoryMemberDao, MemoryTaskDao, MemoryTaskListDao, MemoryUnitOfWork, MemoryUserDao, TaskDao,
+            TaskListDao, TaskListMemoryState, UnitOfWork, UserDao};
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T, U, M> {
+    task_lists: Vec<T>,
+    users: Vec<U>,
+    /// Members of every shared list, missing before lists could be shared.
+    #[serde(default)]
+    members: M,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedSnapshot {
+    Current(Snapshot<TaskListWithTasks, UserRecord, HashMap<task_list::Id, Vec<Member>>>),
+    /// Only lists, saved before there were users.
+    Lists(Vec<TaskListWithTasks>),
+    /// Only lists, saved before tasks had timestamps.
+    Legacy(Vec<LegacyTaskList>),
+}
+
+/// Object with its fields nested in `core`, as saved before the API flattened them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybeNested<T> {
+    Nested { core: T },
+    Flat(T),
+}
+
+impl<T> MaybeNested<T> {
+    fn into_inner(self) -> T {
+        match self {
+            MaybeNested::Nested { core } | MaybeNested::Flat(core) => core,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyTask {
+    id: task::Id,
+    #[serde(flatten)]
+    core: MaybeNested<TaskIn>,
+    done: bool,
+}
+
+#[derive(Deserialize)]
+struct LegacyTaskList {
+    id: task_list::Id,
+    #[serde(flatten)]
+    core: MaybeNested<TaskListIn>,
+    tasks: Vec<LegacyTask>,
+}
+
+impl LegacyTaskList {
+    /// Timestamps are set to `now` like the SQL migrations do.
+    fn upgrade(self, now: DateTime<Utc>) -> TaskListWithTasks {
+        let tasks = self.tasks.into_iter()
+            .map(|task| {
+                let mut upgraded = TaskOut::new(task.id, task.core.into_inner(), now);
+                if task.done {
+                    upgraded.set_done(true, now);
+                }
+                upgraded
+            })
+            .collect();
+        TaskListWithTasks { id: self.id, owner: user::Id::nil(), core: self.core.into_inner(), tasks }
+    }
+}
+
+/// Memory state which is written to a JSON file after every change.
+#[derive(Clone)]
+pub struct FileState {
+    memory: TaskListMemoryState,
+    path: Arc<PathBuf>,
+    /// Held while a snapshot is written.
+    writing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl FileState {
+    /// Loads the snapshot from `path`, a missing file means there are no task lists yet.
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let snapshot = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Can't parse snapshot {}", path.display()))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => SavedSnapshot::Lists(vec![]),
+            Err(err) => return Err(Error::new(err).context(format!("Can't read snapshot {}", path.display()))),
+        };
+        let (task_lists, users, members) = match snapshot {
+            SavedSnapshot::Current(snapshot) => (snapshot.task_lists, snapshot.users, snapshot.members),
+            SavedSnapshot::Lists(task_lists) => (task_lists, vec![], HashMap::new()),
+            SavedSnapshot::Legacy(task_lists) => {
+                let now = Utc::now();
+                (task_lists.into_iter().map(|task_list| task_list.upgrade(now)).collect(), vec![], HashMap::new())
+            }
+        };
+        let memory = TaskListMemoryState::with_task_lists(task_lists, users, members);
+        Ok(Self { memory, path: Arc::new(path), writing: Arc::default() })
+    }
+
+    /// Saves the state once it was changed in memory. Failures are only logged, as the change can't be undone,
+    /// and the next change saves it again.
+    async fn snapshot(&self) {
+        if let Err(err) = self.write_snapshot().await {
+            eprintln!("can't save snapshot: {err:#}");
+        }
+    }
+
+    /// Serializes the whole state while holding its lock and writes it on the blocking thread pool.
+    /// The write lock is taken before the state is unlocked, so snapshots can't be reordered.
+    async fn write_snapshot(&self) -> anyhow::Result<()> {
+        let (data, writing) = {
+            let task_lists = self.memory.lock().await;
+            let members = self.memory.members().lock().await;
+            let users = self.memory.users().lock().await;
+            let snapshot = Snapshot {
+                task_lists: task_lists.values().collect(),
+                users: users.values().collect(),
+                members: &*members,
+            };
+            let data = serde_json::to_vec(&snapshot)?;
+            (data, self.writing.clone().lock_owned().await)
+        };
+        let path = self.path.clone();
+        web::block(move || {
+            let _writing = writing;
+            write_atomically(&path, &data)
+        }).await?
+    }
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it over `path`,
+/// so readers never see a torn snapshot.
+fn write_atomically(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // The rename itself is only durable once the directory is synced.
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path))
+        .and_then(|_| fs::File::open(dir)?.sync_all())
+        .with_context(|| format!("Can't write snapshot {}", path.display()))
+}
+
+/// `MemoryTaskListDao` which saves a snapshot after every change.
+/// If saving fails the change still succeeds, it stays in memory and is saved with the next one.
+pub struct FileTaskListDao {
+    state: FileState,
+    inner: MemoryTaskListDao,
+}
+
+impl FileTaskListDao {
+    pub fn new(state: FileState, user: user::Id) -> Self {
+        let inner = MemoryTaskListDao::new(state.memory.clone(), user);
+        Self { state, inner }
+    }
+}
+
+#[async_trait]
+impl TaskListDao for FileTaskListDao {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let task_list = self.inner.add(data).await?;
+        self.state.snapshot().await;
+        Ok(task_list)
+    }
+
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let task_list = self.inner.rename(id, data).await?;
+        self.state.snapshot().await;
+        Ok(task_list)
+    }
+
+    async fn delete(&self, id: task_list::Id) -> TaskListResult<()> {
+        self.inner.delete(id).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+
+    async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>> {
+        self.inner.get_all().await
+    }
+
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>> {
+        self.inner.find(query).await
+    }
+
+    async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks> {
+        self.inner.get_by_id(id).await
+    }
+}
+
+/// `MemoryTaskDao` which saves a snapshot after every change.
+pub struct FileTaskDao {
+    state: FileState,
+    inner: MemoryTaskDao,
+}
+
+impl FileTaskDao {
+    pub fn new(state: FileState, user: user::Id) -> Self {
+        let inner = MemoryTaskDao::new(state.memory.clone(), user);
+        Self { state, inner }
+    }
+}
+
+#[async_trait]
+impl TaskDao for FileTaskDao {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let task = self.inner.add(task_list_id, data).await?;
+        self.state.snapshot().await;
+        Ok(task)
+    }
+
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let task = self.inner.update(task_list_id, id, data).await?;
+        self.state.snapshot().await;
+        Ok(task)
+    }
+
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.inner.delete(task_list_id, id).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>> {
+        self.inner.find(task_list_id, query).await
+    }
+
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>> {
+        let next = self.inner.mark_as_done(task_list_id, id).await?;
+        self.state.snapshot().await;
+        Ok(next)
+    }
+
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.inner.mark_as_undone(task_list_id, id).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        let tasks = self.inner.toggle(task_list_id, id).await?;
+        self.state.snapshot().await;
+        Ok(tasks)
+    }
+
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()> {
+        self.inner.move_to(task_list_id, id, position).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+}
+
+/// `MemoryUserDao` which saves a snapshot after every change.
+pub struct FileUserDao {
+    state: FileState,
+    inner: MemoryUserDao,
+}
+
+impl FileUserDao {
+    pub fn new(state: FileState) -> Self {
+        let inner = MemoryUserDao::new(state.memory.clone());
+        Self { state, inner }
+    }
+}
+
+#[async_trait]
+impl UserDao for FileUserDao {
+    async fn add(&self, username: String, password_hash: String) -> TaskListResult<User> {
+        let user = self.inner.add(username, password_hash).await?;
+        self.state.snapshot().await;
+        Ok(user)
+    }
+
+    async fn get_by_username(&self, username: String) -> TaskListResult<Option<UserRecord>> {
+        self.inner.get_by_username(username).await
+    }
+
+    async fn get_settings(&self, id: user::Id) -> TaskListResult<Settings> {
+        self.inner.get_settings(id).await
+    }
+
+    async fn update_settings(&self, id: user::Id, settings: Settings) -> TaskListResult<Settings> {
+        let settings = self.inner.update_settings(id, settings).await?;
+        self.state.snapshot().await;
+        Ok(settings)
+    }
+}
+
+/// `MemoryMemberDao` which saves a snapshot after every change.
+pub struct FileMemberDao {
+    state: FileState,
+    inner: MemoryMemberDao,
+}
+
+impl FileMemberDao {
+    pub fn new(state: FileState, user: user::Id) -> Self {
+        let inner = MemoryMemberDao::new(state.memory.clone(), user);
+        Self { state, inner }
+    }
+}
+
+#[async_trait]
+impl MemberDao for FileMemberDao {
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role> {
+        self.inner.get_role(task_list_id).await
+    }
+
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>> {
+        self.inner.get_members(task_list_id).await
+    }
+
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member> {
+        let member = self.inner.invite(task_list_id, data).await?;
+        self.state.snapshot().await;
+        Ok(member)
+    }
+
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()> {
+        self.inner.revoke(task_list_id, user_id).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>> {
+        self.inner.get_invitations().await
+    }
+
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()> {
+        self.inner.accept(task_list_id).await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+}
+
+/// `MemoryUnitOfWork` which saves a snapshot once it is committed.
+pub struct FileUnitOfWork {
+    state: FileState,
+    inner: MemoryUnitOfWork,
+}
+
+impl FileUnitOfWork {
+    pub async fn begin(state: FileState, user: user::Id) -> Self {
+        let inner = MemoryUnitOfWork::begin(state.memory.clone(), user).await;
+        Self { state, inner }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for FileUnitOfWork {
+    fn task_lists(&self) -> Box<dyn TaskListDao> {
+        self.inner.task_lists()
+    }
+
+    fn tasks(&self) -> Box<dyn TaskDao> {
+        self.inner.tasks()
+    }
+
+    async fn commit(&self) -> TaskListResult<()> {
+        self.inner.commit().await?;
+        self.state.snapshot().await;
+        Ok(())
+    }
+
+    async fn rollback(&self) -> TaskListResult<()> {
+        self.inner.rollback().await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::dao::tests::{check_details, check_lifecycle, check_list, check_lists, check_members, check_owners,
+                            check_query, check_recurrence, check_unit_of_work, check_users, task_in, Access};
+
+    /// Snapshot path in the temp directory, removed on drop.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("task4_{}.json", Uuid::new_v4().simple())))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_lists() {
+        let path = TempPath::new();
+        check_lists(&FileTaskListDao::new(FileState::open(path.0.clone()).unwrap(), Uuid::new_v4())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_list() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        check_list(&FileTaskListDao::new(state.clone(), owner), &FileTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_details() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        check_details(&FileTaskListDao::new(state.clone(), owner), &FileTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        check_lifecycle(&FileTaskListDao::new(state.clone(), owner), &FileTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_query() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        check_query(&FileTaskListDao::new(state.clone(), owner), &FileTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_recurrence() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let user_dao = FileUserDao::new(state.clone());
+        let owner = user_dao.add("alice".to_owned(), "hash".to_owned()).await.unwrap().id;
+        check_recurrence(&user_dao, owner, &FileTaskListDao::new(state.clone(), owner), &FileTaskDao::new(state, owner)).await;
+
+        let state = FileState::open(path.0.clone()).unwrap();
+        let user = FileUserDao::new(state).get_by_username("alice".to_owned()).await.unwrap().unwrap();
+        assert_eq!(user.settings.time_zone.name(), "Pacific/Kiritimati");
+    }
+
+    #[actix_web::test]
+    async fn test_users() {
+        let path = TempPath::new();
+        check_users(&FileUserDao::new(FileState::open(path.0.clone()).unwrap())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_owners() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        check_owners((&FileTaskListDao::new(state.clone(), alice), &FileTaskDao::new(state.clone(), alice)),
+                     (&FileTaskListDao::new(state.clone(), bob), &FileTaskDao::new(state, bob))).await;
+    }
+
+    #[actix_web::test]
+    async fn test_members() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let user_dao = FileUserDao::new(state.clone());
+        let mut ids = vec![];
+        for username in ["alice", "bob", "carol"] {
+            ids.push(user_dao.add(username.to_owned(), "hash".to_owned()).await.unwrap().id);
+        }
+        let daos = ids.iter()
+            .map(|&id| (FileTaskListDao::new(state.clone(), id), FileTaskDao::new(state.clone(), id),
+                        FileMemberDao::new(state.clone(), id)))
+            .collect::<Vec<_>>();
+        let [alice, bob, carol] = [0, 1, 2].map(|i| -> Access { (&daos[i].0, &daos[i].1, &daos[i].2) });
+        check_members(alice, bob, carol).await;
+    }
+
+    #[actix_web::test]
+    async fn test_unit_of_work() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        check_unit_of_work(&FileTaskListDao::new(state.clone(), owner), || {
+            let state = state.clone();
+            async move {
+                let unit: Box<dyn UnitOfWork> = Box::new(FileUnitOfWork::begin(state, owner).await);
+                unit
+            }
+        }).await;
+
+        let state = FileState::open(path.0.clone()).unwrap();
+        let task_list = FileTaskListDao::new(state, owner).get_all().await.unwrap().remove(0);
+        assert_eq!(task_list.core.name, "renamed");
+    }
+
+    #[actix_web::test]
+    async fn test_reopen() {
+        let path = TempPath::new();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let owner = FileUserDao::new(state.clone()).add("alice".to_owned(), "hash".to_owned()).await.unwrap().id;
+        FileTaskListDao::new(state.clone(), owner).add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
+        let id = FileTaskListDao::new(state.clone(), owner).get_all().await.unwrap()[0].id;
+        FileTaskDao::new(state.clone(), owner).add(id, task_in("test")).await.unwrap();
+        FileUserDao::new(state.clone()).add("bob".to_owned(), "hash".to_owned()).await.unwrap();
+        FileMemberDao::new(state, owner)
+            .invite(id, MemberIn { username: "bob".to_owned(), role: Role::Viewer }).await.unwrap();
+
+        let state = FileState::open(path.0.clone()).unwrap();
+        let task_list = FileTaskListDao::new(state.clone(), owner).get_by_id(id).await.unwrap();
+        assert_eq!(task_list.core.name, "hello");
+        assert_eq!(task_list.tasks.len(), 1);
+        assert_eq!(task_list.tasks[0].core.name, "test");
+        let members = FileMemberDao::new(state.clone(), owner).get_members(id).await.unwrap();
+        assert_eq!(members[0].user.username, "bob");
+        let user = FileUserDao::new(state).get_by_username("alice".to_owned()).await.unwrap().unwrap();
+        assert_eq!(user.user.id, owner);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_save() {
+        let dir = TempPath::new();
+        let path = dir.0.join("lists.json");
+        let state = FileState::open(path.clone()).unwrap();
+        let owner = Uuid::new_v4();
+        let task_list_dao = FileTaskListDao::new(state.clone(), owner);
+        // The directory is missing, so the change is only kept in memory.
+        task_list_dao.add(TaskListIn { name: "first".to_owned() }).await.unwrap();
+        assert!(!path.exists());
+
+        fs::create_dir(&dir.0).unwrap();
+        task_list_dao.add(TaskListIn { name: "second".to_owned() }).await.unwrap();
+        let task_lists = FileTaskListDao::new(FileState::open(path.clone()).unwrap(), owner).get_all().await.unwrap();
+        assert_eq!(task_lists.len(), 2);
+        fs::remove_dir_all(&dir.0).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_open_lists_snapshot() {
+        let path = TempPath::new();
+        fs::write(&path.0, r#"[{"id": "67e55044-10b1-426f-9247-bb680e5fe0c8", "name": "hello", "tasks": []}]"#).unwrap();
+        let state = FileState::open(path.0.clone()).unwrap();
+        let task_list = FileTaskListDao::new(state, Uuid::nil())
+            .get_by_id("67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap()).await.unwrap();
+        assert_eq!(task_list.core.name, "hello");
+    }
+
+    #[actix_web::test]
+    async fn test_open_legacy_snapshots() {
+        let nested = r#"[{"id": "67e55044-10b1-426f-9247-bb680e5fe0c8", "core": {"name": "hello"},
+            "tasks": [{"id": "936da01f-9abd-4d9d-80c7-02af85c822a8", "core": {"name": "test"}, "done": true}]}]"#;
+        let flat = r#"[{"id": "67e55044-10b1-426f-9247-bb680e5fe0c8", "name": "hello",
+            "tasks": [{"id": "936da01f-9abd-4d9d-80c7-02af85c822a8", "name": "test", "done": true}]}]"#;
+        for snapshot in [nested, flat] {
+            let path = TempPath::new();
+            fs::write(&path.0, snapshot).unwrap();
+            let state = FileState::open(path.0.clone()).unwrap();
+            let task_list = FileTaskListDao::new(state, Uuid::nil())
+                .get_by_id("67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap()).await.unwrap();
+            assert_eq!(task_list.core.name, "hello");
+            assert_eq!(task_list.tasks[0].core.name, "test");
+            assert!(task_list.tasks[0].done);
+            assert!(task_list.tasks[0].completed_at.is_some());
+        }
+    }
+}
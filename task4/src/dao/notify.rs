@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+
+use crate::error::TaskListResult;
+use crate::events::{Change, ChangeBus};
+use crate::model::{task, task_list, user};
+use crate::model::member::{Invitation, Member, MemberIn, Role};
+use crate::model::query::{Page, TaskListQuery, TaskQuery};
+use crate::model::task::{TaskIn, TaskOut};
+use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+
+use super::{MemberDao, TaskDao, TaskListDao, UnitOfWork};
+
+/// DAO of any backend which publishes its changes to the `ChangeBus` once they are made.
+pub struct NotifyingTaskListDao {
+    bus: ChangeBus,
+    inner: Box<dyn TaskListDao>,
+}
+
+impl NotifyingTaskListDao {
+    pub fn new(bus: ChangeBus, inner: Box<dyn TaskListDao>) -> Self {
+        Self { bus, inner }
+    }
+}
+
+#[async_trait]
+impl TaskListDao for NotifyingTaskListDao {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        self.inner.add(data).await
+    }
+
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let task_list = self.inner.rename(id, data).await?;
+        self.bus.publish(id, Change::ListRenamed { task_list: task_list.clone() });
+        Ok(task_list)
+    }
+
+    async fn delete(&self, id: task_list::Id) -> TaskListResult<()> {
+        self.inner.delete(id).await?;
+        self.bus.publish(id, Change::ListDeleted);
+        Ok(())
+    }
+
+    async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>> {
+        self.inner.get_all().await
+    }
+
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>> {
+        self.inner.find(query).await
+    }
+
+    async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks> {
+        self.inner.get_by_id(id).await
+    }
+}
+
+/// `NotifyingTaskListDao` for tasks.
+pub struct NotifyingTaskDao {
+    bus: ChangeBus,
+    inner: Box<dyn TaskDao>,
+}
+
+impl NotifyingTaskDao {
+    pub fn new(bus: ChangeBus, inner: Box<dyn TaskDao>) -> Self {
+        Self { bus, inner }
+    }
+}
+
+#[async_trait]
+impl TaskDao for NotifyingTaskDao {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let task = self.inner.add(task_list_id, data).await?;
+        self.bus.publish(task_list_id, Change::TaskAdded { task: task.clone() });
+        Ok(task)
+    }
+
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        let task = self.inner.update(task_list_id, id, data).await?;
+        self.bus.publish(task_list_id, Change::TaskUpdated { task: task.clone() });
+        Ok(task)
+    }
+
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.inner.delete(task_list_id, id).await?;
+        self.bus.publish(task_list_id, Change::TaskDeleted { id });
+        Ok(())
+    }
+
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>> {
+        self.inner.find(task_list_id, query).await
+    }
+
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>> {
+        let next = self.inner.mark_as_done(task_list_id, id).await?;
+        self.bus.publish(task_list_id, Change::TaskDone { id });
+        if let Some(task) = &next {
+            self.bus.publish(task_list_id, Change::TaskAdded { task: task.clone() });
+        }
+        Ok(next)
+    }
+
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.inner.mark_as_undone(task_list_id, id).await?;
+        self.bus.publish(task_list_id, Change::TaskUndone { id });
+        Ok(())
+    }
+
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        let (task, next) = self.inner.toggle(task_list_id, id).await?;
+        self.bus.publish(task_list_id, Change::TaskUpdated { task: task.clone() });
+        if let Some(task) = &next {
+            self.bus.publish(task_list_id, Change::TaskAdded { task: task.clone() });
+        }
+        Ok((task, next))
+    }
+
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()> {
+        self.inner.move_to(task_list_id, id, position).await?;
+        self.bus.publish(task_list_id, Change::TaskMoved { id, position });
+        Ok(())
+    }
+}
+
+/// `MemberDao` of any backend which ends the subscriptions of revoked members.
+pub struct NotifyingMemberDao {
+    bus: ChangeBus,
+    inner: Box<dyn MemberDao>,
+}
+
+impl NotifyingMemberDao {
+    pub fn new(bus: ChangeBus, inner: Box<dyn MemberDao>) -> Self {
+        Self { bus, inner }
+    }
+}
+
+#[async_trait]
+impl MemberDao for NotifyingMemberDao {
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role> {
+        self.inner.get_role(task_list_id).await
+    }
+
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>> {
+        self.inner.get_members(task_list_id).await
+    }
+
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member> {
+        self.inner.invite(task_list_id, data).await
+    }
+
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()> {
+        self.inner.revoke(task_list_id, user_id).await?;
+        self.bus.end_access(task_list_id, user_id);
+        Ok(())
+    }
+
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>> {
+        self.inner.get_invitations().await
+    }
+
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()> {
+        self.inner.accept(task_list_id).await
+    }
+}
+
+/// Unit of work of any backend which publishes the changes of its DAOs once it is committed.
+pub struct NotifyingUnitOfWork {
+    bus: ChangeBus,
+    inner: Box<dyn UnitOfWork>,
+}
+
+impl NotifyingUnitOfWork {
+    pub fn new(bus: ChangeBus, inner: Box<dyn UnitOfWork>) -> Self {
+        Self { bus: bus.deferred(), inner }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for NotifyingUnitOfWork {
+    fn task_lists(&self) -> Box<dyn TaskListDao> {
+        Box::new(NotifyingTaskListDao::new(self.bus.clone(), self.inner.task_lists()))
+    }
+
+    fn tasks(&self) -> Box<dyn TaskDao> {
+        Box::new(NotifyingTaskDao::new(self.bus.clone(), self.inner.tasks()))
+    }
+
+    async fn commit(&self) -> TaskListResult<()> {
+        self.inner.commit().await?;
+        self.bus.flush();
+        Ok(())
+    }
+
+    async fn rollback(&self) -> TaskListResult<()> {
+        self.inner.rollback().await
+    }
+}
@@ -0,0 +1,962 @@
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{NoTls, Row};
+use uuid::Uuid;
+
+use crate::error::{TaskListError, TaskListResult};
+use crate::model::{task, task_list, user};
+use crate::model::member::{check_role, Invitation, Member, MemberIn, Role};
+use crate::model::query::{Order, Page, SortKey, TaskListQuery, TaskQuery, TaskSort, NO_DUE};
+use crate::model::recurrence::{next_task, today};
+use crate::model::task::{Priority, TaskIn, TaskOut};
+use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+use crate::model::user::{Settings, User, UserRecord};
+use crate::model::validation::MAX_TASKS;
+
+use super::{MemberDao, TaskDao, TaskListDao, UnitOfWork, UserDao};
+
+const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("../../migrations/postgres/0001_init.sql")),
+    (2, include_str!("../../migrations/postgres/0002_task_details.sql")),
+    (3, include_str!("../../migrations/postgres/0003_users.sql")),
+    (4, include_str!("../../migrations/postgres/0004_members.sql")),
+    (5, include_str!("../../migrations/postgres/0005_recurrence.sql")),
+];
+
+const TASK_COLUMNS: &str = "id, name, description, due, priority, tags, recurrence, done, created_at, updated_at, completed_at";
+
+impl From<tokio_postgres::Error> for TaskListError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        TaskListError::Unknown(err.into())
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for TaskListError {
+    fn from(err: deadpool_postgres::PoolError) -> Self {
+        TaskListError::Unknown(err.into())
+    }
+}
+
+pub fn create_pool(config: tokio_postgres::Config) -> anyhow::Result<Pool> {
+    let manager = Manager::from_config(config, NoTls, ManagerConfig {
+        recycling_method: RecyclingMethod::Fast,
+    });
+    Ok(Pool::builder(manager).build()?)
+}
+
+/// Key of the advisory lock `migrate` holds, so instances started at once apply migrations one after another.
+const MIGRATION_LOCK: i64 = 0x74_6173_6b34;
+
+/// Applies migrations which are not applied yet, safe to run from several instances at once.
+pub async fn migrate(pool: &Pool) -> anyhow::Result<()> {
+    let mut client = pool.get().await?;
+    client.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK]).await?;
+    let result = apply_migrations(&mut client).await;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK]).await?;
+    result
+}
+
+async fn apply_migrations(client: &mut Object) -> anyhow::Result<()> {
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY)").await?;
+    for (version, sql) in MIGRATIONS {
+        let transaction = client.transaction().await?;
+        transaction.batch_execute("LOCK TABLE schema_migrations IN EXCLUSIVE MODE").await?;
+        let applied = transaction
+            .query_opt("SELECT 1 FROM schema_migrations WHERE version = $1", &[version])
+            .await?
+            .is_some();
+        if !applied {
+            transaction.batch_execute(sql).await?;
+            transaction.execute("INSERT INTO schema_migrations (version) VALUES ($1)", &[version]).await?;
+        }
+        transaction.commit().await?;
+    }
+    Ok(())
+}
+
+fn task_from_row(row: &Row) -> TaskOut {
+    TaskOut {
+        id: row.get("id"),
+        core: TaskIn {
+            name: row.get("name"),
+            description: row.get("description"),
+            due: row.get("due"),
+            priority: Priority::from_i16(row.get("priority")),
+            tags: row.get("tags"),
+            recurrence: row.get("recurrence"),
+        },
+        done: row.get("done"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        completed_at: row.get("completed_at"),
+    }
+}
+
+/// Time zone of a row with a `time_zone` column, UTC if it is missing or unknown.
+fn time_zone_from_row(row: &Row) -> Tz {
+    row.get::<_, Option<&str>>("time_zone").and_then(|x| x.parse().ok()).unwrap_or_default()
+}
+
+/// Adds a query parameter and returns its placeholder.
+fn param<'a>(params: &mut Vec<&'a (dyn ToSql + Sync)>, value: &'a (dyn ToSql + Sync)) -> String {
+    params.push(value);
+    format!("${}", params.len())
+}
+
+fn sort_column(sort: TaskSort) -> &'static str {
+    match sort {
+        TaskSort::Position => "position",
+        TaskSort::Created => "created_at",
+        TaskSort::Due => "due",
+        TaskSort::Priority => "priority",
+        TaskSort::Name => "name",
+    }
+}
+
+/// Expression tasks are sorted by, applied to a column or a parameter alike.
+/// Names are compared bytewise like in the other backends.
+fn sort_key(sort: TaskSort, value: &str) -> String {
+    match sort {
+        TaskSort::Due => format!("COALESCE({value}, DATE '{NO_DUE}')"),
+        TaskSort::Name => format!("{value} COLLATE \"C\""),
+        TaskSort::Position | TaskSort::Created | TaskSort::Priority => value.to_owned(),
+    }
+}
+
+fn key_to_sql(key: &SortKey) -> &(dyn ToSql + Sync) {
+    match key {
+        SortKey::Position(position) => position,
+        SortKey::Created(created_at) => created_at,
+        SortKey::Due(due) => due,
+        SortKey::Priority(priority) => priority,
+        SortKey::Name(name) => name,
+    }
+}
+
+/// Role of the user, `None` for strangers and invitations which are not accepted.
+async fn role_of(client: &Object, user: user::Id, task_list_id: task_list::Id) -> TaskListResult<Option<Role>> {
+    let row = client
+        .query_opt("SELECT owner_id, (SELECT role FROM task_list_members \
+                                      WHERE task_list_id = task_lists.id AND user_id = $2 AND accepted) AS role \
+                    FROM task_lists WHERE id = $1",
+                   &[&task_list_id, &user])
+        .await?
+        .ok_or(TaskListError::TaskListNotFound(task_list_id))?;
+    if row.get::<_, Option<user::Id>>("owner_id") == Some(user) {
+        return Ok(Some(Role::Owner));
+    }
+    Ok(row.get::<_, Option<&str>>("role").map(Role::parse))
+}
+
+async fn check_access(client: &Object, user: user::Id, task_list_id: task_list::Id, role: Role) -> TaskListResult<Role> {
+    check_role(task_list_id, role_of(client, user, task_list_id).await?, role)
+}
+
+/// Lists the user owns or is a member of.
+fn accessible<'a>(params: &mut Vec<&'a (dyn ToSql + Sync)>, user: &'a user::Id) -> String {
+    let user = param(params, user);
+    format!("(owner_id = {user} OR id IN (SELECT task_list_id FROM task_list_members WHERE user_id = {user} AND accepted))")
+}
+
+fn task_list_from_row(row: &Row) -> TaskListOut {
+    TaskListOut { id: row.get("id"), owner: row.get("owner_id"), core: TaskListIn { name: row.get("name") } }
+}
+
+/// Client of the DAOs of a request, so a request takes only one from the pool.
+/// A unit of work opens its transaction on it.
+pub struct SharedClient {
+    /// Taken only on drop.
+    client: Option<Object>,
+    /// A unit of work has a transaction open.
+    in_transaction: AtomicBool,
+}
+
+impl SharedClient {
+    pub fn new(client: Object) -> Arc<Self> {
+        Arc::new(Self { client: Some(client), in_transaction: AtomicBool::new(false) })
+    }
+}
+
+impl Deref for SharedClient {
+    type Target = Object;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl Drop for SharedClient {
+    fn drop(&mut self) {
+        // The server rolls back the transaction of a closed connection, so such a client never goes back to the pool.
+        if *self.in_transaction.get_mut() {
+            if let Some(client) = self.client.take() {
+                drop(Object::take(client));
+            }
+        }
+    }
+}
+
+pub struct PostgresTaskListDao {
+    client: Arc<SharedClient>,
+    user: user::Id,
+}
+
+impl PostgresTaskListDao {
+    pub fn new(client: Arc<SharedClient>, user: user::Id) -> Self {
+        Self { client, user }
+    }
+}
+
+#[async_trait]
+impl TaskListDao for PostgresTaskListDao {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let id = Uuid::new_v4();
+        self.client
+            .execute("INSERT INTO task_lists (id, name, owner_id) VALUES ($1, $2, $3)", &[&id, &data.name, &self.user])
+            .await?;
+        Ok(TaskListOut { id, owner: self.user, core: data })
+    }
+
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        check_access(&self.client, self.user, id, Role::Editor).await?;
+        self.client
+            .query_opt("UPDATE task_lists SET name = $2 WHERE id = $1 RETURNING id, owner_id, name", &[&id, &data.name])
+            .await?
+            .map(|row| task_list_from_row(&row))
+            .ok_or(TaskListError::TaskListNotFound(id))
+    }
+
+    async fn delete(&self, id: task_list::Id) -> TaskListResult<()> {
+        check_access(&self.client, self.user, id, Role::Owner).await?;
+        let deleted = self.client
+            .execute("DELETE FROM task_lists WHERE id = $1", &[&id])
+            .await?;
+        if deleted == 0 {
+            return Err(TaskListError::TaskListNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>> {
+        let mut params = vec![];
+        let sql = format!("SELECT id, owner_id, name FROM task_lists WHERE {} ORDER BY name COLLATE \"C\", id",
+                          accessible(&mut params, &self.user));
+        Ok(self.client
+            .query(&sql, &params)
+            .await?
+            .iter()
+            .map(task_list_from_row)
+            .collect())
+    }
+
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>> {
+        let after = query.check()?;
+        let mut params = vec![];
+        let mut conditions = vec![accessible(&mut params, &self.user)];
+        if let Some(q) = &query.q {
+            conditions.push(format!("strpos(lower(name), lower({})) > 0", param(&mut params, q)));
+        }
+        if let Some((name, id)) = &after {
+            conditions.push(format!("(name COLLATE \"C\", id) > ({}, {})",
+                                    param(&mut params, name), param(&mut params, id)));
+        }
+        let limit = query.limit.map(|limit| limit as i64 + 1);
+        let sql = format!("SELECT id, owner_id, name FROM task_lists WHERE {} ORDER BY name COLLATE \"C\", id LIMIT {}",
+                          conditions.join(" AND "), param(&mut params, &limit));
+        let task_lists = self.client
+            .query(&sql, &params)
+            .await?
+            .iter()
+            .map(task_list_from_row)
+            .collect();
+        Ok(query.page(task_lists))
+    }
+
+    async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks> {
+        check_access(&self.client, self.user, id, Role::Viewer).await?;
+        let task_list = self.client
+            .query_opt("SELECT id, owner_id, name FROM task_lists WHERE id = $1", &[&id])
+            .await?
+            .ok_or_else(|| TaskListError::TaskListNotFound(id))?;
+        let tasks = self.client
+            .query(&format!("SELECT {TASK_COLUMNS} FROM tasks WHERE task_list_id = $1 ORDER BY position"), &[&id])
+            .await?
+            .iter()
+            .map(task_from_row)
+            .collect();
+        Ok(TaskListWithTasks {
+            id: task_list.get("id"),
+            owner: task_list.get("owner_id"),
+            core: TaskListIn { name: task_list.get("name") },
+            tasks,
+        })
+    }
+}
+
+pub struct PostgresTaskDao {
+    client: Arc<SharedClient>,
+    user: user::Id,
+}
+
+impl PostgresTaskDao {
+    pub fn new(client: Arc<SharedClient>, user: user::Id) -> Self {
+        Self { client, user }
+    }
+
+    async fn set_done(&self, task_list_id: task_list::Id, id: task::Id, done: bool) -> TaskListResult<()> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        let updated = self.client
+            .execute("UPDATE tasks SET done = $3, completed_at = CASE WHEN $3 THEN now() END, updated_at = now() \
+                      WHERE id = $1 AND task_list_id = $2 AND done <> $3",
+                     &[&id, &task_list_id, &done])
+            .await?;
+        if updated > 0 {
+            return Ok(());
+        }
+        let task_exists = self.client
+            .query_opt("SELECT 1 FROM tasks WHERE id = $1 AND task_list_id = $2", &[&id, &task_list_id])
+            .await?
+            .is_some();
+        match (task_exists, done) {
+            (true, true) => Err(TaskListError::TaskAlreadyDone(id)),
+            (true, false) => Err(TaskListError::TaskNotDone(id)),
+            (false, _) => Err(TaskListError::TaskNotFound(id)),
+        }
+    }
+
+    /// Marks the task done and adds the occurrence which follows it in one statement, see `TaskDao::mark_as_done`.
+    async fn complete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        let row = self.client
+            .query_opt(&format!("SELECT {TASK_COLUMNS}, (SELECT time_zone FROM users WHERE id = $3) AS time_zone \
+                                 FROM tasks WHERE id = $1 AND task_list_id = $2"),
+                       &[&id, &task_list_id, &self.user])
+            .await?
+            .ok_or(TaskListError::TaskNotFound(id))?;
+        if row.get::<_, bool>("done") {
+            return Err(TaskListError::TaskAlreadyDone(id));
+        }
+        let next = next_task(&task_from_row(&row).core, today(time_zone_from_row(&row)));
+        // Nothing changes if the next task doesn't fit into the list or the task was done meanwhile.
+        let rows = self.client
+            .query(&format!("WITH done AS (UPDATE tasks SET done = TRUE, completed_at = now(), updated_at = now(), \
+                                                            recurrence = NULL \
+                                           WHERE id = $1 AND task_list_id = $2 AND NOT done \
+                                             AND (NOT $3 OR (SELECT COUNT(*) FROM tasks WHERE task_list_id = $2) < $4) \
+                                           RETURNING {TASK_COLUMNS}), \
+                                  next AS (INSERT INTO tasks (id, task_list_id, name, description, due, priority, tags, \
+                                                              recurrence, position) \
+                                           SELECT $5, $2, $6, $7, $8, $9, $10, $11, \
+                                                  (SELECT COALESCE(MAX(position) + 1, 0) FROM tasks WHERE task_list_id = $2) \
+                                           FROM done WHERE $3 \
+                                           RETURNING {TASK_COLUMNS}) \
+                             SELECT FALSE AS is_next, done.* FROM done UNION ALL SELECT TRUE, next.* FROM next"),
+                   &[&id, &task_list_id, &next.is_some(), &(MAX_TASKS as i64), &Uuid::new_v4(),
+                     &next.as_ref().map(|x| &x.name), &next.as_ref().and_then(|x| x.description.as_ref()),
+                     &next.as_ref().and_then(|x| x.due), &next.as_ref().map(|x| x.priority.to_i16()),
+                     &next.as_ref().map(|x| &x.tags), &next.as_ref().and_then(|x| x.recurrence.as_ref())])
+            .await?;
+        let done = rows.iter().find(|row| !row.get::<_, bool>("is_next")).map(task_from_row);
+        let next = rows.iter().find(|row| row.get::<_, bool>("is_next")).map(task_from_row);
+        if let Some(task) = done {
+            return Ok((task, next));
+        }
+        let row = self.client
+            .query_opt("SELECT done FROM tasks WHERE id = $1 AND task_list_id = $2", &[&id, &task_list_id])
+            .await?;
+        match row.map(|row| row.get::<_, bool>("done")) {
+            None => Err(TaskListError::TaskNotFound(id)),
+            Some(true) => Err(TaskListError::TaskAlreadyDone(id)),
+            Some(false) => Err(TaskListError::TooManyTasks(task_list_id)),
+        }
+    }
+}
+
+#[async_trait]
+impl TaskDao for PostgresTaskDao {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        // Nothing is inserted once the list is full.
+        let res = self.client
+            .query_opt(&format!("INSERT INTO tasks (id, task_list_id, name, description, due, priority, tags, \
+                                                    recurrence, position) \
+                                 SELECT $1, $2, $3, $4, $5, $6, $7, $8, COALESCE(MAX(position) + 1, 0) \
+                                 FROM tasks WHERE task_list_id = $2 HAVING COUNT(*) < $9 \
+                                 RETURNING {TASK_COLUMNS}"),
+                       &[&Uuid::new_v4(), &task_list_id, &data.name, &data.description, &data.due,
+                         &data.priority.to_i16(), &data.tags, &data.recurrence, &(MAX_TASKS as i64)])
+            .await;
+        match res {
+            Err(err) if err.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) =>
+                Err(TaskListError::TaskListNotFound(task_list_id)),
+            Err(err) => Err(err.into()),
+            Ok(Some(row)) => Ok(task_from_row(&row)),
+            Ok(None) => Err(TaskListError::TooManyTasks(task_list_id)),
+        }
+    }
+
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        let row = self.client
+            .query_opt(&format!("UPDATE tasks SET name = $3, description = $4, due = $5, priority = $6, tags = $7, \
+                                                  recurrence = $8, updated_at = now() \
+                                 WHERE id = $1 AND task_list_id = $2 RETURNING {TASK_COLUMNS}"),
+                       &[&id, &task_list_id, &data.name, &data.description, &data.due,
+                         &data.priority.to_i16(), &data.tags, &data.recurrence])
+            .await?;
+        match row {
+            Some(row) => Ok(task_from_row(&row)),
+            None => Err(TaskListError::TaskNotFound(id)),
+        }
+    }
+
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        let deleted = self.client
+            .execute("DELETE FROM tasks WHERE id = $1 AND task_list_id = $2", &[&id, &task_list_id])
+            .await?;
+        if deleted == 0 {
+            return Err(TaskListError::TaskNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>> {
+        query.check()?;
+        check_access(&self.client, self.user, task_list_id, Role::Viewer).await?;
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&task_list_id];
+        let mut conditions = vec!["task_list_id = $1".to_owned()];
+        if let Some(done) = &query.done {
+            conditions.push(format!("done = {}", param(&mut params, done)));
+        }
+        if let Some(tag) = &query.tag {
+            conditions.push(format!("{} = ANY(tags)", param(&mut params, tag)));
+        }
+        if let Some(due_before) = &query.due_before {
+            conditions.push(format!("due < {}", param(&mut params, due_before)));
+        }
+        if let Some(q) = &query.q {
+            conditions.push(format!("strpos(lower(name), lower({})) > 0", param(&mut params, q)));
+        }
+        let key = sort_key(query.sort, sort_column(query.sort));
+        let (direction, after) = match query.order {
+            Order::Asc => ("ASC", ">"),
+            Order::Desc => ("DESC", "<"),
+        };
+        if let Some(cursor) = &query.after {
+            let cursor_key = sort_key(query.sort, &param(&mut params, key_to_sql(&cursor.key)));
+            conditions.push(format!("({key}, id) {after} ({cursor_key}, {})", param(&mut params, &cursor.id)));
+        }
+        // `LIMIT NULL` means none.
+        let limit = query.limit.map(|limit| limit as i64 + 1);
+        let sql = format!("SELECT {TASK_COLUMNS}, position FROM tasks WHERE {} \
+                           ORDER BY {key} {direction}, id {direction} LIMIT {}",
+                          conditions.join(" AND "), param(&mut params, &limit));
+        let tasks = self.client
+            .query(&sql, &params)
+            .await?
+            .iter()
+            .map(|row| {
+                let task = task_from_row(row);
+                (SortKey::of(&task, query.sort, row.get("position")), task)
+            })
+            .collect();
+        Ok(query.page(tasks))
+    }
+
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        self.complete(task_list_id, id).await.map(|(_, next)| next)
+    }
+
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.set_done(task_list_id, id, false).await
+    }
+
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        let done = self.client
+            .query_opt("SELECT done FROM tasks WHERE id = $1 AND task_list_id = $2", &[&id, &task_list_id])
+            .await?
+            .ok_or(TaskListError::TaskNotFound(id))?
+            .get::<_, bool>("done");
+        if !done {
+            return self.complete(task_list_id, id).await;
+        }
+        let row = self.client
+            .query_opt(&format!("UPDATE tasks SET done = FALSE, completed_at = NULL, updated_at = now() \
+                                 WHERE id = $1 AND task_list_id = $2 RETURNING {TASK_COLUMNS}"),
+                       &[&id, &task_list_id])
+            .await?;
+        match row {
+            Some(row) => Ok((task_from_row(&row), None)),
+            None => Err(TaskListError::TaskNotFound(id)),
+        }
+    }
+
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()> {
+        check_access(&self.client, self.user, task_list_id, Role::Editor).await?;
+        // Renumbers the other tasks from zero leaving a gap at `position`, in one statement.
+        let updated = self.client
+            .execute("WITH ordered AS (SELECT id, ROW_NUMBER() OVER (ORDER BY id = $1, position) - 1 AS idx \
+                                       FROM tasks WHERE task_list_id = $2) \
+                      UPDATE tasks SET position = CASE WHEN tasks.id = $1 THEN $3 \
+                                                       WHEN ordered.idx >= $3 THEN ordered.idx + 1 \
+                                                       ELSE ordered.idx END \
+                      FROM ordered WHERE tasks.id = ordered.id \
+                        AND EXISTS (SELECT 1 FROM tasks WHERE id = $1 AND task_list_id = $2)",
+                     &[&id, &task_list_id, &(position as i64)])
+            .await?;
+        if updated == 0 {
+            return Err(TaskListError::TaskNotFound(id));
+        }
+        Ok(())
+    }
+}
+
+pub struct PostgresUserDao {
+    client: Arc<SharedClient>,
+}
+
+impl PostgresUserDao {
+    pub fn new(client: Arc<SharedClient>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl UserDao for PostgresUserDao {
+    async fn add(&self, username: String, password_hash: String) -> TaskListResult<User> {
+        let id = Uuid::new_v4();
+        let res = self.client
+            .execute("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)",
+                     &[&id, &username, &password_hash])
+            .await;
+        match res {
+            Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => Err(TaskListError::UsernameTaken(username)),
+            Err(err) => Err(err.into()),
+            Ok(_) => Ok(User { id, username }),
+        }
+    }
+
+    async fn get_by_username(&self, username: String) -> TaskListResult<Option<UserRecord>> {
+        Ok(self.client
+            .query_opt("SELECT id, username, password_hash, time_zone FROM users WHERE username = $1", &[&username])
+            .await?
+            .map(|row| UserRecord {
+                user: User { id: row.get("id"), username: row.get("username") },
+                password_hash: row.get("password_hash"),
+                settings: Settings { time_zone: time_zone_from_row(&row) },
+            }))
+    }
+
+    async fn get_settings(&self, id: user::Id) -> TaskListResult<Settings> {
+        Ok(self.client
+            .query_opt("SELECT time_zone FROM users WHERE id = $1", &[&id])
+            .await?
+            .map(|row| Settings { time_zone: time_zone_from_row(&row) })
+            .unwrap_or_default())
+    }
+
+    async fn update_settings(&self, id: user::Id, settings: Settings) -> TaskListResult<Settings> {
+        let updated = self.client
+            .execute("UPDATE users SET time_zone = $2 WHERE id = $1", &[&id, &settings.time_zone.name()])
+            .await?;
+        if updated == 0 {
+            return Err(TaskListError::UserNotFound(id.to_string()));
+        }
+        Ok(settings)
+    }
+}
+
+pub struct PostgresMemberDao {
+    client: Arc<SharedClient>,
+    user: user::Id,
+}
+
+impl PostgresMemberDao {
+    pub fn new(client: Arc<SharedClient>, user: user::Id) -> Self {
+        Self { client, user }
+    }
+}
+
+#[async_trait]
+impl MemberDao for PostgresMemberDao {
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role> {
+        check_access(&self.client, self.user, task_list_id, Role::Viewer).await
+    }
+
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>> {
+        check_access(&self.client, self.user, task_list_id, Role::Viewer).await?;
+        Ok(self.client
+            .query("SELECT users.id, users.username, role, accepted \
+                    FROM task_list_members JOIN users ON users.id = user_id \
+                    WHERE task_list_id = $1 ORDER BY users.username COLLATE \"C\"", &[&task_list_id])
+            .await?
+            .iter()
+            .map(|row| Member {
+                user: User { id: row.get("id"), username: row.get("username") },
+                role: Role::parse(row.get("role")),
+                accepted: row.get("accepted"),
+            })
+            .collect())
+    }
+
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member> {
+        check_access(&self.client, self.user, task_list_id, Role::Owner).await?;
+        if data.role == Role::Owner {
+            return Err(TaskListError::OwnerInvited);
+        }
+        let user = self.client
+            .query_opt("SELECT id, username FROM users WHERE username = $1", &[&data.username])
+            .await?
+            .map(|row| User { id: row.get("id"), username: row.get("username") })
+            .ok_or_else(|| TaskListError::UserNotFound(data.username.clone()))?;
+        if user.id == self.user {
+            return Err(TaskListError::AlreadyMember(data.username));
+        }
+        let res = self.client
+            .execute("INSERT INTO task_list_members (task_list_id, user_id, role) VALUES ($1, $2, $3)",
+                     &[&task_list_id, &user.id, &data.role.as_str()])
+            .await;
+        match res {
+            Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => Err(TaskListError::AlreadyMember(data.username)),
+            Err(err) if err.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) =>
+                Err(TaskListError::TaskListNotFound(task_list_id)),
+            Err(err) => Err(err.into()),
+            Ok(_) => Ok(Member { user, role: data.role, accepted: false }),
+        }
+    }
+
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()> {
+        let role = role_of(&self.client, self.user, task_list_id).await?;
+        if user_id != self.user {
+            check_role(task_list_id, role, Role::Owner)?;
+        }
+        let deleted = self.client
+            .execute("DELETE FROM task_list_members WHERE task_list_id = $1 AND user_id = $2", &[&task_list_id, &user_id])
+            .await?;
+        if deleted == 0 {
+            return Err(TaskListError::MemberNotFound(user_id));
+        }
+        Ok(())
+    }
+
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>> {
+        Ok(self.client
+            .query("SELECT task_lists.id, owner_id, name, role \
+                    FROM task_list_members JOIN task_lists ON task_lists.id = task_list_id \
+                    WHERE user_id = $1 AND NOT accepted ORDER BY name COLLATE \"C\", task_lists.id", &[&self.user])
+            .await?
+            .iter()
+            .map(|row| Invitation { task_list: task_list_from_row(row), role: Role::parse(row.get("role")) })
+            .collect())
+    }
+
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()> {
+        let updated = self.client
+            .execute("UPDATE task_list_members SET accepted = TRUE \
+                      WHERE task_list_id = $1 AND user_id = $2 AND NOT accepted", &[&task_list_id, &self.user])
+            .await?;
+        if updated == 0 {
+            return Err(TaskListError::InvitationNotFound(task_list_id));
+        }
+        Ok(())
+    }
+}
+
+/// Its DAOs share the client of the request, which is in a transaction until the unit is finished.
+pub struct PostgresUnitOfWork {
+    client: Arc<SharedClient>,
+    user: user::Id,
+}
+
+impl PostgresUnitOfWork {
+    pub async fn begin(client: Arc<SharedClient>, user: user::Id) -> TaskListResult<Self> {
+        // Set first, so the client is dropped if `BEGIN` is interrupted.
+        client.in_transaction.store(true, Ordering::Relaxed);
+        client.batch_execute("BEGIN").await?;
+        Ok(Self { client, user })
+    }
+
+    async fn end(&self, sql: &str) -> TaskListResult<()> {
+        if !self.client.in_transaction.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.client.batch_execute(sql).await?;
+        self.client.in_transaction.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for PostgresUnitOfWork {
+    fn task_lists(&self) -> Box<dyn TaskListDao> {
+        Box::new(PostgresTaskListDao { client: self.client.clone(), user: self.user })
+    }
+
+    fn tasks(&self) -> Box<dyn TaskDao> {
+        Box::new(PostgresTaskDao { client: self.client.clone(), user: self.user })
+    }
+
+    async fn commit(&self) -> TaskListResult<()> {
+        self.end("COMMIT").await
+    }
+
+    async fn rollback(&self) -> TaskListResult<()> {
+        self.end("ROLLBACK").await
+    }
+}
+
+
+/// Postgres instances for tests.
+///
+/// Uses the server from `TEST_DATABASE_URL` if it is set, otherwise starts a throwaway
+/// cluster with `initdb` and `pg_ctl`. Each test database is created from scratch and dropped
+/// at the end of the test.
+#[cfg(test)]
+pub mod testing {
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    use deadpool_postgres::Pool;
+    use uuid::Uuid;
+
+    use super::{create_pool, migrate, SharedClient};
+
+    pub struct TestDatabase {
+        pub pool: Pool,
+        /// Shared by the DAOs of a test like by those of a request.
+        client: Arc<SharedClient>,
+        /// Connects to the server rather than the test database, to drop it.
+        admin: tokio_postgres::Config,
+        name: String,
+        _cluster: Option<Cluster>,
+    }
+
+    impl TestDatabase {
+        /// Returns `None` if there is no way to get a Postgres server, e.g. `initdb` refuses to run as root.
+        pub async fn start() -> Option<Self> {
+            let (url, cluster) = match std::env::var("TEST_DATABASE_URL") {
+                Ok(url) => (url, None),
+                Err(_) => {
+                    let cluster = Cluster::start()?;
+                    (cluster.url(), Some(cluster))
+                }
+            };
+            let admin = url.parse::<tokio_postgres::Config>().ok()?;
+            let name = format!("task4_test_{}", Uuid::new_v4().simple());
+            create_pool(admin.clone()).ok()?
+                .get().await.ok()?
+                .batch_execute(&format!("CREATE DATABASE {name}")).await.ok()?;
+            let pool = create_pool(admin.clone().dbname(&name).clone()).ok()?;
+            migrate(&pool).await.unwrap();
+            let client = SharedClient::new(pool.get().await.unwrap());
+            Some(Self { pool, client, admin, name, _cluster: cluster })
+        }
+
+        pub fn client(&self) -> Arc<SharedClient> {
+            self.client.clone()
+        }
+    }
+
+    /// Fails without Postgres unless `SKIP_POSTGRES_TESTS` is set, then the test should return on `None`.
+    pub async fn start_or_skip() -> Option<TestDatabase> {
+        match TestDatabase::start().await {
+            Some(db) => Some(db),
+            None if std::env::var_os("SKIP_POSTGRES_TESTS").is_some() => {
+                eprintln!("skipping: no postgres available");
+                None
+            }
+            None => panic!("no postgres available, set TEST_DATABASE_URL or SKIP_POSTGRES_TESTS=1"),
+        }
+    }
+
+    impl Drop for TestDatabase {
+        /// Runs on a thread of its own, the runtime of the test can't be blocked on.
+        fn drop(&mut self) {
+            self.pool.close();
+            let admin = self.admin.clone();
+            let sql = format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name);
+            let dropped = std::thread::spawn(move || {
+                actix_web::rt::Runtime::new()?.block_on(async {
+                    create_pool(admin)?.get().await?.batch_execute(&sql).await?;
+                    Ok::<_, anyhow::Error>(())
+                })
+            }).join();
+            if let Ok(Err(err)) = dropped {
+                eprintln!("can't drop test database: {err:#}");
+            }
+        }
+    }
+
+    /// Cluster listening on a unix socket in its own data directory, removed on drop.
+    struct Cluster(PathBuf);
+
+    impl Cluster {
+        fn start() -> Option<Self> {
+            let dir = std::env::temp_dir().join(format!("task4_pg_{}", Uuid::new_v4().simple()));
+            let initialized = Command::new("initdb")
+                .args(["-U", "postgres", "--auth=trust", "-D"])
+                .arg(&dir)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false);
+            if !initialized {
+                let _ = std::fs::remove_dir_all(&dir);
+                return None;
+            }
+            let cluster = Cluster(dir);
+            let started = Command::new("pg_ctl")
+                .arg("-D").arg(&cluster.0)
+                .arg("-o").arg(format!("-c listen_addresses='' -k {}", cluster.0.display()))
+                .args(["-w", "start"])
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false);
+            started.then_some(cluster)
+        }
+
+        fn url(&self) -> String {
+            format!("host={} user=postgres dbname=postgres", self.0.display())
+        }
+    }
+
+    impl Drop for Cluster {
+        fn drop(&mut self) {
+            let _ = Command::new("pg_ctl")
+                .arg("-D").arg(&self.0)
+                .args(["-m", "immediate", "stop"])
+                .output();
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::testing::{start_or_skip, TestDatabase};
+    use crate::dao::tests::{check_details, check_lifecycle, check_list, check_lists, check_members, check_owners,
+                            check_query, check_recurrence, check_task_limit, check_unit_of_work, check_users, Access};
+
+    macro_rules! test_database {
+        () => {
+            match start_or_skip().await {
+                Some(db) => db,
+                None => return,
+            }
+        };
+    }
+
+    /// Lists reference their owners, so the owner has to be registered.
+    async fn owner(db: &TestDatabase, username: &str) -> user::Id {
+        PostgresUserDao::new(db.client())
+            .add(username.to_owned(), "hash".to_owned()).await.unwrap().id
+    }
+
+    #[actix_web::test]
+    async fn test_lists() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_lists(&PostgresTaskListDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_list() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_list(&PostgresTaskListDao::new(db.client(), owner),
+                   &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_details() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_details(&PostgresTaskListDao::new(db.client(), owner),
+                      &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_lifecycle(&PostgresTaskListDao::new(db.client(), owner),
+                        &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_query() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_query(&PostgresTaskListDao::new(db.client(), owner),
+                    &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_task_limit() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_task_limit(&PostgresTaskListDao::new(db.client(), owner),
+                         &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_unit_of_work() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_unit_of_work(&PostgresTaskListDao::new(db.client(), owner), || async {
+            // Like a unit of another request, so the list DAO does not see its changes.
+            let client = SharedClient::new(db.pool.get().await.unwrap());
+            let unit: Box<dyn UnitOfWork> = Box::new(PostgresUnitOfWork::begin(client, owner).await.unwrap());
+            unit
+        }).await;
+    }
+
+    #[actix_web::test]
+    async fn test_recurrence() {
+        let db = test_database!();
+        let owner = owner(&db, "alice").await;
+        check_recurrence(&PostgresUserDao::new(db.client()), owner,
+                         &PostgresTaskListDao::new(db.client(), owner),
+                         &PostgresTaskDao::new(db.client(), owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_users() {
+        let db = test_database!();
+        check_users(&PostgresUserDao::new(db.client())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_owners() {
+        let db = test_database!();
+        let (alice, bob) = (owner(&db, "alice").await, owner(&db, "bob").await);
+        check_owners((&PostgresTaskListDao::new(db.client(), alice),
+                      &PostgresTaskDao::new(db.client(), alice)),
+                     (&PostgresTaskListDao::new(db.client(), bob),
+                      &PostgresTaskDao::new(db.client(), bob))).await;
+    }
+
+    #[actix_web::test]
+    async fn test_members() {
+        let db = test_database!();
+        let mut daos = vec![];
+        for username in ["alice", "bob", "carol"] {
+            let id = owner(&db, username).await;
+            daos.push((PostgresTaskListDao::new(db.client(), id),
+                       PostgresTaskDao::new(db.client(), id),
+                       PostgresMemberDao::new(db.client(), id)));
+        }
+        let [alice, bob, carol] = [0, 1, 2].map(|i| -> Access { (&daos[i].0, &daos[i].1, &daos[i].2) });
+        check_members(alice, bob, carol).await;
+    }
+}
@@ -0,0 +1,831 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::Utc;
+use chrono_tz::Tz;
+use rusqlite::{ffi, params, Connection, ErrorCode, OptionalExtension, Row, ToSql, TransactionBehavior};
+use rusqlite::types::Type;
+use tokio::sync::OwnedMutexGuard;
+use uuid::Uuid;
+
+use crate::error::{TaskListError, TaskListResult};
+use crate::model::{task, task_list, user};
+use crate::model::member::{check_role, Invitation, Member, MemberIn, Role};
+use crate::model::query::{Order, Page, SortKey, TaskListQuery, TaskQuery, TaskSort, NO_DUE};
+use crate::model::recurrence::{next_task, today};
+use crate::model::task::{Priority, TaskIn, TaskOut};
+use crate::model::task_list::{TaskListIn, TaskListOut, TaskListWithTasks};
+use crate::model::user::{Settings, User, UserRecord};
+use crate::model::validation::MAX_TASKS;
+
+use super::{MemberDao, TaskDao, TaskListDao, UnitOfWork, UserDao};
+
+const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("../../migrations/sqlite/0001_init.sql")),
+    (2, include_str!("../../migrations/sqlite/0002_task_details.sql")),
+    (3, include_str!("../../migrations/sqlite/0003_users.sql")),
+    (4, include_str!("../../migrations/sqlite/0004_members.sql")),
+    (5, include_str!("../../migrations/sqlite/0005_recurrence.sql")),
+];
+
+/// Lists the user owns or is a member of, the user is bound twice.
+const ACCESSIBLE: &str = "(owner_id = ? OR id IN (SELECT task_list_id FROM task_list_members WHERE user_id = ? AND accepted))";
+
+const TASK_COLUMNS: &str = "id, name, description, due, priority, tags, recurrence, done, created_at, updated_at, completed_at";
+
+impl From<rusqlite::Error> for TaskListError {
+    fn from(err: rusqlite::Error) -> Self {
+        TaskListError::Unknown(err.into())
+    }
+}
+
+/// Connection shared by all requests, SQLite serializes writers anyway.
+#[derive(Clone)]
+pub struct SqliteState {
+    connection: Arc<Mutex<Connection>>,
+    /// Held by a unit of work until it is finished, so nothing else runs inside its transaction.
+    unit: Arc<tokio::sync::Mutex<()>>,
+    /// Set for the DAOs of a unit of work, which holds `unit` for them.
+    in_unit: bool,
+}
+
+impl SqliteState {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            unit: Arc::new(tokio::sync::Mutex::new(())),
+            in_unit: false,
+        })
+    }
+
+    /// Runs `f` on the blocking thread pool, so queries don't stall the workers.
+    async fn run<T, F>(&self, f: F) -> TaskListResult<T>
+        where T: Send + 'static,
+              F: FnOnce(&Connection) -> TaskListResult<T> + Send + 'static {
+        let _unit = if self.in_unit { None } else { Some(self.unit.lock().await) };
+        let connection = self.connection.clone();
+        web::block(move || f(&connection.lock().unwrap()))
+            .await
+            .map_err(|err| TaskListError::Unknown(err.into()))?
+    }
+
+    /// Like `run`, once the user is checked to have `role` in the list.
+    async fn run_in_list<T, F>(&self, user: user::Id, task_list_id: task_list::Id, role: Role, f: F) -> TaskListResult<T>
+        where T: Send + 'static,
+              F: FnOnce(&Connection) -> TaskListResult<T> + Send + 'static {
+        self.run(move |connection| {
+            check_role(task_list_id, role_of(connection, user, task_list_id)?, role)?;
+            f(connection)
+        }).await
+    }
+}
+
+/// Applies migrations newer than `user_version` of the database.
+fn migrate(connection: &mut Connection) -> anyhow::Result<()> {
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+    let applied: i32 = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, sql) in MIGRATIONS {
+        if *version > applied {
+            transaction.execute_batch(sql)?;
+            transaction.pragma_update(None, "user_version", version)?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<TaskOut> {
+    let tags: String = row.get("tags")?;
+    let tags = serde_json::from_str(&tags).map_err(|err|
+        rusqlite::Error::FromSqlConversionFailure(row.as_ref().column_index("tags").unwrap_or(0), Type::Text, err.into()))?;
+    Ok(TaskOut {
+        id: row.get("id")?,
+        core: TaskIn {
+            name: row.get("name")?,
+            description: row.get("description")?,
+            due: row.get("due")?,
+            priority: Priority::from_i16(row.get("priority")?),
+            tags,
+            recurrence: row.get("recurrence")?,
+        },
+        done: row.get("done")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+        completed_at: row.get("completed_at")?,
+    })
+}
+
+/// Tags are kept as a JSON array.
+fn tags_to_sql(tags: &[String]) -> String {
+    serde_json::to_string(tags).unwrap()
+}
+
+fn sort_column(sort: TaskSort) -> &'static str {
+    match sort {
+        TaskSort::Position => "position",
+        TaskSort::Created => "created_at",
+        TaskSort::Due => "due",
+        TaskSort::Priority => "priority",
+        TaskSort::Name => "name",
+    }
+}
+
+/// Expression tasks are sorted by, applied to a column or a parameter alike.
+/// Timestamps are normalized since older rows are written in another format.
+fn sort_key(sort: TaskSort, value: &str) -> String {
+    match sort {
+        TaskSort::Created => format!("strftime('%Y-%m-%d %H:%M:%f', {value})"),
+        TaskSort::Due => format!("COALESCE({value}, '{NO_DUE}')"),
+        TaskSort::Position | TaskSort::Priority | TaskSort::Name => value.to_owned(),
+    }
+}
+
+fn key_to_sql(key: &SortKey) -> &dyn ToSql {
+    match key {
+        SortKey::Position(position) => position,
+        SortKey::Created(created_at) => created_at,
+        SortKey::Due(due) => due,
+        SortKey::Priority(priority) => priority,
+        SortKey::Name(name) => name,
+    }
+}
+
+/// Role of the user, `None` for strangers and invitations which are not accepted.
+fn role_of(connection: &Connection, user: user::Id, task_list_id: task_list::Id) -> TaskListResult<Option<Role>> {
+    let row: Option<(Option<user::Id>, Option<String>)> = connection
+        .query_row("SELECT owner_id, (SELECT role FROM task_list_members \
+                                      WHERE task_list_id = task_lists.id AND user_id = ?2 AND accepted) AS role \
+                    FROM task_lists WHERE id = ?1",
+                   params![task_list_id, user], |row| Ok((row.get("owner_id")?, row.get("role")?)))
+        .optional()?;
+    match row {
+        None => Err(TaskListError::TaskListNotFound(task_list_id)),
+        Some((owner, _)) if owner == Some(user) => Ok(Some(Role::Owner)),
+        Some((_, role)) => Ok(role.as_deref().map(Role::parse)),
+    }
+}
+
+fn task_list_from_row(row: &Row) -> rusqlite::Result<TaskListOut> {
+    Ok(TaskListOut { id: row.get("id")?, owner: row.get("owner_id")?, core: TaskListIn { name: row.get("name")? } })
+}
+
+fn set_done(connection: &Connection, task_list_id: task_list::Id, id: task::Id, done: bool) -> TaskListResult<()> {
+    let updated = connection.execute(
+        "UPDATE tasks SET done = ?3, completed_at = CASE WHEN ?3 THEN ?4 END, updated_at = ?4 \
+         WHERE id = ?1 AND task_list_id = ?2 AND done <> ?3",
+        params![id, task_list_id, done, Utc::now()])?;
+    if updated > 0 {
+        return Ok(());
+    }
+    let task_exists = connection
+        .query_row("SELECT 1 FROM tasks WHERE id = ?1 AND task_list_id = ?2",
+                   params![id, task_list_id], |_| Ok(()))
+        .optional()?
+        .is_some();
+    match (task_exists, done) {
+        (true, true) => Err(TaskListError::TaskAlreadyDone(id)),
+        (true, false) => Err(TaskListError::TaskNotDone(id)),
+        (false, _) => Err(TaskListError::TaskNotFound(id)),
+    }
+}
+
+fn get_task(connection: &Connection, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<TaskOut> {
+    connection
+        .query_row(&format!("SELECT {TASK_COLUMNS} FROM tasks WHERE id = ?1 AND task_list_id = ?2"),
+                   params![id, task_list_id], task_from_row)
+        .optional()?
+        .ok_or(TaskListError::TaskNotFound(id))
+}
+
+/// Nothing is inserted once the list is full.
+fn insert_task(connection: &Connection, task_list_id: task_list::Id, data: &TaskIn) -> TaskListResult<TaskOut> {
+    let task = connection
+        .query_row(
+            &format!("INSERT INTO tasks (id, task_list_id, name, description, due, priority, tags, recurrence, \
+                                         created_at, updated_at, position) \
+                      SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9, COALESCE(MAX(position) + 1, 0) \
+                      FROM tasks WHERE task_list_id = ?2 HAVING COUNT(*) < ?10 \
+                      RETURNING {TASK_COLUMNS}"),
+            params![Uuid::new_v4(), task_list_id, data.name, data.description, data.due,
+                    data.priority.to_i16(), tags_to_sql(&data.tags), data.recurrence, Utc::now(), MAX_TASKS],
+            task_from_row)
+        .optional()?;
+    task.ok_or(TaskListError::TooManyTasks(task_list_id))
+}
+
+/// Time zone of the user, UTC for unknown users.
+fn time_zone_of(connection: &Connection, user: user::Id) -> TaskListResult<Tz> {
+    let time_zone: Option<String> = connection
+        .query_row("SELECT time_zone FROM users WHERE id = ?1", params![user], |row| row.get(0))
+        .optional()?;
+    Ok(time_zone.and_then(|x| x.parse().ok()).unwrap_or_default())
+}
+
+/// Marks the task done and adds the occurrence which follows it, see `TaskDao::mark_as_done`.
+/// The next task is added first, so the task stays as it is when the list is full.
+fn complete(connection: &Connection, user: user::Id, task_list_id: task_list::Id, task: TaskOut)
+            -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+    let next = match next_task(&task.core, today(time_zone_of(connection, user)?)) {
+        Some(data) => Some(insert_task(connection, task_list_id, &data)?),
+        None => None,
+    };
+    let task = connection
+        .query_row(&format!("UPDATE tasks SET done = TRUE, completed_at = ?3, updated_at = ?3, recurrence = NULL \
+                             WHERE id = ?1 AND task_list_id = ?2 RETURNING {TASK_COLUMNS}"),
+                   params![task.id, task_list_id, Utc::now()], task_from_row)?;
+    Ok((task, next))
+}
+
+pub struct SqliteTaskListDao {
+    state: SqliteState,
+    user: user::Id,
+}
+
+impl SqliteTaskListDao {
+    pub fn new(state: SqliteState, user: user::Id) -> Self {
+        Self { state, user }
+    }
+}
+
+#[async_trait]
+impl TaskListDao for SqliteTaskListDao {
+    async fn add(&self, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        let owner = self.user;
+        self.state.run(move |connection| {
+            let id = Uuid::new_v4();
+            connection.execute("INSERT INTO task_lists (id, name, owner_id) VALUES (?1, ?2, ?3)",
+                               params![id, data.name, owner])?;
+            Ok(TaskListOut { id, owner, core: data })
+        }).await
+    }
+
+    async fn rename(&self, id: task_list::Id, data: TaskListIn) -> TaskListResult<TaskListOut> {
+        self.state.run_in_list(self.user, id, Role::Editor, move |connection| {
+            Ok(connection.query_row("UPDATE task_lists SET name = ?2 WHERE id = ?1 RETURNING id, owner_id, name",
+                                    params![id, data.name], task_list_from_row)?)
+        }).await
+    }
+
+    async fn delete(&self, id: task_list::Id) -> TaskListResult<()> {
+        self.state.run_in_list(self.user, id, Role::Owner, move |connection| {
+            connection.execute("DELETE FROM task_lists WHERE id = ?1", params![id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_all(&self) -> TaskListResult<Vec<TaskListOut>> {
+        let user = self.user;
+        self.state.run(move |connection| {
+            Ok(connection
+                .prepare(&format!("SELECT id, owner_id, name FROM task_lists WHERE {ACCESSIBLE} ORDER BY name, id"))?
+                .query_map(params![user, user], task_list_from_row)?
+                .collect::<rusqlite::Result<_>>()?)
+        }).await
+    }
+
+    async fn find(&self, query: TaskListQuery) -> TaskListResult<Page<TaskListOut>> {
+        let user = self.user;
+        self.state.run(move |connection| {
+            let after = query.check()?;
+            let mut conditions = vec![ACCESSIBLE];
+            let mut params: Vec<&dyn ToSql> = vec![&user, &user];
+            if let Some(q) = &query.q {
+                conditions.push("instr(lower(name), lower(?)) > 0");
+                params.push(q);
+            }
+            if let Some((name, id)) = &after {
+                conditions.push("(name, id) > (?, ?)");
+                params.push(name);
+                params.push(id);
+            }
+            // Negative limit means none.
+            let limit = query.limit.map_or(-1, |limit| limit as i64 + 1);
+            params.push(&limit);
+            let task_lists = connection
+                .prepare(&format!("SELECT id, owner_id, name FROM task_lists WHERE {} ORDER BY name, id LIMIT ?",
+                                  conditions.join(" AND ")))?
+                .query_map(params.as_slice(), task_list_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(query.page(task_lists))
+        }).await
+    }
+
+    async fn get_by_id(&self, id: task_list::Id) -> TaskListResult<TaskListWithTasks> {
+        self.state.run_in_list(self.user, id, Role::Viewer, move |connection| {
+            let task_list = connection
+                .query_row("SELECT id, owner_id, name FROM task_lists WHERE id = ?1", params![id], task_list_from_row)?;
+            let tasks = connection
+                .prepare(&format!("SELECT {TASK_COLUMNS} FROM tasks WHERE task_list_id = ?1 ORDER BY position"))?
+                .query_map(params![id], task_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(TaskListWithTasks { id, owner: task_list.owner, core: task_list.core, tasks })
+        }).await
+    }
+}
+
+pub struct SqliteTaskDao {
+    state: SqliteState,
+    user: user::Id,
+}
+
+impl SqliteTaskDao {
+    pub fn new(state: SqliteState, user: user::Id) -> Self {
+        Self { state, user }
+    }
+}
+
+#[async_trait]
+impl TaskDao for SqliteTaskDao {
+    async fn add(&self, task_list_id: task_list::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        self.state.run_in_list(self.user, task_list_id, Role::Editor,
+                               move |connection| insert_task(connection, task_list_id, &data)).await
+    }
+
+    async fn update(&self, task_list_id: task_list::Id, id: task::Id, data: TaskIn) -> TaskListResult<TaskOut> {
+        self.state.run_in_list(self.user, task_list_id, Role::Editor, move |connection| {
+            let task = connection
+                .query_row(&format!("UPDATE tasks SET name = ?3, description = ?4, due = ?5, priority = ?6, tags = ?7, \
+                                                     recurrence = ?8, updated_at = ?9 \
+                                     WHERE id = ?1 AND task_list_id = ?2 RETURNING {TASK_COLUMNS}"),
+                           params![id, task_list_id, data.name, data.description, data.due,
+                                   data.priority.to_i16(), tags_to_sql(&data.tags), data.recurrence, Utc::now()],
+                           task_from_row)
+                .optional()?;
+            task.ok_or(TaskListError::TaskNotFound(id))
+        }).await
+    }
+
+    async fn delete(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.state.run_in_list(self.user, task_list_id, Role::Editor, move |connection| {
+            let deleted = connection.execute("DELETE FROM tasks WHERE id = ?1 AND task_list_id = ?2",
+                                             params![id, task_list_id])?;
+            if deleted == 0 {
+                return Err(TaskListError::TaskNotFound(id));
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn find(&self, task_list_id: task_list::Id, query: TaskQuery) -> TaskListResult<Page<TaskOut>> {
+        query.check()?;
+        self.state.run_in_list(self.user, task_list_id, Role::Viewer, move |connection| {
+            let mut conditions = vec!["task_list_id = ?".to_owned()];
+            let mut params: Vec<&dyn ToSql> = vec![&task_list_id];
+            if let Some(done) = &query.done {
+                conditions.push("done = ?".to_owned());
+                params.push(done);
+            }
+            if let Some(tag) = &query.tag {
+                conditions.push("EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?)".to_owned());
+                params.push(tag);
+            }
+            if let Some(due_before) = &query.due_before {
+                conditions.push("due < ?".to_owned());
+                params.push(due_before);
+            }
+            if let Some(q) = &query.q {
+                conditions.push("instr(lower(name), lower(?)) > 0".to_owned());
+                params.push(q);
+            }
+            let key = sort_key(query.sort, sort_column(query.sort));
+            let (direction, after) = match query.order {
+                Order::Asc => ("ASC", ">"),
+                Order::Desc => ("DESC", "<"),
+            };
+            if let Some(cursor) = &query.after {
+                conditions.push(format!("({key}, id) {after} ({}, ?)", sort_key(query.sort, "?")));
+                params.push(key_to_sql(&cursor.key));
+                params.push(&cursor.id);
+            }
+            let limit = query.limit.map_or(-1, |limit| limit as i64 + 1);
+            params.push(&limit);
+            let tasks = connection
+                .prepare(&format!("SELECT {TASK_COLUMNS}, position FROM tasks WHERE {} \
+                                   ORDER BY {key} {direction}, id {direction} LIMIT ?", conditions.join(" AND ")))?
+                .query_map(params.as_slice(), |row| {
+                    let task = task_from_row(row)?;
+                    Ok((SortKey::of(&task, query.sort, row.get("position")?), task))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(query.page(tasks))
+        }).await
+    }
+
+    async fn mark_as_done(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<Option<TaskOut>> {
+        let user = self.user;
+        self.state.run_in_list(self.user, task_list_id, Role::Editor, move |connection| {
+            let task = get_task(connection, task_list_id, id)?;
+            if task.done {
+                return Err(TaskListError::TaskAlreadyDone(id));
+            }
+            complete(connection, user, task_list_id, task).map(|(_, next)| next)
+        }).await
+    }
+
+    async fn mark_as_undone(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<()> {
+        self.state.run_in_list(self.user, task_list_id, Role::Editor,
+                               move |connection| set_done(connection, task_list_id, id, false)).await
+    }
+
+    async fn toggle(&self, task_list_id: task_list::Id, id: task::Id) -> TaskListResult<(TaskOut, Option<TaskOut>)> {
+        let user = self.user;
+        self.state.run_in_list(self.user, task_list_id, Role::Editor, move |connection| {
+            let task = get_task(connection, task_list_id, id)?;
+            if !task.done {
+                return complete(connection, user, task_list_id, task);
+            }
+            let task = connection
+                .query_row(&format!("UPDATE tasks SET done = FALSE, completed_at = NULL, updated_at = ?3 \
+                                     WHERE id = ?1 AND task_list_id = ?2 RETURNING {TASK_COLUMNS}"),
+                           params![id, task_list_id, Utc::now()], task_from_row)?;
+            Ok((task, None))
+        }).await
+    }
+
+    async fn move_to(&self, task_list_id: task_list::Id, id: task::Id, position: usize) -> TaskListResult<()> {
+        self.state.run_in_list(self.user, task_list_id, Role::Editor, move |connection| {
+            // Renumbers the other tasks from zero leaving a gap at `position`.
+            let updated = connection.execute(
+                "WITH ordered AS (SELECT id, ROW_NUMBER() OVER (ORDER BY id = ?1, position) - 1 AS idx \
+                                  FROM tasks WHERE task_list_id = ?2) \
+                 UPDATE tasks SET position = CASE WHEN tasks.id = ?1 THEN ?3 \
+                                                  WHEN ordered.idx >= ?3 THEN ordered.idx + 1 \
+                                                  ELSE ordered.idx END \
+                 FROM ordered WHERE tasks.id = ordered.id \
+                   AND EXISTS (SELECT 1 FROM tasks WHERE id = ?1 AND task_list_id = ?2)",
+                params![id, task_list_id, position as i64])?;
+            if updated == 0 {
+                return Err(TaskListError::TaskNotFound(id));
+            }
+            Ok(())
+        }).await
+    }
+}
+
+pub struct SqliteUserDao {
+    state: SqliteState,
+}
+
+impl SqliteUserDao {
+    pub fn new(state: SqliteState) -> Self {
+        Self { state }
+    }
+}
+
+#[async_trait]
+impl UserDao for SqliteUserDao {
+    async fn add(&self, username: String, password_hash: String) -> TaskListResult<User> {
+        self.state.run(move |connection| {
+            let id = Uuid::new_v4();
+            let res = connection.execute("INSERT INTO users (id, username, password_hash) VALUES (?1, ?2, ?3)",
+                                         params![id, username, password_hash]);
+            match res {
+                Err(err) if is_constraint_violation(&err, ffi::SQLITE_CONSTRAINT_UNIQUE) =>
+                    Err(TaskListError::UsernameTaken(username)),
+                Err(err) => Err(err.into()),
+                Ok(_) => Ok(User { id, username }),
+            }
+        }).await
+    }
+
+    async fn get_by_username(&self, username: String) -> TaskListResult<Option<UserRecord>> {
+        self.state.run(move |connection| {
+            Ok(connection
+                .query_row("SELECT id, username, password_hash, time_zone FROM users WHERE username = ?1", params![username],
+                           |row| Ok(UserRecord {
+                               user: User { id: row.get("id")?, username: row.get("username")? },
+                               password_hash: row.get("password_hash")?,
+                               settings: settings_from_row(row)?,
+                           }))
+                .optional()?)
+        }).await
+    }
+
+    async fn get_settings(&self, id: user::Id) -> TaskListResult<Settings> {
+        self.state.run(move |connection| {
+            Ok(connection
+                .query_row("SELECT time_zone FROM users WHERE id = ?1", params![id], settings_from_row)
+                .optional()?
+                .unwrap_or_default())
+        }).await
+    }
+
+    async fn update_settings(&self, id: user::Id, settings: Settings) -> TaskListResult<Settings> {
+        self.state.run(move |connection| {
+            let updated = connection.execute("UPDATE users SET time_zone = ?2 WHERE id = ?1",
+                                             params![id, settings.time_zone.name()])?;
+            if updated == 0 {
+                return Err(TaskListError::UserNotFound(id.to_string()));
+            }
+            Ok(settings)
+        }).await
+    }
+}
+
+/// Settings of a `users` row, unknown time zones are UTC.
+fn settings_from_row(row: &Row) -> rusqlite::Result<Settings> {
+    Ok(Settings { time_zone: row.get::<_, String>("time_zone")?.parse().unwrap_or_default() })
+}
+
+pub struct SqliteMemberDao {
+    state: SqliteState,
+    user: user::Id,
+}
+
+impl SqliteMemberDao {
+    pub fn new(state: SqliteState, user: user::Id) -> Self {
+        Self { state, user }
+    }
+}
+
+fn is_constraint_violation(err: &rusqlite::Error, extended_code: i32) -> bool {
+    matches!(err, rusqlite::Error::SqliteFailure(err, _)
+        if err.code == ErrorCode::ConstraintViolation && err.extended_code == extended_code)
+}
+
+#[async_trait]
+impl MemberDao for SqliteMemberDao {
+    async fn get_role(&self, task_list_id: task_list::Id) -> TaskListResult<Role> {
+        let user = self.user;
+        self.state.run(move |connection| check_role(task_list_id, role_of(connection, user, task_list_id)?, Role::Viewer)).await
+    }
+
+    async fn get_members(&self, task_list_id: task_list::Id) -> TaskListResult<Vec<Member>> {
+        self.state.run_in_list(self.user, task_list_id, Role::Viewer, move |connection| {
+            Ok(connection
+                .prepare("SELECT users.id, users.username, role, accepted \
+                          FROM task_list_members JOIN users ON users.id = user_id \
+                          WHERE task_list_id = ?1 ORDER BY users.username")?
+                .query_map(params![task_list_id], |row| Ok(Member {
+                    user: User { id: row.get("id")?, username: row.get("username")? },
+                    role: Role::parse(&row.get::<_, String>("role")?),
+                    accepted: row.get("accepted")?,
+                }))?
+                .collect::<rusqlite::Result<_>>()?)
+        }).await
+    }
+
+    async fn invite(&self, task_list_id: task_list::Id, data: MemberIn) -> TaskListResult<Member> {
+        let owner = self.user;
+        self.state.run_in_list(owner, task_list_id, Role::Owner, move |connection| {
+            if data.role == Role::Owner {
+                return Err(TaskListError::OwnerInvited);
+            }
+            let user = connection
+                .query_row("SELECT id, username FROM users WHERE username = ?1", params![data.username],
+                           |row| Ok(User { id: row.get("id")?, username: row.get("username")? }))
+                .optional()?
+                .ok_or_else(|| TaskListError::UserNotFound(data.username.clone()))?;
+            if user.id == owner {
+                return Err(TaskListError::AlreadyMember(data.username));
+            }
+            let res = connection.execute("INSERT INTO task_list_members (task_list_id, user_id, role) VALUES (?1, ?2, ?3)",
+                                         params![task_list_id, user.id, data.role.as_str()]);
+            match res {
+                Err(err) if is_constraint_violation(&err, ffi::SQLITE_CONSTRAINT_PRIMARYKEY) =>
+                    Err(TaskListError::AlreadyMember(data.username)),
+                Err(err) => Err(err.into()),
+                Ok(_) => Ok(Member { user, role: data.role, accepted: false }),
+            }
+        }).await
+    }
+
+    async fn revoke(&self, task_list_id: task_list::Id, user_id: user::Id) -> TaskListResult<()> {
+        let user = self.user;
+        self.state.run(move |connection| {
+            let role = role_of(connection, user, task_list_id)?;
+            if user_id != user {
+                check_role(task_list_id, role, Role::Owner)?;
+            }
+            let deleted = connection.execute("DELETE FROM task_list_members WHERE task_list_id = ?1 AND user_id = ?2",
+                                             params![task_list_id, user_id])?;
+            if deleted == 0 {
+                return Err(TaskListError::MemberNotFound(user_id));
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn get_invitations(&self) -> TaskListResult<Vec<Invitation>> {
+        let user = self.user;
+        self.state.run(move |connection| {
+            Ok(connection
+                .prepare("SELECT task_lists.id, owner_id, name, role \
+                          FROM task_list_members JOIN task_lists ON task_lists.id = task_list_id \
+                          WHERE user_id = ?1 AND NOT accepted ORDER BY name, task_lists.id")?
+                .query_map(params![user], |row| Ok(Invitation {
+                    task_list: task_list_from_row(row)?,
+                    role: Role::parse(&row.get::<_, String>("role")?),
+                }))?
+                .collect::<rusqlite::Result<_>>()?)
+        }).await
+    }
+
+    async fn accept(&self, task_list_id: task_list::Id) -> TaskListResult<()> {
+        let user = self.user;
+        self.state.run(move |connection| {
+            let updated = connection.execute("UPDATE task_list_members SET accepted = TRUE \
+                                              WHERE task_list_id = ?1 AND user_id = ?2 AND NOT accepted",
+                                             params![task_list_id, user])?;
+            if updated == 0 {
+                return Err(TaskListError::InvitationNotFound(task_list_id));
+            }
+            Ok(())
+        }).await
+    }
+}
+
+/// Its DAOs run in one transaction of the shared connection.
+pub struct SqliteUnitOfWork {
+    state: SqliteState,
+    user: user::Id,
+    /// Released once the unit is finished.
+    unit: Mutex<Option<OwnedMutexGuard<()>>>,
+}
+
+impl SqliteUnitOfWork {
+    pub async fn begin(state: SqliteState, user: user::Id) -> TaskListResult<Self> {
+        let unit = state.unit.clone().lock_owned().await;
+        let state = SqliteState { in_unit: true, ..state };
+        state.run(|connection| Ok(connection.execute_batch("BEGIN IMMEDIATE")?)).await?;
+        Ok(Self { state, user, unit: Mutex::new(Some(unit)) })
+    }
+
+    /// Runs `sql` which ends the transaction, it is rolled back if that fails.
+    async fn end(&self, sql: &'static str) -> TaskListResult<()> {
+        let Some(unit) = self.unit.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let res = self.state.run(move |connection| {
+            let res = connection.execute_batch(sql);
+            roll_back_open(connection);
+            Ok(res?)
+        }).await;
+        drop(unit);
+        res
+    }
+}
+
+/// Leaves no transaction open on the shared connection.
+fn roll_back_open(connection: &Connection) {
+    if !connection.is_autocommit() {
+        let _ = connection.execute_batch("ROLLBACK");
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for SqliteUnitOfWork {
+    fn task_lists(&self) -> Box<dyn TaskListDao> {
+        Box::new(SqliteTaskListDao::new(self.state.clone(), self.user))
+    }
+
+    fn tasks(&self) -> Box<dyn TaskDao> {
+        Box::new(SqliteTaskDao::new(self.state.clone(), self.user))
+    }
+
+    async fn commit(&self) -> TaskListResult<()> {
+        self.end("COMMIT").await
+    }
+
+    async fn rollback(&self) -> TaskListResult<()> {
+        self.end("ROLLBACK").await
+    }
+}
+
+impl Drop for SqliteUnitOfWork {
+    fn drop(&mut self) {
+        if self.unit.get_mut().unwrap().is_some() {
+            roll_back_open(&self.state.connection.lock().unwrap());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::tests::{check_details, check_lifecycle, check_list, check_lists, check_members, check_owners,
+                            check_query, check_recurrence, check_task_limit, check_unit_of_work, check_users, task_in,
+                            Access};
+
+    fn state() -> SqliteState {
+        SqliteState::open(Path::new(":memory:")).unwrap()
+    }
+
+    /// Lists reference their owners, so the owner has to be registered.
+    async fn owner(state: &SqliteState, username: &str) -> user::Id {
+        SqliteUserDao::new(state.clone()).add(username.to_owned(), "hash".to_owned()).await.unwrap().id
+    }
+
+    #[actix_web::test]
+    async fn test_lists() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_lists(&SqliteTaskListDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_list() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_list(&SqliteTaskListDao::new(state.clone(), owner), &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_details() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_details(&SqliteTaskListDao::new(state.clone(), owner), &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_lifecycle(&SqliteTaskListDao::new(state.clone(), owner), &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_query() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_query(&SqliteTaskListDao::new(state.clone(), owner), &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_task_limit() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_task_limit(&SqliteTaskListDao::new(state.clone(), owner), &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_unit_of_work() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_unit_of_work(&SqliteTaskListDao::new(state.clone(), owner), || {
+            let state = state.clone();
+            async move {
+                let unit: Box<dyn UnitOfWork> = Box::new(SqliteUnitOfWork::begin(state, owner).await.unwrap());
+                unit
+            }
+        }).await;
+    }
+
+    #[actix_web::test]
+    async fn test_recurrence() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        check_recurrence(&SqliteUserDao::new(state.clone()), owner, &SqliteTaskListDao::new(state.clone(), owner),
+                         &SqliteTaskDao::new(state, owner)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_users() {
+        check_users(&SqliteUserDao::new(state())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_owners() {
+        let state = state();
+        let (alice, bob) = (owner(&state, "alice").await, owner(&state, "bob").await);
+        check_owners((&SqliteTaskListDao::new(state.clone(), alice), &SqliteTaskDao::new(state.clone(), alice)),
+                     (&SqliteTaskListDao::new(state.clone(), bob), &SqliteTaskDao::new(state, bob))).await;
+    }
+
+    #[actix_web::test]
+    async fn test_members() {
+        let state = state();
+        let mut ids = vec![];
+        for username in ["alice", "bob", "carol"] {
+            ids.push(owner(&state, username).await);
+        }
+        let daos = ids.iter()
+            .map(|&id| (SqliteTaskListDao::new(state.clone(), id), SqliteTaskDao::new(state.clone(), id),
+                        SqliteMemberDao::new(state.clone(), id)))
+            .collect::<Vec<_>>();
+        let [alice, bob, carol] = [0, 1, 2].map(|i| -> Access { (&daos[i].0, &daos[i].1, &daos[i].2) });
+        check_members(alice, bob, carol).await;
+    }
+
+    #[actix_web::test]
+    async fn test_delete_cascades() {
+        let state = state();
+        let owner = owner(&state, "alice").await;
+        let task_lists_dao = SqliteTaskListDao::new(state.clone(), owner);
+        task_lists_dao.add(TaskListIn { name: "hello".to_owned() }).await.unwrap();
+        let id = task_lists_dao.get_all().await.unwrap()[0].id;
+        SqliteTaskDao::new(state.clone(), owner).add(id, task_in("test")).await.unwrap();
+
+        task_lists_dao.delete(id).await.unwrap();
+        let tasks = state.run(|connection| {
+            Ok(connection.query_row("SELECT COUNT(*) FROM tasks", [], |row| row.get::<_, i64>(0))?)
+        }).await.unwrap();
+        assert_eq!(tasks, 0);
+    }
+}
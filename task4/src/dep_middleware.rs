@@ -1,74 +1,449 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
 use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
 
-use actix_web::web;
-use futures::future::LocalBoxFuture;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use tokio::sync::OnceCell;
 
-use crate::dao::{MemoryTaskDao, MemoryTaskListDao, TaskDao, TaskListDao, TaskListMemoryState};
+use crate::auth;
+use crate::dao::notify::{NotifyingMemberDao, NotifyingTaskDao, NotifyingTaskListDao, NotifyingUnitOfWork};
+use crate::dao::{MemberDao, MemoryMemberDao, MemoryTaskDao, MemoryTaskListDao, MemoryUnitOfWork, MemoryUserDao, TaskDao,
+                 TaskListDao, TaskListMemoryState, UnitOfWork, UserDao};
+use crate::dao::file::{FileMemberDao, FileState, FileTaskDao, FileTaskListDao, FileUnitOfWork, FileUserDao};
+use crate::dao::postgres::{PostgresMemberDao, PostgresTaskDao, PostgresTaskListDao, PostgresUnitOfWork, PostgresUserDao,
+                           SharedClient};
+use crate::dao::sqlite::{SqliteMemberDao, SqliteState, SqliteTaskDao, SqliteTaskListDao, SqliteUnitOfWork, SqliteUserDao};
+use crate::error::{TaskListError, TaskListResult};
+use crate::events::ChangeBus;
+use crate::model::user;
 
-pub struct Dependency<T: ?Sized>(Box<T>);
-
-impl<T: ?Sized> Dependency<T> {
-    pub fn new(value: Box<T>) -> Self {
-        Self { 0: value }
-    }
-}
+/// Value of `T` resolved from the `Container` of the app.
+pub struct Dependency<T: ?Sized>(Rc<T>);
 
 impl<T: ?Sized> Deref for Dependency<T> {
-    type Target = Box<T>;
+    type Target = Rc<T>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-pub trait DependencyFactory<T: ?Sized> {
-    fn new(&self, req: &actix_web::HttpRequest) -> LocalBoxFuture<'static, Result<Dependency<T>, actix_web::Error>>;
+impl<T: ?Sized + 'static> FromRequest for Dependency<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        resolve(req)
+    }
 }
 
-impl DependencyFactory<dyn TaskListDao> for MemoryTaskListDaoFactory {
-    fn new(&self, req: &actix_web::HttpRequest) -> LocalBoxFuture<'static, Result<Dependency<dyn TaskListDao>, actix_web::Error>> {
-        let state = req.app_data::<TaskListMemoryState>().unwrap().clone();
-        Box::pin(async move {
-            let value = MemoryTaskListDao::new(state);
-            let res: Dependency<dyn TaskListDao> = Dependency::new(Box::new(value));
-            Ok(res)
-        })
+fn resolve<T: ?Sized + 'static>(req: &HttpRequest) -> LocalBoxFuture<'static, Result<Dependency<T>, actix_web::Error>> {
+    match req.app_data::<web::Data<Container>>() {
+        Some(container) => container.resolve(req),
+        None => Box::pin(ready(Err(not_registered::<T>()))),
     }
 }
 
-impl DependencyFactory<dyn TaskDao> for MemoryTaskDaoFactory {
-    fn new(&self, req: &actix_web::HttpRequest) -> LocalBoxFuture<'static, Result<Dependency<dyn TaskDao>, actix_web::Error>> {
-        let state = req.app_data::<TaskListMemoryState>().unwrap().clone();
+/// `Dependency<T>` which is only resolved once the handler asks for it.
+/// Extractors run at the same time, so a unit of work taken as `Dependency` would begin while the body is read.
+pub struct Deferred<T: ?Sized> {
+    req: HttpRequest,
+    value: PhantomData<fn() -> Rc<T>>,
+}
+
+impl<T: ?Sized + 'static> Deferred<T> {
+    pub async fn resolve(&self) -> Result<Dependency<T>, actix_web::Error> {
+        resolve(&self.req).await
+    }
+}
+
+impl<T: ?Sized + 'static> FromRequest for Deferred<T> {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(Ok(Self { req: req.clone(), value: PhantomData }))
+    }
+}
+
+/// How long a resolved value is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lifetime {
+    /// Made once by the first request which needs it. Every worker has its own container, so its own value.
+    Singleton,
+    /// Made once per request.
+    Scoped,
+    /// Made every time it is resolved.
+    Transient,
+}
+
+type Factory<T> = Rc<dyn Fn(&HttpRequest) -> LocalBoxFuture<'static, Result<Box<T>, actix_web::Error>>>;
+
+struct Registration<T: ?Sized> {
+    lifetime: Lifetime,
+    factory: Factory<T>,
+    singleton: Rc<OnceCell<Rc<T>>>,
+}
+
+/// Value of a `Scoped` registration, kept in the extensions of its request.
+struct Scoped<T: ?Sized>(Rc<T>);
+
+/// Factories of the values handlers take as `Dependency<T>`, usually `T` is a trait object like `dyn TaskDao`.
+#[derive(Default)]
+pub struct Container {
+    registrations: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Container {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the factory of `T` if there is one already.
+    pub fn register<T, F, Fut>(&mut self, lifetime: Lifetime, factory: F) -> &mut Self
+        where T: ?Sized + 'static,
+              F: Fn(&HttpRequest) -> Fut + 'static,
+              Fut: Future<Output=Result<Box<T>, actix_web::Error>> + 'static {
+        let factory: Factory<T> = Rc::new(move |req| Box::pin(factory(req)));
+        let registration = Registration { lifetime, factory, singleton: Rc::new(OnceCell::new()) };
+        self.registrations.insert(TypeId::of::<T>(), Box::new(registration));
+        self
+    }
+
+    /// Wraps every value the registered factory of `T` makes.
+    ///
+    /// # Panics
+    ///
+    /// If `T` is not registered yet.
+    pub fn decorate<T, F>(&mut self, decorator: F) -> &mut Self
+        where T: ?Sized + 'static,
+              F: Fn(Box<T>) -> Box<T> + 'static {
+        let registration = self.registration_mut::<T>()
+            .unwrap_or_else(|| panic!("`{}` is decorated before it is registered", type_name::<T>()));
+        let inner = registration.factory.clone();
+        let decorator = Rc::new(decorator);
+        registration.factory = Rc::new(move |req| {
+            let value = inner(req);
+            let decorator = decorator.clone();
+            Box::pin(async move { Ok(decorator(value.await?)) })
+        });
+        self
+    }
+
+    /// Fails with an internal server error if `T` is not registered.
+    pub fn resolve<T: ?Sized + 'static>(&self, req: &HttpRequest) -> LocalBoxFuture<'static, Result<Dependency<T>, actix_web::Error>> {
+        let Some(registration) = self.registration::<T>() else {
+            return Box::pin(ready(Err(not_registered::<T>())));
+        };
+        match registration.lifetime {
+            Lifetime::Singleton => {
+                let singleton = registration.singleton.clone();
+                let factory = registration.factory.clone();
+                let req = req.clone();
+                Box::pin(async move {
+                    let value = singleton.get_or_try_init(|| async { factory(&req).await.map(Rc::from) }).await?;
+                    Ok(Dependency(value.clone()))
+                })
+            }
+            Lifetime::Scoped => {
+                if let Some(Scoped(value)) = req.extensions().get::<Scoped<T>>() {
+                    return Box::pin(ready(Ok(Dependency(value.clone()))));
+                }
+                let value = (registration.factory)(req);
+                let req = req.clone();
+                Box::pin(async move {
+                    let value: Rc<T> = Rc::from(value.await?);
+                    // Another extractor of the same request may have been faster.
+                    let mut extensions = req.extensions_mut();
+                    let scoped = extensions.get::<Scoped<T>>().map(|Scoped(value)| value.clone());
+                    let value = scoped.unwrap_or_else(|| {
+                        extensions.insert(Scoped(value.clone()));
+                        value
+                    });
+                    Ok(Dependency(value))
+                })
+            }
+            Lifetime::Transient => {
+                let value = (registration.factory)(req);
+                Box::pin(async move { Ok(Dependency(Rc::from(value.await?))) })
+            }
+        }
+    }
+
+    fn registration<T: ?Sized + 'static>(&self) -> Option<&Registration<T>> {
+        self.registrations.get(&TypeId::of::<T>())
+            .and_then(|registration| registration.downcast_ref())
+    }
+
+    fn registration_mut<T: ?Sized + 'static>(&mut self) -> Option<&mut Registration<T>> {
+        self.registrations.get_mut(&TypeId::of::<T>())
+            .and_then(|registration| registration.downcast_mut())
+    }
+}
+
+/// Details go to the server log, the client only sees an unexpected server error.
+fn not_registered<T: ?Sized>() -> actix_web::Error {
+    TaskListError::Unknown(anyhow::anyhow!("`{}` is not registered in the dependency container", type_name::<T>())).into()
+}
+
+/// Factory of a DAO which acts for the logged in user, without one it fails with `Unauthorized`.
+/// Such a DAO only clones the state, so it is made for every handler which takes it.
+fn for_user<T, S>(state: S, new: fn(S, user::Id) -> Box<T>) -> impl Fn(&HttpRequest) -> Ready<Result<Box<T>, actix_web::Error>>
+    where T: ?Sized, S: Clone {
+    move |req| ready(auth::current_user_id(req).map(|user| new(state.clone(), user)).map_err(Into::into))
+}
+
+/// Pool client of a request, kept in its extensions.
+#[derive(Clone, Default)]
+struct RequestClient(Rc<OnceCell<Arc<SharedClient>>>);
+
+/// Takes a client from the pool the first time a DAO of the request needs one, the others share it.
+/// A request never holds a client while it waits for another, so requests can't starve each other.
+fn request_client(req: &HttpRequest, pool: &deadpool_postgres::Pool)
+                  -> impl Future<Output=Result<Arc<SharedClient>, actix_web::Error>> + 'static {
+    let cell = {
+        let mut extensions = req.extensions_mut();
+        match extensions.get::<RequestClient>() {
+            Some(RequestClient(cell)) => cell.clone(),
+            None => {
+                let client = RequestClient::default();
+                extensions.insert(client.clone());
+                client.0
+            }
+        }
+    };
+    let pool = pool.clone();
+    async move {
+        cell.get_or_try_init(|| async { pool.get().await.map(SharedClient::new) }).await
+            .cloned()
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+}
+
+/// `for_user` for Postgres, where the DAOs of a request share its client.
+fn for_user_with_client<T: ?Sized + 'static>(pool: deadpool_postgres::Pool,
+                                             new: fn(Arc<SharedClient>, user::Id) -> Box<T>)
+                                             -> impl Fn(&HttpRequest) -> LocalBoxFuture<'static, Result<Box<T>, actix_web::Error>> {
+    move |req| {
+        let user = auth::current_user_id(req);
+        let client = request_client(req, &pool);
         Box::pin(async move {
-            let value = MemoryTaskDao::new(state);
-            let res: Dependency<dyn TaskDao> = Dependency::new(Box::new(value));
-            Ok(res)
+            let user = user?;
+            Ok(new(client.await?, user))
         })
     }
 }
 
-pub struct MemoryTaskListDaoFactory;
+/// Factory of a unit of work for the logged in user, like `for_user`.
+fn unit_for_user<S, Fut>(state: S, begin: fn(S, user::Id) -> Fut)
+                         -> impl Fn(&HttpRequest) -> LocalBoxFuture<'static, Result<Box<dyn UnitOfWork>, actix_web::Error>>
+    where S: Clone + 'static,
+          Fut: Future<Output=TaskListResult<Box<dyn UnitOfWork>>> + 'static {
+    move |req| {
+        let state = state.clone();
+        let user = auth::current_user_id(req);
+        Box::pin(async move { Ok(begin(state, user?).await?) })
+    }
+}
+
+/// Registers the DAOs of the in-memory backend.
+pub fn register_memory(container: &mut Container, state: TaskListMemoryState) {
+    container
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskListDao> {
+            Box::new(MemoryTaskListDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskDao> {
+            Box::new(MemoryTaskDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn MemberDao> {
+            Box::new(MemoryMemberDao::new(state, user))
+        }))
+        .register(Lifetime::Scoped, unit_for_user(state.clone(), |state, user| async move {
+            let unit: Box<dyn UnitOfWork> = Box::new(MemoryUnitOfWork::begin(state, user).await);
+            Ok(unit)
+        }))
+        .register(Lifetime::Singleton, move |_: &HttpRequest| -> Ready<Result<Box<dyn UserDao>, actix_web::Error>> {
+            ready(Ok(Box::new(MemoryUserDao::new(state.clone()))))
+        });
+}
 
-pub struct MemoryTaskDaoFactory;
+/// Registers the DAOs of the JSON snapshot backend.
+pub fn register_file(container: &mut Container, state: FileState) {
+    container
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskListDao> {
+            Box::new(FileTaskListDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskDao> {
+            Box::new(FileTaskDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn MemberDao> {
+            Box::new(FileMemberDao::new(state, user))
+        }))
+        .register(Lifetime::Scoped, unit_for_user(state.clone(), |state, user| async move {
+            let unit: Box<dyn UnitOfWork> = Box::new(FileUnitOfWork::begin(state, user).await);
+            Ok(unit)
+        }))
+        .register(Lifetime::Singleton, move |_: &HttpRequest| -> Ready<Result<Box<dyn UserDao>, actix_web::Error>> {
+            ready(Ok(Box::new(FileUserDao::new(state.clone()))))
+        });
+}
 
-impl actix_web::FromRequest for Dependency<dyn TaskListDao + 'static> {
-    type Error = actix_web::Error;
-    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+/// Registers the DAOs of the SQLite backend.
+pub fn register_sqlite(container: &mut Container, state: SqliteState) {
+    container
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskListDao> {
+            Box::new(SqliteTaskListDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn TaskDao> {
+            Box::new(SqliteTaskDao::new(state, user))
+        }))
+        .register(Lifetime::Transient, for_user(state.clone(), |state, user| -> Box<dyn MemberDao> {
+            Box::new(SqliteMemberDao::new(state, user))
+        }))
+        .register(Lifetime::Scoped, unit_for_user(state.clone(), |state, user| async move {
+            let unit: Box<dyn UnitOfWork> = Box::new(SqliteUnitOfWork::begin(state, user).await?);
+            Ok(unit)
+        }))
+        .register(Lifetime::Singleton, move |_: &HttpRequest| -> Ready<Result<Box<dyn UserDao>, actix_web::Error>> {
+            ready(Ok(Box::new(SqliteUserDao::new(state.clone()))))
+        });
+}
 
-    fn from_request(req: &actix_web::HttpRequest,
-                    _payload: &mut actix_web::dev::Payload) -> Self::Future {
-        let factory = req.app_data::<web::Data<dyn DependencyFactory<dyn TaskListDao>>>().unwrap();
-        factory.new(req)
-    }
+/// Registers the DAOs of the Postgres backend. A request holds at most one client.
+pub fn register_postgres(container: &mut Container, pool: deadpool_postgres::Pool) {
+    container
+        .register(Lifetime::Scoped, for_user_with_client(pool.clone(), |client, user| -> Box<dyn TaskListDao> {
+            Box::new(PostgresTaskListDao::new(client, user))
+        }))
+        .register(Lifetime::Scoped, for_user_with_client(pool.clone(), |client, user| -> Box<dyn TaskDao> {
+            Box::new(PostgresTaskDao::new(client, user))
+        }))
+        .register(Lifetime::Scoped, for_user_with_client(pool.clone(), |client, user| -> Box<dyn MemberDao> {
+            Box::new(PostgresMemberDao::new(client, user))
+        }))
+        .register(Lifetime::Scoped, {
+            let pool = pool.clone();
+            move |req: &HttpRequest| {
+                let user = auth::current_user_id(req);
+                let client = request_client(req, &pool);
+                async move {
+                    let unit: Box<dyn UnitOfWork> = Box::new(PostgresUnitOfWork::begin(client.await?, user?).await?);
+                    Ok(unit)
+                }
+            }
+        })
+        .register(Lifetime::Scoped, move |req: &HttpRequest| {
+            let client = request_client(req, &pool);
+            async move {
+                let dao: Box<dyn UserDao> = Box::new(PostgresUserDao::new(client.await?));
+                Ok(dao)
+            }
+        });
 }
 
-impl actix_web::FromRequest for Dependency<dyn TaskDao + 'static> {
-    type Error = actix_web::Error;
-    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+/// Makes the task list and task DAOs of any backend publish their changes to `bus`.
+pub fn register_notifying(container: &mut Container, bus: ChangeBus) {
+    let task_bus = bus.clone();
+    let member_bus = bus.clone();
+    let unit_bus = bus.clone();
+    container
+        .decorate::<dyn TaskListDao, _>(move |dao| Box::new(NotifyingTaskListDao::new(bus.clone(), dao)))
+        .decorate::<dyn TaskDao, _>(move |dao| Box::new(NotifyingTaskDao::new(task_bus.clone(), dao)))
+        .decorate::<dyn MemberDao, _>(move |dao| Box::new(NotifyingMemberDao::new(member_bus.clone(), dao)))
+        .decorate::<dyn UnitOfWork, _>(move |unit| Box::new(NotifyingUnitOfWork::new(unit_bus.clone(), unit)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use std::time::Duration;
+
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::dao::postgres::testing::start_or_skip;
+
+    trait Counter {
+        fn number(&self) -> usize;
+    }
+
+    struct Numbered(usize);
+
+    impl Counter for Numbered {
+        fn number(&self) -> usize {
+            self.0
+        }
+    }
+
+    /// Container where `dyn Counter` numbers the values it makes.
+    fn container(lifetime: Lifetime) -> web::Data<Container> {
+        let made = Rc::new(Cell::new(0));
+        let mut container = Container::new();
+        container.register(lifetime, move |_: &HttpRequest| -> Ready<Result<Box<dyn Counter>, actix_web::Error>> {
+            made.set(made.get() + 1);
+            ready(Ok(Box::new(Numbered(made.get()))))
+        });
+        web::Data::new(container)
+    }
+
+    async fn numbers(req: &HttpRequest) -> (usize, usize) {
+        let first = Dependency::<dyn Counter>::extract(req).await.unwrap();
+        let second = Dependency::<dyn Counter>::extract(req).await.unwrap();
+        (first.number(), second.number())
+    }
+
+    #[actix_web::test]
+    async fn test_lifetimes() {
+        let singleton = container(Lifetime::Singleton);
+        let scoped = container(Lifetime::Scoped);
+        let transient = container(Lifetime::Transient);
+        for (container, expected) in [(singleton, [(1, 1), (1, 1)]), (scoped, [(1, 1), (2, 2)]), (transient, [(1, 2), (3, 4)])] {
+            let first = TestRequest::default().app_data(container.clone()).to_http_request();
+            let second = TestRequest::default().app_data(container).to_http_request();
+            assert_eq!([numbers(&first).await, numbers(&second).await], expected);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_decorate() {
+        let mut container = Container::new();
+        container
+            .register(Lifetime::Transient, |_: &HttpRequest| -> Ready<Result<Box<dyn Counter>, actix_web::Error>> {
+                ready(Ok(Box::new(Numbered(1))))
+            })
+            .decorate::<dyn Counter, _>(|counter| Box::new(Numbered(counter.number() + 10)));
+        let req = TestRequest::default().app_data(web::Data::new(container)).to_http_request();
+        assert_eq!(Dependency::<dyn Counter>::extract(&req).await.unwrap().number(), 11);
+    }
+
+    #[actix_web::test]
+    async fn test_not_registered() {
+        let req = TestRequest::default().app_data(web::Data::new(Container::new())).to_http_request();
+        let err = Dependency::<dyn Counter>::extract(&req).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let req = TestRequest::default().to_http_request();
+        let err = Dependency::<dyn Counter>::extract(&req).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
 
-    fn from_request(req: &actix_web::HttpRequest,
-                    _payload: &mut actix_web::dev::Payload) -> Self::Future {
-        let factory = req.app_data::<web::Data<dyn DependencyFactory<dyn TaskDao>>>().unwrap();
-        factory.new(req)
+    #[actix_web::test]
+    async fn test_request_client() {
+        let Some(db) = start_or_skip().await else {
+            return;
+        };
+        // One client is held by the test database, a second one for the request would never come.
+        db.pool.resize(2);
+        let req = TestRequest::default().to_http_request();
+        let clients = futures::future::join(request_client(&req, &db.pool), request_client(&req, &db.pool));
+        let (first, second) = actix_web::rt::time::timeout(Duration::from_secs(5), clients).await.unwrap();
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
     }
 }
@@ -1,6 +1,11 @@
+use actix_web::{HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
 use thiserror::Error;
 
-use crate::model::{task, task_list};
+use crate::exchange::ImportErrors;
+use crate::model::{task, task_list, user};
+use crate::model::validation::{FieldErrors, MAX_TASKS};
+use crate::view;
 
 #[derive(Error, Debug)]
 pub enum TaskListError {
@@ -10,8 +15,97 @@ pub enum TaskListError {
     TaskNotFound(task::Id),
     #[error("task {0} already done")]
     TaskAlreadyDone(task::Id),
-    // #[error("unexpected server error")]
-    // Unknown,
+    #[error("task {0} is not done")]
+    TaskNotDone(task::Id),
+    #[error("invalid input: {0}")]
+    Invalid(FieldErrors),
+    #[error("invalid file: {0}")]
+    InvalidImport(ImportErrors),
+    #[error("task list `{0}` already has {max} tasks", max = MAX_TASKS)]
+    TooManyTasks(task_list::Id),
+    #[error("cursor does not belong to this sort")]
+    InvalidCursor,
+    #[error("not allowed for task list `{0}`")]
+    Forbidden(task_list::Id),
+    #[error("user `{0}` not found")]
+    UserNotFound(String),
+    #[error("user `{0}` is already invited")]
+    AlreadyMember(String),
+    #[error("user for id `{0}` is not a member")]
+    MemberNotFound(user::Id),
+    #[error("no invitation to task list `{0}`")]
+    InvitationNotFound(task_list::Id),
+    #[error("lists are shared only with viewers and editors")]
+    OwnerInvited,
+    #[error("username `{0}` is taken")]
+    UsernameTaken(String),
+    #[error("wrong username or password")]
+    InvalidCredentials,
+    #[error("unexpected server error")]
+    Unknown(#[source] anyhow::Error),
 }
 
 pub type TaskListResult<T> = Result<T, TaskListError>;
+
+impl TaskListError {
+    /// Storage failures are only shown as a generic message, so their details go to the server log.
+    pub fn log_internal(&self) {
+        if let TaskListError::Unknown(err) = self {
+            eprintln!("internal error: {err:?}");
+        }
+    }
+}
+
+impl ResponseError for TaskListError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TaskListError::TaskListNotFound(_) | TaskListError::TaskNotFound(_) | TaskListError::UserNotFound(_)
+            | TaskListError::MemberNotFound(_) | TaskListError::InvitationNotFound(_) => StatusCode::NOT_FOUND,
+            TaskListError::TaskAlreadyDone(_) | TaskListError::TaskNotDone(_) | TaskListError::UsernameTaken(_)
+            | TaskListError::AlreadyMember(_) | TaskListError::TooManyTasks(_) => StatusCode::CONFLICT,
+            TaskListError::Invalid(_) | TaskListError::InvalidImport(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            TaskListError::InvalidCursor | TaskListError::OwnerInvited => StatusCode::BAD_REQUEST,
+            TaskListError::Forbidden(_) => StatusCode::FORBIDDEN,
+            TaskListError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            TaskListError::Unknown(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.log_internal();
+        view::view_error(self.status_code(), &self.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+    use anyhow::anyhow;
+    use uuid::Uuid;
+
+    use super::*;
+
+    async fn body(err: TaskListError) -> (StatusCode, String) {
+        let res = err.error_response();
+        let status = res.status();
+        let body = to_bytes(res.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let id = Uuid::new_v4();
+        let (status, page) = body(TaskListError::TaskListNotFound(id)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(page.contains(&format!("task list for id `{id}` not found")));
+        assert!(!page.contains("TaskListNotFound"));
+
+        assert_eq!(body(TaskListError::TaskAlreadyDone(id)).await.0, StatusCode::CONFLICT);
+
+        let (status, page) = body(TaskListError::Unknown(anyhow!("connection to 10.0.0.1 refused"))).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(page.contains("unexpected server error"));
+        assert!(!page.contains("10.0.0.1"));
+    }
+}
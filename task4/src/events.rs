@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::model::{task, task_list, user};
+use crate::model::task::TaskOut;
+use crate::model::task_list::TaskListOut;
+
+/// Changes of a list kept for subscribers which are behind, older ones are dropped and `Lagged` is sent instead.
+const CAPACITY: usize = 256;
+
+/// Change made to a list, as sent to the pages which show it.
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    TaskAdded { task: TaskOut },
+    /// Edited or toggled.
+    TaskUpdated { task: TaskOut },
+    TaskDone { id: task::Id },
+    TaskUndone { id: task::Id },
+    TaskMoved { id: task::Id, position: usize },
+    TaskDeleted { id: task::Id },
+    ListRenamed { task_list: TaskListOut },
+    /// Nothing follows it.
+    ListDeleted,
+    /// Some changes were missed, the whole list should be fetched again.
+    Lagged,
+}
+
+#[derive(Clone)]
+enum Message {
+    Change(Change),
+    /// The user may not see the list any more, so their subscriptions end.
+    Ended(user::Id),
+}
+
+type Channels = HashMap<task_list::Id, broadcast::Sender<Message>>;
+type Pending = Vec<(task_list::Id, Change)>;
+
+/// Changes of every list, published by the DAOs once they are made.
+#[derive(Clone)]
+pub struct ChangeBus {
+    /// Channel of every list somebody subscribed to, so changes only wake the subscribers of their list.
+    channels: Arc<Mutex<Channels>>,
+    /// Changes of a unit of work, kept until it is committed.
+    pending: Option<Arc<Mutex<Pending>>>,
+}
+
+impl ChangeBus {
+    pub fn new() -> Self {
+        Self { channels: Arc::default(), pending: None }
+    }
+
+    /// Bus which keeps the changes published to it until `flush`.
+    pub fn deferred(&self) -> Self {
+        Self { channels: self.channels.clone(), pending: Some(Arc::default()) }
+    }
+
+    fn send(&self, task_list_id: task_list::Id, message: Message) {
+        let mut channels = self.channels.lock().unwrap();
+        // Fails only if nobody listens any more.
+        if channels.get(&task_list_id).is_some_and(|sender| sender.send(message).is_err()) {
+            channels.remove(&task_list_id);
+        }
+    }
+
+    pub fn publish(&self, task_list_id: task_list::Id, change: Change) {
+        match &self.pending {
+            Some(pending) => pending.lock().unwrap().push((task_list_id, change)),
+            None => self.send(task_list_id, Message::Change(change)),
+        }
+    }
+
+    /// Sends the changes kept by a `deferred` bus.
+    pub fn flush(&self) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        for (task_list_id, change) in pending.lock().unwrap().drain(..) {
+            self.send(task_list_id, Message::Change(change));
+        }
+    }
+
+    /// Ends the subscriptions of `user_id` to the list, access is only checked when they start.
+    pub fn end_access(&self, task_list_id: task_list::Id, user_id: user::Id) {
+        self.send(task_list_id, Message::Ended(user_id));
+    }
+
+    /// Ends every subscription of `user_id`, e.g. when they log out. Pages of their other sessions subscribe again.
+    pub fn end_user(&self, user_id: user::Id) {
+        self.channels.lock().unwrap().retain(|_, sender| sender.send(Message::Ended(user_id)).is_ok());
+    }
+
+    /// Changes of one list seen by `user_id` from now on, ending after `ListDeleted` or once their access ends.
+    pub fn subscribe(&self, task_list_id: task_list::Id, user_id: user::Id) -> impl Stream<Item=Change> {
+        let receiver = {
+            let mut channels = self.channels.lock().unwrap();
+            channels.retain(|_, sender| sender.receiver_count() > 0);
+            channels.entry(task_list_id).or_insert_with(|| broadcast::channel(CAPACITY).0).subscribe()
+        };
+        futures::stream::unfold(Some(receiver), move |receiver| async move {
+            let mut receiver = receiver?;
+            loop {
+                let change = match receiver.recv().await {
+                    Ok(Message::Change(change)) => change,
+                    Ok(Message::Ended(id)) if id == user_id => return None,
+                    Ok(Message::Ended(_)) => continue,
+                    Err(RecvError::Lagged(_)) => Change::Lagged,
+                    Err(RecvError::Closed) => return None,
+                };
+                let receiver = (!matches!(change, Change::ListDeleted)).then_some(receiver);
+                return Some((change, receiver));
+            }
+        })
+    }
+}
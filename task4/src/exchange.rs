@@ -0,0 +1,309 @@
+use std::fmt::{Display, Formatter};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::error::TaskListError;
+use crate::model::task::{Priority, TaskIn};
+use crate::model::task_list::{TaskListIn, TaskListWithTasks};
+use crate::model::validation::{Validate, MAX_TASKS};
+
+mod csv;
+mod markdown;
+mod todo_txt;
+
+/// Tasks of a file which names no list are imported into it.
+pub const DEFAULT_LIST: &str = "Inbox";
+
+/// Format of exported and imported files.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// The lists as the API returns them.
+    #[default]
+    Json,
+    /// A row per task with its list.
+    Csv,
+    /// A `#` heading per list with a `- [ ]` item per task.
+    Markdown,
+    /// A line per task, the list is its first `+project`.
+    #[serde(rename = "todotxt")]
+    TodoTxt,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Markdown => "text/markdown; charset=utf-8",
+            Format::TodoTxt => "text/plain; charset=utf-8",
+        }
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Markdown => "md",
+            Format::TodoTxt => "txt",
+        }
+    }
+}
+
+/// Task of an imported file, checked like the input of the API.
+#[derive(Deserialize, Clone)]
+pub struct ImportedTask {
+    #[serde(flatten)]
+    pub core: TaskIn,
+    #[serde(default)]
+    pub done: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ImportedList {
+    #[serde(flatten)]
+    pub core: TaskListIn,
+    #[serde(default)]
+    pub tasks: Vec<ImportedTask>,
+}
+
+/// Why a line of an imported file is rejected.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct ImportError {
+    /// Missing for JSON files, whose message tells the list and task instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Every problem found in an imported file, in the order of its lines.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(transparent)]
+pub struct ImportErrors(Vec<ImportError>);
+
+impl ImportErrors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for ImportErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match error.line {
+                Some(line) => write!(f, "line {line}: {}", error.message)?,
+                None => f.write_str(&error.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The list in the format, JSON is a single object.
+pub fn export_list(format: Format, task_list: &TaskListWithTasks) -> String {
+    match format {
+        Format::Json => serde_json::to_string_pretty(task_list).unwrap(),
+        _ => export_lists(format, std::slice::from_ref(task_list)),
+    }
+}
+
+/// The lists in the format, JSON is an array.
+pub fn export_lists(format: Format, task_lists: &[TaskListWithTasks]) -> String {
+    match format {
+        Format::Json => serde_json::to_string_pretty(task_lists).unwrap(),
+        Format::Csv => csv::export(task_lists),
+        Format::Markdown => markdown::export(task_lists),
+        Format::TodoTxt => todo_txt::export(task_lists),
+    }
+}
+
+/// Lists of the file, checked to be valid and not too long.
+/// JSON may hold an array of lists or a single one, like the exported files.
+pub fn import(format: Format, data: &str) -> Result<Vec<ImportedList>, ImportErrors> {
+    let mut importer = Importer::default();
+    match format {
+        Format::Json => import_json(&mut importer, data),
+        Format::Csv => csv::import(&mut importer, data),
+        Format::Markdown => markdown::import(&mut importer, data),
+        Format::TodoTxt => todo_txt::import(&mut importer, data),
+    }
+    importer.finish()
+}
+
+fn import_json(importer: &mut Importer, data: &str) {
+    let task_lists = if data.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<ImportedList>>(data)
+    } else {
+        serde_json::from_str::<ImportedList>(data).map(|task_list| vec![task_list])
+    };
+    let task_lists = match task_lists {
+        Ok(task_lists) => task_lists,
+        Err(err) => return importer.error(Some(err.line()), err.to_string()),
+    };
+    for (i, task_list) in task_lists.into_iter().enumerate() {
+        let Some(list) = importer.list(None, task_list.core.name) else {
+            continue;
+        };
+        for (j, task) in task_list.tasks.into_iter().enumerate() {
+            importer.task_at(None, &format!("list {}, task {}: ", i + 1, j + 1), list, task.core, task.done);
+        }
+    }
+}
+
+/// Collects the lists of a file with the errors of its lines.
+#[derive(Default)]
+struct Importer {
+    task_lists: Vec<ImportedList>,
+    errors: ImportErrors,
+}
+
+impl Importer {
+    fn error(&mut self, line: Option<usize>, message: impl Into<String>) {
+        self.errors.0.push(ImportError { line, message: message.into() });
+    }
+
+    /// Index of the list with the name, added unless the file already has one.
+    fn list(&mut self, line: Option<usize>, name: String) -> Option<usize> {
+        let core = match (TaskListIn { name }).validate() {
+            Ok(core) => core,
+            Err(err) => {
+                self.error(line, format!("list {}", invalid_message(err)));
+                return None;
+            }
+        };
+        let index = self.task_lists.iter().position(|x| x.core.name == core.name).unwrap_or_else(|| {
+            self.task_lists.push(ImportedList { core, tasks: vec![] });
+            self.task_lists.len() - 1
+        });
+        Some(index)
+    }
+
+    fn task(&mut self, line: usize, list: usize, task: TaskIn, done: bool) {
+        self.task_at(Some(line), "", list, task, done);
+    }
+
+    /// `prefix` tells where the task is if there is no `line`.
+    fn task_at(&mut self, line: Option<usize>, prefix: &str, list: usize, task: TaskIn, done: bool) {
+        let core = match task.validate() {
+            Ok(core) => core,
+            Err(err) => return self.error(line, format!("{prefix}{}", invalid_message(err))),
+        };
+        let task_list = &mut self.task_lists[list];
+        if task_list.tasks.len() >= MAX_TASKS {
+            let message = format!("{prefix}list `{}` has more than {MAX_TASKS} tasks", task_list.core.name);
+            return self.error(line, message);
+        }
+        task_list.tasks.push(ImportedTask { core, done });
+    }
+
+    fn finish(mut self) -> Result<Vec<ImportedList>, ImportErrors> {
+        if self.errors.is_empty() && self.task_lists.is_empty() {
+            self.error(None, "the file has no lists");
+        }
+        if self.errors.is_empty() { Ok(self.task_lists) } else { Err(self.errors) }
+    }
+}
+
+/// Fields of `Invalid` as a sentence like "name must not be empty".
+fn invalid_message(err: TaskListError) -> String {
+    match err {
+        TaskListError::Invalid(errors) => errors.to_string(),
+        err => err.to_string(),
+    }
+}
+
+/// Priority by its name in any case.
+fn parse_priority(value: &str) -> Option<Priority> {
+    Priority::ALL.into_iter().find(|priority| priority.as_str().eq_ignore_ascii_case(value))
+}
+
+/// Date like `2024-12-31`.
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("`{value}` is not a date like 2024-12-31"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::model::task::TaskOut;
+
+    fn task_lists() -> Vec<TaskListWithTasks> {
+        let now = Utc::now();
+        let mut done = TaskOut::new(Uuid::new_v4(), TaskIn {
+            name: "Call \"Bob\", again".to_owned(),
+            description: Some("first line\nsecond line".to_owned()),
+            due: NaiveDate::from_ymd_opt(2024, 12, 31),
+            priority: Priority::High,
+            tags: vec!["work".to_owned(), "phone calls".to_owned()],
+            recurrence: None,
+        }, now);
+        done.set_done(true, now);
+        let todo = TaskOut::new(Uuid::new_v4(), TaskIn {
+            name: "Buy milk".to_owned(),
+            recurrence: Some("FREQ=WEEKLY;BYDAY=SA".to_owned()),
+            ..Default::default()
+        }, now);
+        vec![
+            TaskListWithTasks { id: Uuid::new_v4(), owner: Uuid::new_v4(), core: TaskListIn { name: "Home".to_owned() },
+                                tasks: vec![done, todo] },
+            TaskListWithTasks { id: Uuid::new_v4(), owner: Uuid::new_v4(), core: TaskListIn { name: "Empty".to_owned() },
+                                tasks: vec![] },
+        ]
+    }
+
+    /// Lists as `(name, tasks)` with `(name, done)` of each task.
+    fn summary(task_lists: &[ImportedList]) -> Vec<(&str, Vec<(&str, bool)>)> {
+        task_lists.iter()
+            .map(|x| (x.core.name.as_str(), x.tasks.iter().map(|x| (x.core.name.as_str(), x.done)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let task_lists = task_lists();
+        for format in [Format::Json, Format::Csv, Format::Markdown, Format::TodoTxt] {
+            let imported = import(format, &export_lists(format, &task_lists)).unwrap();
+            let home = vec![("Call \"Bob\", again", true), ("Buy milk", false)];
+            // Only JSON and Markdown keep lists without tasks.
+            match format {
+                Format::Json | Format::Markdown => assert_eq!(summary(&imported), [("Home", home), ("Empty", vec![])]),
+                Format::Csv | Format::TodoTxt => assert_eq!(summary(&imported), [("Home", home)]),
+            }
+            let task = &imported[0].tasks[0].core;
+            if format != Format::Markdown {
+                assert_eq!(task.due, NaiveDate::from_ymd_opt(2024, 12, 31));
+                assert_eq!(task.priority, Priority::High);
+                assert_eq!(task.tags, ["work", "phone calls"]);
+            }
+            if matches!(format, Format::Json | Format::Csv | Format::Markdown) {
+                assert_eq!(task.description.as_deref(), Some("first line\nsecond line"));
+            }
+            if format != Format::Markdown {
+                assert_eq!(imported[0].tasks[1].core.recurrence.as_deref(), Some("FREQ=WEEKLY;BYDAY=SA"));
+            }
+        }
+
+        let imported = import(Format::Json, &export_list(Format::Json, &task_lists[0])).unwrap();
+        assert_eq!(summary(&imported)[0].0, "Home");
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let errors = import(Format::Json, "[{\"name\": \"list\", \"tasks\": [{\"name\": \" \"}]}]").err().unwrap();
+        assert_eq!(errors.to_string(), "list 1, task 1: name must not be empty");
+        let errors = import(Format::Json, "[\n{\"name\": }]").err().unwrap();
+        assert_eq!(errors.0[0].line, Some(2));
+        assert_eq!(import(Format::Json, "[]").err().unwrap().to_string(), "the file has no lists");
+
+        let tasks = (0..=MAX_TASKS).map(|i| format!("- [ ] task {i}")).collect::<Vec<_>>().join("\n");
+        let errors = import(Format::Markdown, &format!("# full\n{tasks}")).err().unwrap();
+        assert_eq!(errors.to_string(), format!("line {}: list `full` has more than {MAX_TASKS} tasks", MAX_TASKS + 2));
+    }
+}
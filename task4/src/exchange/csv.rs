@@ -0,0 +1,175 @@
+use crate::model::task::TaskIn;
+use crate::model::task_list::TaskListWithTasks;
+
+use super::{parse_date, parse_priority, Importer, DEFAULT_LIST};
+
+const HEADER: [&str; 8] = ["list", "name", "description", "due", "priority", "tags", "recurrence", "done"];
+
+/// A header row and a row per task, quoted as in RFC 4180.
+pub fn export(task_lists: &[TaskListWithTasks]) -> String {
+    let mut out = String::new();
+    write_record(&mut out, HEADER.map(str::to_owned));
+    for task_list in task_lists {
+        for task in &task_list.tasks {
+            write_record(&mut out, [
+                task_list.core.name.clone(),
+                task.core.name.clone(),
+                task.core.description.clone().unwrap_or_default(),
+                task.core.due.map(|due| due.to_string()).unwrap_or_default(),
+                task.core.priority.as_str().to_owned(),
+                task.core.tags.join(", "),
+                task.core.recurrence.clone().unwrap_or_default(),
+                task.done.to_string(),
+            ]);
+        }
+    }
+    out
+}
+
+fn write_record(out: &mut String, fields: [String; 8]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// Columns are found by the names of the header, only `name` is required.
+/// Rows without a `list` go to the default list.
+pub(super) fn import(importer: &mut Importer, data: &str) {
+    let records = match read_records(data) {
+        Ok(records) => records,
+        Err((line, message)) => return importer.error(Some(line), message),
+    };
+    let mut records = records.into_iter();
+    let Some((_, header)) = records.next() else {
+        return;
+    };
+    let column = |name: &str| header.iter().position(|x| x.trim().eq_ignore_ascii_case(name));
+    let [list, name, description, due, priority, tags, recurrence, done] = HEADER.map(column);
+    let Some(name) = name else {
+        return importer.error(Some(1), "the header has no `name` column");
+    };
+
+    for (line, record) in records {
+        let field = |column: Option<usize>| {
+            column.and_then(|i| record.get(i)).map(|x| x.trim()).filter(|x| !x.is_empty())
+        };
+        let mut task = TaskIn {
+            name: record.get(name).cloned().unwrap_or_default(),
+            description: field(description).map(str::to_owned),
+            tags: field(tags).map(|x| x.split(',').map(|tag| tag.trim().to_owned()).collect()).unwrap_or_default(),
+            recurrence: field(recurrence).map(str::to_owned),
+            ..Default::default()
+        };
+        if let Some(value) = field(due) {
+            match parse_date(value) {
+                Ok(due) => task.due = Some(due),
+                Err(message) => {
+                    importer.error(Some(line), format!("due {message}"));
+                    continue;
+                }
+            }
+        }
+        if let Some(value) = field(priority) {
+            match parse_priority(value) {
+                Some(priority) => task.priority = priority,
+                None => {
+                    importer.error(Some(line), format!("priority `{value}` is not low, normal or high"));
+                    continue;
+                }
+            }
+        }
+        let done = match field(done).map(str::to_ascii_lowercase).as_deref() {
+            None | Some("false" | "no" | "0") => false,
+            Some("true" | "yes" | "1" | "x") => true,
+            Some(value) => {
+                importer.error(Some(line), format!("done `{value}` is not true or false"));
+                continue;
+            }
+        };
+        let list_name = field(list).unwrap_or(DEFAULT_LIST).to_owned();
+        if let Some(list) = importer.list(Some(line), list_name) {
+            importer.task(line, list, task, done);
+        }
+    }
+}
+
+/// Fields of a row with the line it starts at.
+type Record = (usize, Vec<String>);
+
+/// Records of the file, empty lines are skipped. Fails with the line of an unclosed quote.
+fn read_records(data: &str) -> Result<Vec<Record>, (usize, String)> {
+    let mut records = vec![];
+    let mut chars = data.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut record = vec![];
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() => loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err((start, "a quoted field is not closed".to_owned())),
+                    }
+                },
+                Some(',') => record.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if !record.is_empty() || !field.trim().is_empty() {
+            record.push(field);
+            records.push((start, record));
+        }
+    }
+    Ok(records)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::super::{import, Format};
+
+    #[test]
+    fn test_import() {
+        let errors = import(Format::Csv, "name,due\nBuy milk,\n\n\"open,").err().unwrap();
+        assert_eq!(errors.to_string(), "line 4: a quoted field is not closed");
+
+        let data = "Name,Done,Due\r\n\"Buy \"\"oat\"\" milk\",yes,\n\nCall Bob,,tomorrow\n,false,\n\"two\nlines\",maybe,";
+        let errors = import(Format::Csv, data).err().unwrap();
+        assert_eq!(errors.to_string(), "line 4: due `tomorrow` is not a date like 2024-12-31, \
+                                        line 5: name must not be empty, line 6: done `maybe` is not true or false");
+
+        let task_lists = import(Format::Csv, "list,name,done\nHome,\"Buy \"\"oat\"\" milk\",yes\n,Read mail,").unwrap();
+        assert_eq!(task_lists.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["Home", "Inbox"]);
+        assert_eq!(task_lists[0].tasks[0].core.name, "Buy \"oat\" milk");
+        assert!(task_lists[0].tasks[0].done);
+        assert_eq!(import(Format::Csv, "list,done\nHome,no").err().unwrap().to_string(),
+                   "line 1: the header has no `name` column");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::model::task::TaskIn;
+use crate::model::task_list::TaskListWithTasks;
+
+use super::{Importer, DEFAULT_LIST};
+
+/// A `#` heading per list and a checklist item per task, the description is indented below it.
+/// Due dates, priorities, tags and recurrences are left out.
+pub fn export(task_lists: &[TaskListWithTasks]) -> String {
+    let mut out = String::new();
+    for (i, task_list) in task_lists.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("# {}\n\n", task_list.core.name));
+        for task in &task_list.tasks {
+            let check = if task.done { 'x' } else { ' ' };
+            out.push_str(&format!("- [{check}] {}\n", task.core.name));
+            for line in task.core.description.iter().flat_map(|x| x.lines()) {
+                out.push_str(&format!("  {line}\n"));
+            }
+        }
+    }
+    out
+}
+
+/// Headings of any level up to `##` start lists, items before them go to the default list.
+/// Indented lines below an item are its description.
+pub(super) fn import(importer: &mut Importer, data: &str) {
+    let mut list = None;
+    // Item being read with the line it starts at, kept until its description ends.
+    let mut item: Option<(usize, TaskIn, bool)> = None;
+    // Empty lines since the last one, they belong to a description which goes on after them.
+    let mut empty = 0;
+    for (i, text) in data.lines().enumerate() {
+        let line = i + 1;
+        if text.trim().is_empty() {
+            empty += 1;
+            continue;
+        }
+        if text.starts_with([' ', '\t']) {
+            if let Some((_, task, _)) = &mut item {
+                let description = task.description.get_or_insert_with(String::new);
+                if !description.is_empty() {
+                    description.push_str(&"\n".repeat(empty + 1));
+                }
+                description.push_str(text.trim());
+                empty = 0;
+                continue;
+            }
+        }
+        empty = 0;
+        if let Some((line, task, done)) = item.take() {
+            add_task(importer, &mut list, line, task, done);
+        }
+        let text = text.trim();
+        if let Some(name) = text.strip_prefix("## ").or_else(|| text.strip_prefix("# ")) {
+            list = Some(importer.list(Some(line), name.to_owned()));
+        } else if let Some((name, done)) = parse_item(text) {
+            item = Some((line, TaskIn { name: name.to_owned(), ..Default::default() }, done));
+        } else {
+            importer.error(Some(line), "expected a `# list` heading or a `- [ ] task` item");
+        }
+    }
+    if let Some((line, task, done)) = item {
+        add_task(importer, &mut list, line, task, done);
+    }
+}
+
+/// Name and whether it is checked of an item like `- [x] name` or `* [ ] name`.
+fn parse_item(text: &str) -> Option<(&str, bool)> {
+    let text = text.strip_prefix("- ").or_else(|| text.strip_prefix("* "))?;
+    let done = match text.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    Some((&text[3..], done))
+}
+
+/// Tasks of a list whose heading is invalid are skipped, as its heading is already reported.
+fn add_task(importer: &mut Importer, list: &mut Option<Option<usize>>, line: usize, task: TaskIn, done: bool) {
+    let list = *list.get_or_insert_with(|| importer.list(Some(line), DEFAULT_LIST.to_owned()));
+    if let Some(list) = list {
+        importer.task(line, list, task, done);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::super::{import, Format};
+
+    #[test]
+    fn test_import() {
+        let task_lists = import(Format::Markdown, "* [X] Read mail\n\n## Home\n- [ ] Buy milk\n  oat\n\n  not skimmed\n").unwrap();
+        assert_eq!(task_lists.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["Inbox", "Home"]);
+        assert!(task_lists[0].tasks[0].done);
+        assert_eq!(task_lists[1].tasks[0].core.description.as_deref(), Some("oat\n\nnot skimmed"));
+
+        let errors = import(Format::Markdown, "# a\u{7}b\n- [ ] skipped\n# Home\n- [ ] \nsome text\n- [?] unknown").err().unwrap();
+        assert_eq!(errors.to_string(), "line 1: list name must not contain control characters, \
+                                        line 4: name must not be empty, \
+                                        line 5: expected a `# list` heading or a `- [ ] task` item, \
+                                        line 6: expected a `# list` heading or a `- [ ] task` item");
+    }
+}
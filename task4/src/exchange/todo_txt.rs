@@ -0,0 +1,137 @@
+use crate::model::task::{Priority, TaskIn};
+use crate::model::task_list::TaskListWithTasks;
+
+use super::{parse_date, Importer, DEFAULT_LIST};
+
+/// A line per task like `(A) 2024-12-01 Call Bob due:2024-12-31 rrule:FREQ=WEEKLY @phone +Home`.
+/// Spaces of lists and tags become `_`, descriptions are left out.
+pub fn export(task_lists: &[TaskListWithTasks]) -> String {
+    let mut out = String::new();
+    for task_list in task_lists {
+        for task in &task_list.tasks {
+            let priority = match task.core.priority {
+                Priority::High => "A",
+                Priority::Normal => "B",
+                Priority::Low => "C",
+            };
+            let created = task.created_at.date_naive();
+            match task.completed_at {
+                // Done tasks start with `x` and their dates, so their priority moves to a `pri:` tag.
+                Some(completed) if task.done => {
+                    out.push_str(&format!("x {} {created} {}", completed.date_naive(), task.core.name));
+                    if task.core.priority != Priority::Normal {
+                        out.push_str(&format!(" pri:{priority}"));
+                    }
+                }
+                _ => {
+                    if task.core.priority != Priority::Normal {
+                        out.push_str(&format!("({priority}) "));
+                    }
+                    out.push_str(&format!("{created} {}", task.core.name));
+                }
+            }
+            if let Some(due) = task.core.due {
+                out.push_str(&format!(" due:{due}"));
+            }
+            if let Some(recurrence) = &task.core.recurrence {
+                out.push_str(&format!(" rrule:{recurrence}"));
+            }
+            for tag in &task.core.tags {
+                out.push_str(&format!(" @{}", tag.replace(' ', "_")));
+            }
+            out.push_str(&format!(" +{}\n", task_list.core.name.replace(' ', "_")));
+        }
+    }
+    out
+}
+
+/// The first `+project` of a task is its list, tasks without one go to the default list.
+/// Dates of creation and completion are not kept, as the tasks are created again.
+pub(super) fn import(importer: &mut Importer, data: &str) {
+    for (i, text) in data.lines().enumerate() {
+        let line = i + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let mut words = text.split_whitespace().peekable();
+        let done = words.next_if_eq(&"x").is_some();
+        let mut task = TaskIn::default();
+        if let Some(priority) = words.next_if(|word| word.len() == 3 && word.starts_with('(') && word.ends_with(')')
+            && is_letter(&word[1..2])) {
+            task.priority = to_priority(&priority[1..2]);
+        }
+        // Completion and creation dates.
+        while words.next_if(|word| parse_date(word).is_ok()).is_some() {}
+
+        let mut list = None;
+        let mut name = vec![];
+        let mut error = None;
+        for word in words {
+            if let Some(project) = word.strip_prefix('+').filter(|x| !x.is_empty() && list.is_none()) {
+                list = Some(project.replace('_', " "));
+            } else if let Some(tag) = word.strip_prefix('@').filter(|x| !x.is_empty()) {
+                task.tags.push(tag.replace('_', " "));
+            } else if let Some(due) = word.strip_prefix("due:") {
+                match parse_date(due) {
+                    Ok(due) => task.due = Some(due),
+                    Err(message) => error = Some(format!("due {message}")),
+                }
+            } else if let Some(recurrence) = word.strip_prefix("rrule:").filter(|x| !x.is_empty()) {
+                task.recurrence = Some(recurrence.to_owned());
+            } else if let Some(priority) = word.strip_prefix("pri:").filter(|x| is_letter(x)) {
+                task.priority = to_priority(priority);
+            } else {
+                name.push(word);
+            }
+        }
+        if let Some(message) = error {
+            importer.error(Some(line), message);
+            continue;
+        }
+        task.name = name.join(" ");
+        if let Some(list) = importer.list(Some(line), list.unwrap_or_else(|| DEFAULT_LIST.to_owned())) {
+            importer.task(line, list, task, done);
+        }
+    }
+}
+
+/// Letter of a priority like `(A)` or `pri:A`.
+fn is_letter(value: &str) -> bool {
+    matches!(value.as_bytes(), [b'A'..=b'Z'])
+}
+
+/// `A` is high and `B` normal, any later letter is low.
+fn to_priority(letter: &str) -> Priority {
+    match letter {
+        "A" => Priority::High,
+        "B" => Priority::Normal,
+        _ => Priority::Low,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::super::{import, Format};
+    use crate::model::task::Priority;
+
+    #[test]
+    fn test_import() {
+        let data = "(D) 2024-11-30 Read mail @home\n\nx 2024-12-02 Call mom +Family_Stuff +Work pri:A\nBuy milk due:2024-12-31";
+        let task_lists = import(Format::TodoTxt, data).unwrap();
+        assert_eq!(task_lists.iter().map(|x| x.core.name.as_str()).collect::<Vec<_>>(), ["Inbox", "Family Stuff"]);
+        let read_mail = &task_lists[0].tasks[0];
+        assert_eq!((read_mail.core.name.as_str(), read_mail.core.priority), ("Read mail", Priority::Low));
+        assert_eq!(read_mail.core.tags, ["home"]);
+        assert_eq!(task_lists[0].tasks[1].core.due, NaiveDate::from_ymd_opt(2024, 12, 31));
+        let call_mom = &task_lists[1].tasks[0];
+        assert_eq!((call_mom.core.name.as_str(), call_mom.done), ("Call mom +Work", true));
+        assert_eq!(call_mom.core.priority, Priority::High);
+
+        let errors = import(Format::TodoTxt, "Buy milk due:friday\n(A) +Home").err().unwrap();
+        assert_eq!(errors.to_string(), "line 1: due `friday` is not a date like 2024-12-31, \
+                                        line 2: name must not be empty");
+    }
+}
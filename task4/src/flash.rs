@@ -0,0 +1,36 @@
+use actix_session::Session;
+use serde::{Deserialize, Serialize};
+
+use crate::model::validation::FieldErrors;
+
+/// Session entry with the message for the next page.
+const FLASH_KEY: &str = "flash";
+/// Session entry with the invalid fields of the last submitted form.
+const FORM_ERRORS_KEY: &str = "form_errors";
+
+/// Invalid fields of a form, shown next to them on the next page.
+#[derive(Serialize, Deserialize)]
+pub struct FormErrors {
+    /// Name the page gives the form, to tell it from the other forms with the same fields.
+    pub form: String,
+    pub errors: FieldErrors,
+}
+
+/// Keeps `message` until the next page shows it, a newer message replaces an older one.
+pub fn push(session: &Session, message: impl Into<String>) {
+    // Losing a message is not worth failing the request for.
+    let _ = session.insert(FLASH_KEY, message.into());
+}
+
+/// Message left by the previous request, removed once read.
+pub fn take(session: &Session) -> Option<String> {
+    session.remove_as::<String>(FLASH_KEY).and_then(Result::ok)
+}
+
+pub fn push_errors(session: &Session, form: &str, errors: &FieldErrors) {
+    let _ = session.insert(FORM_ERRORS_KEY, FormErrors { form: form.to_owned(), errors: errors.clone() });
+}
+
+pub fn take_errors(session: &Session) -> Option<FormErrors> {
+    session.remove_as::<FormErrors>(FORM_ERRORS_KEY).and_then(Result::ok)
+}
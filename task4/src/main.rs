@@ -1,39 +1,143 @@
-use std::sync::Arc;
+use std::path::PathBuf;
 
+use actix_session::SessionMiddleware;
+use actix_session::storage::CookieSessionStore;
 use actix_web::{App, HttpServer};
-use actix_web::web::Data;
+use actix_web::cookie::Key;
+use actix_web::middleware::from_fn;
+use actix_web::web::{Data, ServiceConfig};
+use clap::{Parser, ValueEnum};
 
-use crate::dao::{TaskDao, TaskListDao, TaskListMemoryState};
-use crate::dep_middleware::{DependencyFactory, MemoryTaskListDaoFactory, MemoryTaskDaoFactory};
+use crate::dao::TaskListMemoryState;
+use crate::dao::file::FileState;
+use crate::dao::postgres::{create_pool, migrate};
+use crate::dao::sqlite::SqliteState;
+use crate::dep_middleware::{register_file, register_memory, register_notifying, register_postgres, register_sqlite,
+                            Container};
+use crate::events::ChangeBus;
 
+mod api;
+mod auth;
+mod csrf;
+mod flash;
 mod model;
 mod view;
 mod controller;
 mod dao;
 mod dep_middleware;
 mod error;
+mod events;
+mod exchange;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Storage {
+    /// Nothing is kept after restart.
+    Memory,
+    /// JSON snapshot rewritten on every change.
+    File,
+    Sqlite,
+    Postgres,
+}
+
+#[derive(Parser)]
+struct Args {
+    #[arg(long, value_enum, env = "STORAGE", default_value = "memory")]
+    storage: Storage,
+    /// Postgres connection string, e.g. `host=localhost user=postgres`.
+    #[arg(long, env = "DATABASE_URL", required_if_eq("storage", "postgres"))]
+    database_url: Option<String>,
+    /// SQLite database or JSON snapshot, created if missing.
+    #[arg(long, env = "STORAGE_PATH", required_if_eq_any([("storage", "sqlite"), ("storage", "file")]))]
+    storage_path: Option<PathBuf>,
+    /// Signs and encrypts session cookies, at least 64 bytes. A random one logs everybody out on restart.
+    #[arg(long, env = "SESSION_KEY")]
+    session_key: Option<String>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Memory(TaskListMemoryState),
+    File(FileState),
+    Sqlite(SqliteState),
+    Postgres(deadpool_postgres::Pool),
+}
+
+impl Backend {
+    async fn open(args: &Args) -> anyhow::Result<Self> {
+        Ok(match args.storage {
+            Storage::Memory => Backend::Memory(TaskListMemoryState::new()),
+            Storage::File => Backend::File(FileState::open(args.storage_path.clone().unwrap())?),
+            Storage::Sqlite => Backend::Sqlite(SqliteState::open(args.storage_path.as_ref().unwrap())?),
+            Storage::Postgres => {
+                let pool = create_pool(args.database_url.as_ref().unwrap().parse()?)?;
+                migrate(&pool).await?;
+                Backend::Postgres(pool)
+            }
+        })
+    }
+
+    /// Registers the DAOs of the backend, which publish changes to `bus`.
+    fn configure(&self, cfg: &mut ServiceConfig, bus: &ChangeBus) {
+        let mut container = Container::new();
+        match self {
+            Backend::Memory(state) => register_memory(&mut container, state.clone()),
+            Backend::File(state) => register_file(&mut container, state.clone()),
+            Backend::Sqlite(state) => register_sqlite(&mut container, state.clone()),
+            Backend::Postgres(pool) => register_postgres(&mut container, pool.clone()),
+        }
+        register_notifying(&mut container, bus.clone());
+        cfg.app_data(Data::new(container));
+    }
+}
+
+fn session_key(args: &Args) -> anyhow::Result<Key> {
+    match &args.session_key {
+        Some(key) => Key::try_from(key.as_bytes()).map_err(|_| anyhow::anyhow!("session key is shorter than 64 bytes")),
+        None => Ok(Key::generate()),
+    }
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let memory_state = TaskListMemoryState::new();
+    let args = Args::parse();
+    let key = session_key(&args).map_err(std::io::Error::other)?;
+    let backend = Backend::open(&args).await.map_err(std::io::Error::other)?;
+    // Shared by the workers, so a change reaches pages served by any of them.
+    let bus = ChangeBus::new();
 
     HttpServer::new(move || {
-        let task_list_dao: Arc<dyn DependencyFactory<dyn TaskListDao>> =
-            Arc::new(MemoryTaskListDaoFactory {});
-        let task_dao: Arc<dyn DependencyFactory<dyn TaskDao>> =
-            Arc::new(MemoryTaskDaoFactory {});
-
         App::new()
-            .app_data(memory_state.clone())
-            .app_data(Data::from(task_list_dao))
-            .app_data(Data::from(task_dao))
+            .configure(|cfg| backend.configure(cfg, &bus))
+            .app_data(Data::new(bus.clone()))
+            .wrap(from_fn(csrf::verify))
+            // Served over plain HTTP, so the cookie can't be `Secure`.
+            .wrap(SessionMiddleware::builder(CookieSessionStore::default(), key.clone())
+                .cookie_secure(false)
+                .build())
+            .service(api::scope())
+            .service(controller::get_login)
+            .service(controller::log_in)
+            .service(controller::get_register)
+            .service(controller::register)
+            .service(controller::log_out)
             .service(controller::get_todo_lists)
+            .service(controller::update_settings)
             .service(controller::add_task_list)
             .service(controller::delete_task_list)
             .service(controller::get_task_list)
             .service(controller::add_task)
-            .service(controller::mark_task_as_done
-            )
+            .service(controller::rename_task_list)
+            .service(controller::mark_task_as_done)
+            .service(controller::toggle_task)
+            .service(controller::update_task)
+            .service(controller::delete_task)
+            .service(controller::move_task)
+            .service(controller::get_members)
+            .service(controller::invite_member)
+            .service(controller::revoke_member)
+            .service(controller::get_invitations)
+            .service(controller::accept_invitation)
+            .service(controller::decline_invitation)
     })
         .bind(("127.0.0.1", 8080))?
         .run()
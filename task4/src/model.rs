@@ -1,2 +1,7 @@
+pub mod member;
+pub mod query;
+pub mod recurrence;
 pub mod task;
 pub mod task_list;
+pub mod user;
+pub mod validation;
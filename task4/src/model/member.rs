@@ -0,0 +1,79 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{TaskListError, TaskListResult};
+
+use super::task_list;
+use super::task_list::TaskListOut;
+use super::user::User;
+
+/// What a user may do with a list, each role may do everything the previous ones may.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Reads the list and its tasks.
+    Viewer,
+    /// Also changes tasks and renames the list.
+    Editor,
+    /// Also deletes and shares the list, not given by sharing.
+    Owner,
+}
+
+impl Role {
+    /// Roles lists are shared with.
+    pub const SHARED: [Role; 2] = [Role::Viewer, Role::Editor];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    /// Text kept in databases, unknown roles are treated as the least allowed one.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "editor" => Role::Editor,
+            "owner" => Role::Owner,
+            _ => Role::Viewer,
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Passes if `role`, which is `None` for strangers, allows what `required` does.
+pub fn check_role(task_list_id: task_list::Id, role: Option<Role>, required: Role) -> TaskListResult<Role> {
+    match role {
+        Some(role) if role >= required => Ok(role),
+        _ => Err(TaskListError::Forbidden(task_list_id)),
+    }
+}
+
+/// Invitation by the owner.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MemberIn {
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Member {
+    pub user: User,
+    pub role: Role,
+    /// Invited users get their role once they accept.
+    pub accepted: bool,
+}
+
+/// List the user is invited to and has not accepted yet.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Invitation {
+    pub task_list: TaskListOut,
+    pub role: Role,
+}
@@ -0,0 +1,316 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+use crate::error::{TaskListError, TaskListResult};
+
+use super::task::TaskOut;
+use super::task_list::TaskListOut;
+
+/// Due date of tasks without one when sorting, so they go last.
+pub const NO_DUE: &str = "9999-12-31";
+
+/// Most items on a page, larger limits are cut to it.
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskSort {
+    /// Order set by moving tasks.
+    #[default]
+    Position,
+    Created,
+    Due,
+    Priority,
+    Name,
+}
+
+impl TaskSort {
+    pub const ALL: [TaskSort; 5] = [TaskSort::Position, TaskSort::Created, TaskSort::Due,
+                                    TaskSort::Priority, TaskSort::Name];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskSort::Position => "position",
+            TaskSort::Created => "created",
+            TaskSort::Due => "due",
+            TaskSort::Priority => "priority",
+            TaskSort::Name => "name",
+        }
+    }
+}
+
+impl Display for TaskSort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Order {
+    pub const ALL: [Order; 2] = [Order::Asc, Order::Desc];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        }
+    }
+}
+
+impl Display for Order {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Value a task is sorted by, one variant per `TaskSort`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Position(i64),
+    Created(DateTime<Utc>),
+    Due(NaiveDate),
+    Priority(i16),
+    Name(String),
+}
+
+impl SortKey {
+    /// `position` is where the task is kept in its list, only used by `TaskSort::Position`.
+    pub fn of(task: &TaskOut, sort: TaskSort, position: i64) -> Self {
+        match sort {
+            TaskSort::Position => SortKey::Position(position),
+            TaskSort::Created => SortKey::Created(task.created_at),
+            TaskSort::Due => SortKey::Due(task.core.due.unwrap_or_else(|| NO_DUE.parse().unwrap())),
+            TaskSort::Priority => SortKey::Priority(task.core.priority.to_i16()),
+            TaskSort::Name => SortKey::Name(task.core.name.clone()),
+        }
+    }
+
+    fn sort(&self) -> TaskSort {
+        match self {
+            SortKey::Position(_) => TaskSort::Position,
+            SortKey::Created(_) => TaskSort::Created,
+            SortKey::Due(_) => TaskSort::Due,
+            SortKey::Priority(_) => TaskSort::Priority,
+            SortKey::Name(_) => TaskSort::Name,
+        }
+    }
+}
+
+/// Last item of a page, the next one starts after it. Opaque hex string for clients.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Cursor {
+    pub key: SortKey,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Whether an item goes after the cursor, ties of keys are broken by ids.
+    fn is_before(&self, key: &SortKey, id: Uuid, order: Order) -> bool {
+        let ordering = (&self.key, self.id).cmp(&(key, id));
+        match order {
+            Order::Asc => ordering == Ordering::Less,
+            Order::Desc => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in serde_json::to_vec(self).map_err(|_| std::fmt::Error)? {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| s.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<_>>>();
+        bytes
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| "invalid cursor".to_owned())
+    }
+}
+
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Where the following page starts, `None` on the last one.
+    pub next: Option<Cursor>,
+}
+
+impl<T> Page<T> {
+    /// Cuts sorted `items` to `limit`, those past it only tell that there is a next page.
+    pub fn new(mut items: Vec<T>, limit: Option<usize>, cursor: impl Fn(&T) -> Cursor) -> Self {
+        let next = match limit {
+            Some(limit) if items.len() > limit => {
+                items.truncate(limit);
+                items.last().map(cursor)
+            }
+            _ => None,
+        };
+        Self { items, next }
+    }
+}
+
+/// Empty query parameters, as sent by HTML forms, mean "any".
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where D: Deserializer<'de>,
+          T: FromStr,
+          T::Err: Display {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.is_empty() => value.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+fn capped_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    Ok(empty_as_none::<D, usize>(deserializer)?.map(|limit| limit.min(MAX_LIMIT)))
+}
+
+fn cursor_to_str<S: Serializer>(cursor: &Option<Cursor>, serializer: S) -> Result<S::Ok, S::Error> {
+    match cursor {
+        Some(cursor) => serializer.collect_str(cursor),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Case insensitive substring search.
+fn contains(name: &str, search: &Option<String>) -> bool {
+    search.as_ref().is_none_or(|search| name.to_lowercase().contains(&search.to_lowercase()))
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TaskQuery {
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub done: Option<bool>,
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub due_before: Option<NaiveDate>,
+    /// Part of the name.
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub sort: TaskSort,
+    #[serde(default)]
+    pub order: Order,
+    #[serde(default, deserialize_with = "empty_as_none", serialize_with = "cursor_to_str",
+            skip_serializing_if = "Option::is_none")]
+    pub after: Option<Cursor>,
+    #[serde(default, deserialize_with = "capped_limit", skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl TaskQuery {
+    /// A cursor only makes sense for the sort it was made by.
+    pub fn check(&self) -> TaskListResult<()> {
+        match &self.after {
+            Some(after) if after.key.sort() != self.sort => Err(TaskListError::InvalidCursor),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether all tasks are shown in list order, so their indices are their positions.
+    pub fn is_list_order(&self) -> bool {
+        self.done.is_none() && self.tag.is_none() && self.due_before.is_none() && self.q.is_none()
+            && self.sort == TaskSort::Position && self.order == Order::Asc && self.after.is_none()
+    }
+
+    fn matches(&self, task: &TaskOut) -> bool {
+        self.done.is_none_or(|done| task.done == done)
+            && self.tag.as_ref().is_none_or(|tag| task.core.tags.contains(tag))
+            && self.due_before.is_none_or(|due_before| task.core.due.is_some_and(|due| due < due_before))
+            && contains(&task.core.name, &self.q)
+    }
+
+    /// Runs the query over all `tasks` of a list, kept in list order.
+    pub fn apply(&self, tasks: Vec<TaskOut>) -> TaskListResult<Page<TaskOut>> {
+        self.check()?;
+        let mut tasks = tasks.into_iter()
+            .enumerate()
+            .map(|(position, task)| (SortKey::of(&task, self.sort, position as i64), task))
+            .filter(|(key, task)| self.matches(task)
+                && self.after.as_ref().is_none_or(|after| after.is_before(key, task.id, self.order)))
+            .collect::<Vec<_>>();
+        tasks.sort_by(|(a_key, a), (b_key, b)| {
+            let ordering = (a_key, a.id).cmp(&(b_key, b.id));
+            match self.order {
+                Order::Asc => ordering,
+                Order::Desc => ordering.reverse(),
+            }
+        });
+        Ok(self.page(tasks))
+    }
+
+    /// Page of tasks already filtered and sorted along with their keys.
+    pub fn page(&self, tasks: Vec<(SortKey, TaskOut)>) -> Page<TaskOut> {
+        let page = Page::new(tasks, self.limit, |(key, task)| Cursor { key: key.clone(), id: task.id });
+        Page { items: page.items.into_iter().map(|(_, task)| task).collect(), next: page.next }
+    }
+}
+
+/// Lists are always sorted by name.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TaskListQuery {
+    /// Part of the name.
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none", serialize_with = "cursor_to_str",
+            skip_serializing_if = "Option::is_none")]
+    pub after: Option<Cursor>,
+    #[serde(default, deserialize_with = "capped_limit", skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl TaskListQuery {
+    /// Name the page starts after.
+    pub fn check(&self) -> TaskListResult<Option<(&str, Uuid)>> {
+        match &self.after {
+            Some(Cursor { key: SortKey::Name(name), id }) => Ok(Some((name, *id))),
+            Some(_) => Err(TaskListError::InvalidCursor),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs the query over all `task_lists`.
+    pub fn apply(&self, task_lists: Vec<TaskListOut>) -> TaskListResult<Page<TaskListOut>> {
+        let after = self.check()?;
+        let mut task_lists = task_lists.into_iter()
+            .filter(|task_list| contains(&task_list.core.name, &self.q)
+                && after.is_none_or(|after| after < (task_list.core.name.as_str(), task_list.id)))
+            .collect::<Vec<_>>();
+        sort_by_name(&mut task_lists);
+        Ok(self.page(task_lists))
+    }
+
+    /// Page of lists already filtered and sorted by name.
+    pub fn page(&self, task_lists: Vec<TaskListOut>) -> Page<TaskListOut> {
+        Page::new(task_lists, self.limit, |task_list| Cursor {
+            key: SortKey::Name(task_list.core.name.clone()),
+            id: task_list.id,
+        })
+    }
+}
+
+/// Order of lists, the same in every backend.
+pub fn sort_by_name(task_lists: &mut [TaskListOut]) {
+    task_lists.sort_by(|a, b| (&a.core.name, a.id).cmp(&(&b.core.name, b.id)));
+}
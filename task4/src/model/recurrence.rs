@@ -0,0 +1,313 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+
+use super::task::TaskIn;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+
+    fn has_months(&self) -> bool {
+        matches!(self, Frequency::Monthly | Frequency::Yearly)
+    }
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Mon), ("TU", Weekday::Tue), ("WE", Weekday::Wed), ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri), ("SA", Weekday::Sat), ("SU", Weekday::Sun),
+];
+
+/// Rule of a recurring task, the part of RFC 5545 `RRULE` which is about days:
+/// `FREQ`, `INTERVAL`, `BYDAY` for weekly and `BYMONTHDAY` for monthly and yearly rules, `COUNT` and `UNTIL`.
+/// Written like `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Every how many days, weeks, months or years.
+    pub interval: u32,
+    /// Days of the week, sorted from Monday. None means the weekday of the due date.
+    pub by_day: Vec<Weekday>,
+    /// Negative days count from the end of the month, days past it are its last one.
+    /// Yearly rules keep the month of the due date. None means the day of the due date.
+    pub by_month_day: Option<i32>,
+    /// Occurrences left including the current one.
+    pub count: Option<u32>,
+    /// Last day an occurrence may be due.
+    pub until: Option<NaiveDate>,
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let value = value.strip_prefix("RRULE:").unwrap_or(value);
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: vec![],
+            by_month_day: None,
+            count: None,
+            until: None,
+        };
+        for part in value.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("`{part}` is not like KEY=VALUE"))?;
+            let value = value.to_ascii_uppercase();
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => frequency = Some(match value.as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(format!("FREQ `{value}` is not DAILY, WEEKLY, MONTHLY or YEARLY")),
+                }),
+                "INTERVAL" => recurrence.interval = value.parse().ok().filter(|&x| x > 0)
+                    .ok_or_else(|| format!("INTERVAL `{value}` is not a positive number"))?,
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let weekday = WEEKDAYS.iter().find(|(name, _)| *name == day)
+                            .ok_or_else(|| format!("BYDAY `{day}` is not one of MO, TU, WE, TH, FR, SA, SU"))?.1;
+                        recurrence.by_day.push(weekday);
+                    }
+                    recurrence.by_day.sort_by_key(Weekday::num_days_from_monday);
+                    recurrence.by_day.dedup();
+                }
+                "BYMONTHDAY" => recurrence.by_month_day = Some(value.parse().ok().filter(|x: &i32| (1..=31).contains(&x.abs()))
+                    .ok_or_else(|| format!("BYMONTHDAY `{value}` is not a day from 1 to 31 or -31 to -1"))?),
+                "COUNT" => recurrence.count = Some(value.parse().ok().filter(|&x| x > 0)
+                    .ok_or_else(|| format!("COUNT `{value}` is not a positive number"))?),
+                // Times of `UNTIL` are ignored, as tasks are due on days.
+                "UNTIL" => recurrence.until = Some(value.get(..8)
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+                    .ok_or_else(|| format!("UNTIL `{value}` is not a date like 20241231"))?),
+                key => return Err(format!("`{key}` is not supported")),
+            }
+        }
+        recurrence.frequency = frequency.ok_or("FREQ is missing")?;
+        if !recurrence.by_day.is_empty() && recurrence.frequency != Frequency::Weekly {
+            return Err("BYDAY is only supported with FREQ=WEEKLY".to_owned());
+        }
+        if recurrence.by_month_day.is_some() && !recurrence.frequency.has_months() {
+            return Err("BYMONTHDAY is only supported with FREQ=MONTHLY or FREQ=YEARLY".to_owned());
+        }
+        Ok(recurrence)
+    }
+}
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FREQ={}", self.frequency.as_str())?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days = self.by_day.iter()
+                .map(|&day| WEEKDAYS.iter().find(|(_, weekday)| *weekday == day).unwrap().0)
+                .collect::<Vec<_>>();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(day) = self.by_month_day {
+            write!(f, ";BYMONTHDAY={day}")?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%d"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Recurrence {
+    /// Months between occurrences of monthly and yearly rules.
+    fn months(&self) -> Option<u32> {
+        match self.frequency {
+            Frequency::Yearly => self.interval.checked_mul(12),
+            _ => Some(self.interval),
+        }
+    }
+
+    /// Due date of the occurrence after the one due at `date`, `None` past the last date chrono knows.
+    fn following(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self.frequency {
+            Frequency::Daily => date.checked_add_signed(Duration::days(self.interval.into())),
+            Frequency::Weekly => {
+                let later_this_week = self.by_day.iter()
+                    .find(|day| day.num_days_from_monday() > date.weekday().num_days_from_monday());
+                let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+                match (later_this_week, self.by_day.first()) {
+                    (Some(day), _) => Some(monday + Duration::days(day.num_days_from_monday().into())),
+                    (None, Some(first)) => monday
+                        .checked_add_signed(Duration::weeks(self.interval.into()) + Duration::days(first.num_days_from_monday().into())),
+                    (None, None) => date.checked_add_signed(Duration::weeks(self.interval.into())),
+                }
+            }
+            Frequency::Monthly | Frequency::Yearly => {
+                let day = self.by_month_day.unwrap_or(date.day() as i32);
+                let this_month = day_of_month(date, day).filter(|&x| x > date);
+                this_month.or_else(|| day_of_month(date.checked_add_months(Months::new(self.months()?))?, day))
+            }
+        }
+    }
+
+    /// `date` moved by whole intervals to less than an interval before `today`, so that catching up
+    /// on missed occurrences takes a few steps however long ago `date` was.
+    fn skip_missed(&self, date: NaiveDate, today: NaiveDate) -> Option<NaiveDate> {
+        let interval = i64::from(self.interval);
+        let (periods, length) = match self.frequency {
+            Frequency::Daily => ((today - date).num_days(), interval),
+            Frequency::Weekly => ((today - date).num_weeks(), interval),
+            Frequency::Monthly | Frequency::Yearly => {
+                let months = i64::from(today.year() - date.year()) * 12 + i64::from(today.month()) - i64::from(date.month());
+                (months, i64::from(self.months()?))
+            }
+        };
+        let skipped = (periods / length - 1).max(0) * length;
+        match self.frequency {
+            Frequency::Daily => date.checked_add_signed(Duration::days(skipped)),
+            Frequency::Weekly => date.checked_add_signed(Duration::weeks(skipped)),
+            Frequency::Monthly | Frequency::Yearly => date.checked_add_months(Months::new(skipped.try_into().ok()?)),
+        }
+    }
+}
+
+/// `day` of the month of `date`, see `Recurrence::by_month_day`.
+fn day_of_month(date: NaiveDate, day: i32) -> Option<NaiveDate> {
+    let first = date.with_day(1)?;
+    let last = (first.checked_add_months(Months::new(1))? - Duration::days(1)).day() as i32;
+    let day = if day < 0 { (last + 1 + day).max(1) } else { day.min(last) };
+    first.with_day(day as u32)
+}
+
+/// Date in the time zone, recurring tasks without a due date are next due a period after it.
+pub fn today(time_zone: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&time_zone).date_naive()
+}
+
+/// The task which follows `task` once it is done, `None` unless it recurs and its rule has occurrences left.
+/// It is due at the first occurrence after the due date of `task` which is not before `today`,
+/// so missed ones are skipped, and carries the rest of the rule.
+/// Monthly and yearly rules are pinned to the day they were first due, so that short months do not move it.
+pub fn next_task(task: &TaskIn, today: NaiveDate) -> Option<TaskIn> {
+    let mut recurrence: Recurrence = task.recurrence.as_deref()?.parse().ok()?;
+    if recurrence.count == Some(1) {
+        return None;
+    }
+    let start = task.due.unwrap_or(today);
+    if recurrence.frequency.has_months() {
+        recurrence.by_month_day.get_or_insert(start.day() as i32);
+    }
+    let mut due = recurrence.following(recurrence.skip_missed(start, today)?)?;
+    while due < today {
+        due = recurrence.following(due)?;
+    }
+    if recurrence.until.is_some_and(|until| due > until) {
+        return None;
+    }
+    recurrence.count = recurrence.count.map(|count| count - 1);
+    Some(TaskIn { due: Some(due), recurrence: Some(recurrence.to_string()), ..task.clone() })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Due dates of the tasks which follow one due at `due`, done on that day.
+    fn dues(rule: &str, due: NaiveDate, n: usize) -> Vec<NaiveDate> {
+        let mut task = TaskIn { name: "chore".to_owned(), due: Some(due), recurrence: Some(rule.to_owned()), ..Default::default() };
+        let mut dues = vec![];
+        for _ in 0..n {
+            let Some(next) = next_task(&task, task.due.unwrap()) else {
+                break;
+            };
+            dues.push(next.due.unwrap());
+            task = next;
+        }
+        dues
+    }
+
+    #[test]
+    fn test_parse() {
+        let recurrence: Recurrence = "RRULE:freq=weekly;byday=th,mo,MO;interval=2;until=20241231T235959Z".parse().unwrap();
+        assert_eq!(recurrence.by_day, [Weekday::Mon, Weekday::Thu]);
+        assert_eq!(recurrence.to_string(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;UNTIL=20241231");
+        assert_eq!("FREQ=MONTHLY;BYMONTHDAY=-1;COUNT=3".parse::<Recurrence>().unwrap().to_string(),
+                   "FREQ=MONTHLY;BYMONTHDAY=-1;COUNT=3");
+
+        let error = |rule: &str| rule.parse::<Recurrence>().unwrap_err();
+        assert_eq!(error("INTERVAL=2"), "FREQ is missing");
+        assert_eq!(error("FREQ=HOURLY"), "FREQ `HOURLY` is not DAILY, WEEKLY, MONTHLY or YEARLY");
+        assert_eq!(error("FREQ=DAILY;BYDAY=MO"), "BYDAY is only supported with FREQ=WEEKLY");
+        assert_eq!(error("FREQ=WEEKLY;BYMONTHDAY=1"), "BYMONTHDAY is only supported with FREQ=MONTHLY or FREQ=YEARLY");
+        assert_eq!(error("FREQ=MONTHLY;BYMONTHDAY=32"), "BYMONTHDAY `32` is not a day from 1 to 31 or -31 to -1");
+        assert_eq!(error("FREQ=DAILY;BYHOUR=9"), "`BYHOUR` is not supported");
+        assert_eq!(error("FREQ=DAILY;COUNT"), "`COUNT` is not like KEY=VALUE");
+    }
+
+    #[test]
+    fn test_next_task() {
+        assert_eq!(dues("FREQ=DAILY;INTERVAL=3", date(2024, 2, 27), 2), [date(2024, 3, 1), date(2024, 3, 4)]);
+        // 2024-01-03 is a Wednesday.
+        assert_eq!(dues("FREQ=WEEKLY", date(2024, 1, 3), 2), [date(2024, 1, 10), date(2024, 1, 17)]);
+        assert_eq!(dues("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH", date(2024, 1, 3), 3),
+                   [date(2024, 1, 4), date(2024, 1, 15), date(2024, 1, 18)]);
+        assert_eq!(dues("FREQ=MONTHLY;BYMONTHDAY=-1", date(2024, 1, 15), 3),
+                   [date(2024, 1, 31), date(2024, 2, 29), date(2024, 3, 31)]);
+        assert_eq!(dues("FREQ=MONTHLY;BYMONTHDAY=31", date(2024, 1, 31), 2), [date(2024, 2, 29), date(2024, 3, 31)]);
+        assert_eq!(dues("FREQ=MONTHLY", date(2024, 1, 31), 4), [date(2024, 2, 29), date(2024, 3, 31), date(2024, 4, 30), date(2024, 5, 31)]);
+        assert_eq!(dues("FREQ=YEARLY", date(2024, 2, 29), 4), [date(2025, 2, 28), date(2026, 2, 28), date(2027, 2, 28), date(2028, 2, 29)]);
+        assert_eq!(dues("FREQ=YEARLY;INTERVAL=2;BYMONTHDAY=-1", date(2023, 2, 10), 2), [date(2023, 2, 28), date(2025, 2, 28)]);
+        assert_eq!(dues("FREQ=DAILY;COUNT=3", date(2024, 1, 1), 5), [date(2024, 1, 2), date(2024, 1, 3)]);
+        assert_eq!(dues("FREQ=WEEKLY;UNTIL=20240115", date(2024, 1, 1), 5), [date(2024, 1, 8), date(2024, 1, 15)]);
+
+        let task = TaskIn { due: Some(date(2024, 1, 1)), recurrence: Some("FREQ=WEEKLY;COUNT=5".to_owned()), ..Default::default() };
+        // Occurrences which were missed are skipped.
+        let next = next_task(&task, date(2024, 1, 20)).unwrap();
+        assert_eq!(next.due, Some(date(2024, 1, 22)));
+        assert_eq!(next.recurrence.as_deref(), Some("FREQ=WEEKLY;COUNT=4"));
+
+        // Catching up on a date long ago does not step through every occurrence.
+        let weeks_ago = date(2024, 1, 20) - Duration::weeks(10_000_000);
+        for (rule, start, due) in [
+            ("FREQ=DAILY;INTERVAL=7", weeks_ago, date(2024, 1, 20)),
+            ("FREQ=WEEKLY;BYDAY=MO,TH", weeks_ago, date(2024, 1, 22)),
+            ("FREQ=MONTHLY;BYMONTHDAY=-1", date(-200_000, 1, 1), date(2024, 1, 31)),
+            ("FREQ=YEARLY;INTERVAL=3", date(-200_000, 1, 1), date(2026, 1, 1)),
+        ] {
+            let task = TaskIn { due: Some(start), recurrence: Some(rule.to_owned()), ..Default::default() };
+            assert_eq!(next_task(&task, date(2024, 1, 20)).unwrap().due, Some(due), "{rule}");
+        }
+        let task = TaskIn { due: None, ..task };
+        assert_eq!(next_task(&task, date(2024, 1, 20)).unwrap().due, Some(date(2024, 1, 27)));
+        assert!(next_task(&TaskIn { recurrence: None, ..task }, date(2024, 1, 20)).is_none());
+
+        let task = TaskIn { due: Some(date(2024, 1, 31)), recurrence: Some("FREQ=MONTHLY".to_owned()), ..Default::default() };
+        let next = next_task(&task, date(2024, 2, 1)).unwrap();
+        assert_eq!(next.recurrence.as_deref(), Some("FREQ=MONTHLY;BYMONTHDAY=31"));
+        assert_eq!(next_task(&next, date(2024, 3, 1)).unwrap().due, Some(date(2024, 3, 31)));
+    }
+}
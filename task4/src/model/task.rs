@@ -1,15 +1,91 @@
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 pub type Id = uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    /// Number kept in databases, ordered like the priorities.
+    pub fn to_i16(self) -> i16 {
+        self as i16
+    }
+
+    /// Unknown numbers are treated as the closest priority.
+    pub fn from_i16(value: i16) -> Self {
+        match value {
+            i16::MIN..=0 => Priority::Low,
+            1 => Priority::Normal,
+            _ => Priority::High,
+        }
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct TaskIn {
     pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `Recurrence` as written in `RRULE`, the next occurrence is added once the task is done.
+    #[serde(default)]
+    pub recurrence: Option<String>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TaskOut {
     pub id: Id,
+    #[serde(flatten)]
     pub core: TaskIn,
     pub done: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl TaskOut {
+    pub fn new(id: Id, core: TaskIn, now: DateTime<Utc>) -> Self {
+        Self { id, core, done: false, created_at: now, updated_at: now, completed_at: None }
+    }
+
+    pub fn set_done(&mut self, done: bool, now: DateTime<Utc>) {
+        self.done = done;
+        self.completed_at = done.then_some(now);
+        self.updated_at = now;
+    }
+
+    /// Not done while its due date has passed.
+    pub fn is_overdue(&self, today: &NaiveDate) -> bool {
+        !self.done && self.core.due.is_some_and(|due| due < *today)
+    }
 }
@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use super::task::TaskOut;
+use super::user;
 
 pub type Id = uuid::Uuid;
 
@@ -8,15 +9,22 @@ pub struct TaskListIn {
     pub name: String,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TaskListOut {
     pub id: Id,
+    /// Lists saved before there were users belong to nobody.
+    #[serde(default)]
+    pub owner: user::Id,
+    #[serde(flatten)]
     pub core: TaskListIn,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TaskListWithTasks {
     pub id: Id,
+    #[serde(default)]
+    pub owner: user::Id,
+    #[serde(flatten)]
     pub core: TaskListIn,
     pub tasks: Vec<TaskOut>,
 }
@@ -0,0 +1,36 @@
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+pub type Id = uuid::Uuid;
+
+/// Login form and registration request.
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct User {
+    pub id: Id,
+    pub username: String,
+}
+
+/// User as kept by the DAOs, with an Argon2 PHC string instead of the password.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserRecord {
+    #[serde(flatten)]
+    pub user: User,
+    pub password_hash: String,
+    /// Missing for users saved before there were settings.
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+/// Preferences of a user, applied by the DAOs to what the user does.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct Settings {
+    /// Recurring tasks are due on days of this time zone.
+    #[serde(default)]
+    pub time_zone: Tz,
+}
@@ -0,0 +1,199 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{TaskListError, TaskListResult};
+
+use super::recurrence::Recurrence;
+use super::task::TaskIn;
+use super::task_list::TaskListIn;
+use super::user::Credentials;
+
+pub const MAX_NAME_LEN: usize = 200;
+pub const MAX_USERNAME_LEN: usize = 50;
+pub const MAX_DESCRIPTION_LEN: usize = 10_000;
+pub const MAX_TAG_LEN: usize = 50;
+pub const MAX_TAGS: usize = 20;
+/// Tasks a single list may hold, checked by the DAOs when a task is added.
+pub const MAX_TASKS: usize = 1000;
+
+/// Why a field of the input is rejected.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found in an input, at most one per field.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(transparent)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        if self.get(field).is_none() {
+            self.0.push(FieldError { field: field.to_owned(), message: message.into() });
+        }
+    }
+
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.0.iter().find(|error| error.field == field).map(|error| error.message.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `value` if nothing was found, otherwise the errors as `TaskListError::Invalid`.
+    fn or<T>(self, value: T) -> TaskListResult<T> {
+        if self.is_empty() { Ok(value) } else { Err(TaskListError::Invalid(self)) }
+    }
+}
+
+impl Display for FieldErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Input which is checked before it is passed to the DAOs.
+pub trait Validate: Sized {
+    /// The input with names trimmed, or `Invalid` with every problem found.
+    fn validate(self) -> TaskListResult<Self>;
+}
+
+/// Trims `value`, which has to be a non-empty line of at most `max_len` characters.
+fn check_line(errors: &mut FieldErrors, field: &str, value: String, max_len: usize) -> String {
+    let value = value.trim();
+    if value.is_empty() {
+        errors.add(field, "must not be empty");
+    } else if value.chars().count() > max_len {
+        errors.add(field, format!("must be at most {max_len} characters"));
+    } else if value.chars().any(char::is_control) {
+        errors.add(field, "must not contain control characters");
+    }
+    value.to_owned()
+}
+
+impl Validate for TaskListIn {
+    fn validate(self) -> TaskListResult<Self> {
+        let mut errors = FieldErrors::default();
+        let name = check_line(&mut errors, "name", self.name, MAX_NAME_LEN);
+        errors.or(TaskListIn { name })
+    }
+}
+
+impl Validate for TaskIn {
+    fn validate(self) -> TaskListResult<Self> {
+        let mut errors = FieldErrors::default();
+        let name = check_line(&mut errors, "name", self.name, MAX_NAME_LEN);
+        // Descriptions span several lines, so only line breaks and tabs are allowed among control characters.
+        if let Some(description) = &self.description {
+            if description.chars().count() > MAX_DESCRIPTION_LEN {
+                errors.add("description", format!("must be at most {MAX_DESCRIPTION_LEN} characters"));
+            } else if description.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) {
+                errors.add("description", "must not contain control characters");
+            }
+        }
+        if self.tags.len() > MAX_TAGS {
+            errors.add("tags", format!("must be at most {MAX_TAGS}"));
+        }
+        let mut tag_errors = FieldErrors::default();
+        let tags = self.tags.into_iter()
+            .map(|tag| check_line(&mut tag_errors, "tags", tag, MAX_TAG_LEN))
+            .collect();
+        if let Some(message) = tag_errors.get("tags") {
+            errors.add("tags", format!("each {message}"));
+        }
+        // Kept as written by `Recurrence`, so equal rules are stored alike.
+        let recurrence = self.recurrence.and_then(|rule| match rule.parse::<Recurrence>() {
+            Ok(recurrence) => Some(recurrence.to_string()),
+            Err(message) => {
+                errors.add("recurrence", message);
+                None
+            }
+        });
+        errors.or(TaskIn { name, tags, recurrence, ..self })
+    }
+}
+
+impl Validate for Credentials {
+    /// Passwords are kept as typed, only the username is trimmed.
+    fn validate(self) -> TaskListResult<Self> {
+        let mut errors = FieldErrors::default();
+        let username = check_line(&mut errors, "username", self.username, MAX_USERNAME_LEN);
+        if self.password.is_empty() {
+            errors.add("password", "must not be empty");
+        }
+        errors.or(Credentials { username, ..self })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid<T>(res: TaskListResult<T>) -> FieldErrors {
+        match res {
+            Err(TaskListError::Invalid(errors)) => errors,
+            _ => panic!("expected invalid input"),
+        }
+    }
+
+    #[test]
+    fn test_task_list() {
+        assert_eq!(TaskListIn { name: "  groceries ".to_owned() }.validate().unwrap().name, "groceries");
+        let errors = invalid(TaskListIn { name: " \t ".to_owned() }.validate());
+        assert_eq!(errors.get("name"), Some("must not be empty"));
+        let errors = invalid(TaskListIn { name: "x".repeat(MAX_NAME_LEN + 1) }.validate());
+        assert_eq!(errors.get("name"), Some("must be at most 200 characters"));
+        let errors = invalid(TaskListIn { name: "a\u{7}b".to_owned() }.validate());
+        assert_eq!(errors.get("name"), Some("must not contain control characters"));
+    }
+
+    #[test]
+    fn test_task() {
+        let task = TaskIn {
+            name: " milk ".to_owned(),
+            description: Some("two\nbottles".to_owned()),
+            tags: vec![" shop ".to_owned()],
+            ..TaskIn::default()
+        }.validate().unwrap();
+        assert_eq!(task.name, "milk");
+        assert_eq!(task.tags, vec!["shop"]);
+
+        let errors = invalid(TaskIn {
+            name: String::new(),
+            description: Some("\u{0}".to_owned()),
+            tags: vec!["ok".to_owned(), "".to_owned()],
+            ..TaskIn::default()
+        }.validate());
+        assert_eq!(errors.get("name"), Some("must not be empty"));
+        assert_eq!(errors.get("description"), Some("must not contain control characters"));
+        assert_eq!(errors.get("tags"), Some("each must not be empty"));
+        assert_eq!(errors.to_string(),
+                   "name must not be empty, description must not contain control characters, tags each must not be empty");
+
+        let errors = invalid(TaskIn { name: "x".to_owned(), tags: vec!["t".to_owned(); MAX_TAGS + 1], ..TaskIn::default() }
+            .validate());
+        assert_eq!(errors.get("tags"), Some("must be at most 20"));
+    }
+
+    #[test]
+    fn test_credentials() {
+        let credentials = Credentials { username: " alice ".to_owned(), password: " secret ".to_owned() }.validate().unwrap();
+        assert_eq!(credentials.username, "alice");
+        assert_eq!(credentials.password, " secret ");
+
+        let errors = invalid(Credentials { username: "a\nb".to_owned(), password: String::new() }.validate());
+        assert_eq!(errors.get("username"), Some("must not contain control characters"));
+        assert_eq!(errors.get("password"), Some("must not be empty"));
+    }
+}
@@ -1,9 +1,63 @@
-use actix_web::Responder;
+use std::convert::Infallible;
+use std::fmt::Display;
+
+use actix_session::{Session, SessionExt};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder, ResponseError};
+use actix_web::dev::Payload as RequestPayload;
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
 use askama_actix::{Template, TemplateToResponse};
-use crate::error::TaskListResult;
+use chrono::NaiveDate;
+use futures::future::{ready, Ready};
+use serde::Serialize;
+
+use crate::{auth, csrf, flash};
+use crate::auth::Unauthorized;
+use crate::error::{TaskListError, TaskListResult};
+use crate::flash::FormErrors;
 
+use crate::model::member::{Invitation, Member, Role};
+use crate::model::query::{Cursor, Order, Page, TaskListQuery, TaskQuery, TaskSort};
+use crate::model::recurrence::today;
+use crate::model::task::Priority;
 use crate::model::task_list;
 use crate::model::task_list::TaskListWithTasks;
+use crate::model::user::{self, Settings};
+
+/// Parts of every page which come from the session.
+pub struct PageContext {
+    /// Sent back by every form, see `csrf::verify`.
+    pub csrf_token: String,
+    /// Left by the previous request, usually why a form submit failed.
+    pub flash: Option<String>,
+    pub form_errors: Option<FormErrors>,
+    /// Of the logged in user, pages showing their data return the error.
+    pub user_id: Result<user::Id, Unauthorized>,
+}
+
+impl PageContext {
+    /// Why `field` was rejected, if the form named `form` was just submitted.
+    pub fn error(&self, form: &(impl Display + ?Sized), field: &str) -> Option<&str> {
+        self.form_errors.as_ref()
+            .filter(|form_errors| form_errors.form == form.to_string())
+            .and_then(|form_errors| form_errors.errors.get(field))
+    }
+}
+
+impl FromRequest for PageContext {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut RequestPayload) -> Self::Future {
+        let session = req.get_session();
+        ready(Ok(PageContext {
+            csrf_token: csrf::token(&session),
+            flash: flash::take(&session),
+            form_errors: flash::take_errors(&session),
+            user_id: auth::current_user_id(req),
+        }))
+    }
+}
 
 #[derive(Template)]
 #[template(path = "error.html")]
@@ -11,37 +65,162 @@ struct ErrorTemplate<'a> {
     text: &'a str,
 }
 
+/// Login or registration form.
+#[derive(Template)]
+#[template(path = "auth.html")]
+struct AuthTemplate<'a> {
+    ctx: &'a PageContext,
+    title: &'a str,
+    action: &'a str,
+}
+
 #[derive(Template)]
 #[template(path = "lists.html")]
 struct ListsTemplate<'a> {
+    ctx: &'a PageContext,
     data: &'a Vec<task_list::TaskListOut>,
+    query: &'a TaskListQuery,
+    /// Query string of the next page.
+    next: Option<String>,
+    settings: &'a Settings,
 }
 
 #[derive(Template)]
 #[template(path = "list.html")]
 struct ListTemplate<'a> {
+    ctx: &'a PageContext,
+    data: &'a TaskListWithTasks,
+    /// Tasks due before it are overdue, in the time zone of the user.
+    today: NaiveDate,
+    priorities: &'a [Priority],
+    query: &'a TaskQuery,
+    /// `query.done` as a form value.
+    done: &'a str,
+    sorts: &'a [TaskSort],
+    orders: &'a [Order],
+    /// Tasks can be moved only while they are shown at their positions.
+    movable: bool,
+    /// Viewers get no forms which change the list.
+    editable: bool,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "members.html")]
+struct MembersTemplate<'a> {
+    ctx: &'a PageContext,
     data: &'a TaskListWithTasks,
+    members: &'a Vec<Member>,
+    /// Only the owner invites and revokes others.
+    is_owner: bool,
+    roles: &'a [Role],
+}
+
+#[derive(Template)]
+#[template(path = "invitations.html")]
+struct InvitationsTemplate<'a> {
+    ctx: &'a PageContext,
+    data: &'a Vec<Invitation>,
+}
+
+
+pub fn view_login(ctx: &PageContext) -> HttpResponse {
+    AuthTemplate { ctx, title: "Log in", action: "/login" }.to_response()
 }
 
+pub fn view_register(ctx: &PageContext) -> HttpResponse {
+    AuthTemplate { ctx, title: "Register", action: "/register" }.to_response()
+}
 
-pub fn view_get_todo_lists(data: TaskListResult<Vec<task_list::TaskListOut>>) -> impl Responder {
+/// Error page with the status of the error.
+pub fn view_error(status: StatusCode, text: &str) -> HttpResponse {
+    let mut res = ErrorTemplate { text }.to_response();
+    *res.status_mut() = status;
+    res
+}
+
+pub fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+/// Answers a form submit with the page to show next, so reloading it does not submit the form again.
+/// A failure is shown there as a flash message.
+pub fn view_submitted(res: TaskListResult<()>, session: &Session, location: &str) -> HttpResponse {
+    if let Err(err) = res {
+        err.log_internal();
+        flash::push(session, err.to_string());
+    }
+    redirect(location)
+}
+
+/// Like `view_submitted`, invalid fields are also kept for the form named `form` to show them.
+pub fn view_form_submitted(res: TaskListResult<()>, session: &Session, location: &str, form: &str) -> HttpResponse {
+    if let Err(TaskListError::Invalid(errors)) = &res {
+        flash::push_errors(session, form, errors);
+    }
+    view_submitted(res, session, location)
+}
+
+pub fn view_find_todo_lists(data: TaskListResult<(Page<task_list::TaskListOut>, Settings)>, query: &TaskListQuery,
+                            ctx: &PageContext) -> impl Responder {
     match data {
-        Ok(data) => {
-            ListsTemplate { data: &data }.to_response()
+        Ok((page, settings)) => {
+            let next = page.next.map(|after| to_query_string(TaskListQuery { after: Some(after), ..query.clone() }));
+            ListsTemplate { ctx, data: &page.items, query, next, settings: &settings }.to_response()
+        }
+        Err(err) => err.error_response()
+    }
+}
+
+pub fn view_find_todo_list(data: TaskListResult<(TaskListWithTasks, Role, Option<Cursor>, Settings)>,
+                           query: &TaskQuery,
+                           ctx: &PageContext) -> impl Responder {
+    match data {
+        Ok((data, role, next, settings)) => {
+            let done = match query.done {
+                None => "",
+                Some(true) => "true",
+                Some(false) => "false",
+            };
+            ListTemplate {
+                ctx,
+                data: &data,
+                today: today(settings.time_zone),
+                priorities: &Priority::ALL,
+                query,
+                done,
+                sorts: &TaskSort::ALL,
+                orders: &Order::ALL,
+                movable: query.is_list_order(),
+                editable: role >= Role::Editor,
+                next: next.map(|after| to_query_string(TaskQuery { after: Some(after), ..query.clone() })),
+            }.to_response()
         }
-        Err(err) => {
-            ErrorTemplate { text: &*format!("{err:?}") }.to_response()
+        Err(err) => err.error_response()
+    }
+}
+
+pub fn view_members(data: TaskListResult<(TaskListWithTasks, Role, Vec<Member>)>, ctx: &PageContext) -> impl Responder {
+    match data {
+        Ok((data, role, members)) => {
+            MembersTemplate { ctx, data: &data, members: &members, is_owner: role == Role::Owner, roles: &Role::SHARED }
+                .to_response()
         }
+        Err(err) => err.error_response()
     }
 }
 
-pub fn view_get_todo_list(data: TaskListResult<TaskListWithTasks>) -> impl Responder {
+pub fn view_invitations(data: TaskListResult<Vec<Invitation>>, ctx: &PageContext) -> impl Responder {
     match data {
         Ok(data) => {
-            ListTemplate { data: &data }.to_response()
-        }
-        Err(err) => {
-            ErrorTemplate { text: &*format!("{err:?}") }.to_response()
+            InvitationsTemplate { ctx, data: &data }.to_response()
         }
+        Err(err) => err.error_response()
     }
-}
\ No newline at end of file
+}
+
+fn to_query_string(query: impl Serialize) -> String {
+    serde_urlencoded::to_string(query).unwrap()
+}